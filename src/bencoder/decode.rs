@@ -59,8 +59,15 @@ impl Decoder {
         self.len <= self.pos
     }
 
+    fn byte_at(&self, pos: usize) -> Result<u8, Box<dyn error::Error>> {
+        self.to_decode
+            .get(pos)
+            .copied()
+            .ok_or_else(|| Box::new(fmt::Error) as Box<dyn error::Error>)
+    }
+
     fn decode_next(&mut self) -> Result<Types, Box<dyn error::Error>> {
-        match self.to_decode[self.pos] {
+        match self.byte_at(self.pos)? {
             START_INTEGER => self.decode_integer(),
             START_LIST => self.decode_list(),
             START_DICT => self.decode_dictionary(),
@@ -73,7 +80,7 @@ impl Decoder {
         self.pos += 1;
         let slice_start = self.pos;
 
-        while self.to_decode[self.pos] != END {
+        while self.byte_at(self.pos)? != END {
             self.pos += 1;
         }
 
@@ -87,7 +94,7 @@ impl Decoder {
     fn decode_string(&mut self) -> Result<Types, Box<dyn error::Error>> {
         let mut len = 0;
 
-        while self.to_decode[self.pos + len] != START_STRING {
+        while self.byte_at(self.pos + len)? != START_STRING {
             len += 1;
         }
 
@@ -96,7 +103,11 @@ impl Decoder {
 
         self.pos += len + 1; // Because we skip the len and the ":"
 
-        let s = self.to_decode[self.pos..self.pos + len_string].to_vec();
+        let s = self
+            .to_decode
+            .get(self.pos..self.pos.checked_add(len_string).ok_or(fmt::Error)?)
+            .ok_or(fmt::Error)?
+            .to_vec();
 
         self.pos += len_string;
 
@@ -107,7 +118,7 @@ impl Decoder {
         let mut list = LinkedList::new();
         self.pos += 1;
 
-        while self.to_decode[self.pos] != END {
+        while self.byte_at(self.pos)? != END {
             let parsed_item = self.decode_next()?;
             list.push_back(parsed_item);
         }
@@ -120,7 +131,7 @@ impl Decoder {
         let mut dict = HashMap::new();
         self.pos += 1;
 
-        while self.to_decode[self.pos] != END {
+        while self.byte_at(self.pos)? != END {
             let key = match self.decode_next()? {
                 Types::String(key) => key,
                 _ => return Err(Box::new(fmt::Error)),
@@ -235,4 +246,19 @@ mod tests {
         let mut bencoder = Decoder::new_from_string(bencoded_message);
         assert!(bencoder.decode().is_err());
     }
+
+    #[test]
+    fn test6_doesnt_panic_on_truncated_input() {
+        for bencoded_message in [
+            "",
+            "i12",
+            "5:ab",
+            "d1:a",
+            "l4:spam",
+            "99999999999999999999:a",
+        ] {
+            let mut bencoder = Decoder::new_from_string(bencoded_message.to_string());
+            assert!(bencoder.decode().is_err());
+        }
+    }
 }
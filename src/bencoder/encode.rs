@@ -1,5 +1,5 @@
 use super::common::*;
-use std::{collections::HashMap, collections::LinkedList, error, str::from_utf8};
+use std::{collections::HashMap, collections::LinkedList, error};
 
 pub struct Encoder {
     to_encode: Types,
@@ -28,10 +28,11 @@ impl Encoder {
         Ok(format!("i{}e", int).as_bytes().to_vec())
     }
 
+    // Strings are raw bytes, they do not need to be valid UTF-8.
     fn encode_string(&self, str: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
-        Ok(format!("{}:{}", str.len(), from_utf8(str)?)
-            .as_bytes()
-            .to_vec())
+        let mut encoded_string = format!("{}:", str.len()).into_bytes();
+        encoded_string.extend_from_slice(str);
+        Ok(encoded_string)
     }
 
     fn encode_list(&self, list: &LinkedList<Types>) -> Result<Vec<u8>, Box<dyn error::Error>> {
@@ -48,7 +49,11 @@ impl Encoder {
         dict: &HashMap<Vec<u8>, Types>,
     ) -> Result<Vec<u8>, Box<dyn error::Error>> {
         let mut encoded_dict: Vec<u8> = vec![b'd'];
-        for (key, val) in dict {
+        let mut entries: Vec<(&Vec<u8>, &Types)> = dict.iter().collect();
+        // Keys must appear in lexicographical order of their raw bytes.
+        entries.sort_by_key(|(key, _)| *key);
+
+        for (key, val) in entries {
             encoded_dict.extend_from_slice(&self.encode_string(key)?);
             encoded_dict.extend_from_slice(&self.match_encode(val)?);
         }
@@ -110,4 +115,18 @@ mod tests {
             .encode()
             .expect("Error in test-4: Unable to encode the dictionary.");
     }
+
+    #[test]
+    fn test5_encode_binary_strings_and_sorted_keys() {
+        let mut dict = HashMap::new();
+        dict.insert(b"z".to_vec(), Types::String(vec![0xff, 0x00]));
+        dict.insert(b"a".to_vec(), Types::Integer(1));
+
+        let bencoder = Encoder::new(Types::Dictionary(dict));
+        let encoded_message = bencoder
+            .encode()
+            .expect("Error in test-5: Unable to encode the dictionary.");
+
+        assert_eq!(encoded_message, b"d1:ai1e1:z2:\xff\x00e".to_vec());
+    }
 }
@@ -0,0 +1,219 @@
+use crate::utils::{env_setting, optional_env_setting};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+// Only used to look up which of our addresses the route to it leaves from. Nothing is sent to it.
+const IPV6_PROBE: &str = "[2001:4860:4860::8888]:53";
+
+#[derive(Debug)]
+pub struct Addresses {
+    listen_interface: IpAddr,
+    advertised_ip: Option<IpAddr>,
+    advertised_port: Option<u16>,
+    detect_external_ip: bool,
+    external_ip: OnceLock<Option<IpAddr>>,
+    mapped_port: Mutex<Option<u16>>,
+}
+
+impl Default for Addresses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Addresses {
+    pub fn new() -> Self {
+        Self::new_with(
+            env_setting("LISTEN_INTERFACE", IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            optional_env_setting("ADVERTISED_IP"),
+            optional_env_setting("ADVERTISED_PORT").filter(|port| *port > 0),
+            env_setting("DETECT_EXTERNAL_IP", false),
+        )
+    }
+
+    pub fn new_with(
+        listen_interface: IpAddr,
+        advertised_ip: Option<IpAddr>,
+        advertised_port: Option<u16>,
+        detect_external_ip: bool,
+    ) -> Self {
+        Self {
+            listen_interface,
+            advertised_ip,
+            advertised_port,
+            detect_external_ip,
+            external_ip: OnceLock::new(),
+            mapped_port: Mutex::new(None),
+        }
+    }
+
+    pub fn listen_interface(&self) -> IpAddr {
+        self.listen_interface
+    }
+
+    pub fn advertised_ip(&self) -> Option<IpAddr> {
+        self.advertised_ip
+    }
+
+    // The configured port, else the one mapped on the gateway, else the one we listen on.
+    pub fn advertised_port(&self, listening_port: u16) -> u16 {
+        self.advertised_port
+            .or(*self.mapped_port.lock().unwrap())
+            .unwrap_or(listening_port)
+    }
+
+    pub fn set_mapped_port(&self, mapped_port: Option<u16>) {
+        *self.mapped_port.lock().unwrap() = mapped_port;
+    }
+
+    // Either the advertised IP or, when enabled, the one detected on first use.
+    pub fn external_ip(&self) -> Option<IpAddr> {
+        self.advertised_ip.or_else(|| {
+            *self
+                .external_ip
+                .get_or_init(|| match self.detect_external_ip {
+                    true => detect_external_ip(),
+                    false => None,
+                })
+        })
+    }
+
+    pub fn public_ipv4(&self) -> Option<Ipv4Addr> {
+        match self.external_ip() {
+            Some(IpAddr::V4(ip)) => Some(ip),
+            _ => None,
+        }
+    }
+
+    // Unless advertised, the source address of the route to the internet, as IPv6 hosts are
+    // usually not behind a NAT.
+    pub fn public_ipv6(&self) -> Option<Ipv6Addr> {
+        if let Some(IpAddr::V6(ip)) = self.advertised_ip {
+            return Some(ip);
+        }
+
+        let ip = match self.listen_interface {
+            IpAddr::V6(ip) if !ip.is_unspecified() => ip,
+            IpAddr::V4(ip) if !ip.is_unspecified() => return None,
+            _ => {
+                let socket = UdpSocket::bind("[::]:0").ok()?;
+                socket.connect(IPV6_PROBE).ok()?;
+
+                match socket.local_addr().ok()?.ip() {
+                    IpAddr::V6(ip) => ip,
+                    IpAddr::V4(_) => return None,
+                }
+            }
+        };
+
+        is_global_ipv6(ip).then_some(ip)
+    }
+
+    // Trackers hand our own address back to us.
+    pub fn is_own(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+
+        ip.is_unspecified()
+            || (!self.listen_interface.is_unspecified() && ip == self.listen_interface)
+            || self.external_ip() == Some(ip)
+    }
+
+    // A peer that turns out to be another torrent of this session is reached through our
+    // listening interface.
+    pub fn connect_address(&self, ip: IpAddr, port: u16) -> SocketAddr {
+        if !ip.is_loopback() && self.is_own(ip) {
+            return match self.listen_interface.is_unspecified() {
+                true => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                false => SocketAddr::new(self.listen_interface, port),
+            };
+        }
+
+        SocketAddr::new(ip, port)
+    }
+}
+
+fn is_global_ipv6(ip: Ipv6Addr) -> bool {
+    let first_segment = ip.segments()[0];
+
+    !ip.is_unspecified()
+        && !ip.is_loopback()
+        && ip.to_ipv4_mapped().is_none()
+        && (first_segment & 0xffc0) != 0xfe80
+        && (first_segment & 0xfe00) != 0xfc00
+}
+
+fn detect_external_ip() -> Option<IpAddr> {
+    let output = Command::new("dig")
+        .args([
+            "-4",
+            "TXT",
+            "+short",
+            "+time=2",
+            "+tries=1",
+            "o-o.myaddr.l.google.com",
+            "@ns1.google.com",
+        ])
+        .output()
+        .ok()?;
+
+    let address = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .trim_matches('"')
+        .parse::<IpAddr>();
+
+    match address {
+        Ok(address) => {
+            log::info!("Detected external IP {}", address);
+            Some(address)
+        }
+        Err(_) => {
+            log::warn!("Failed to detect external IP");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_own_addresses_are_reached_through_the_listening_interface() {
+        let advertised = IpAddr::from([203, 0, 113, 7]);
+        let lan = IpAddr::from([192, 168, 1, 20]);
+
+        let addresses = Addresses::new_with(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Some(advertised),
+            Some(51413),
+            true,
+        );
+
+        assert_eq!(addresses.external_ip(), Some(advertised));
+        assert_eq!(addresses.advertised_port(8000), 51413);
+        assert_eq!(
+            addresses.connect_address(advertised, 8001),
+            "127.0.0.1:8001".parse().unwrap()
+        );
+        assert_eq!(
+            addresses.connect_address(lan, 8001),
+            "192.168.1.20:8001".parse().unwrap()
+        );
+
+        let addresses = Addresses::new_with(lan, None, None, false);
+
+        assert_eq!(addresses.external_ip(), None);
+        assert_eq!(addresses.advertised_port(8000), 8000);
+        addresses.set_mapped_port(Some(40000));
+        assert_eq!(addresses.advertised_port(8000), 40000);
+        assert_eq!(
+            addresses.connect_address(lan, 8001),
+            "192.168.1.20:8001".parse().unwrap()
+        );
+        assert_eq!(
+            addresses.connect_address(advertised, 8001),
+            "203.0.113.7:8001".parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use super::{Direction, Profile, RateLimiter};
+use crate::utils::env_setting;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Limits are configured in KiB/s, 0 or too large meaning unlimited.
+fn limit_from_env(key: &str) -> u64 {
+    env_setting(key, 0_u64).checked_mul(1024).unwrap_or(0)
+}
+
+// Limits are in bytes per second, 0 meaning unlimited. A torrent with an override is
+// capped by both its own limiter and the global one. An active scheduled profile
+// replaces the global limits set from the settings.
+#[derive(Debug)]
+pub struct BandwidthLimits {
+    global: Arc<RateLimiter>,
+    base: Mutex<(u64, u64)>,
+    profile: Mutex<Option<Profile>>,
+    torrents: Mutex<HashMap<String, Arc<RateLimiter>>>,
+}
+
+impl Default for BandwidthLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BandwidthLimits {
+    pub fn new() -> Self {
+        let download_rate = limit_from_env("DOWNLOAD_RATE_LIMIT");
+        let upload_rate = limit_from_env("UPLOAD_RATE_LIMIT");
+
+        Self {
+            global: Arc::new(RateLimiter::new(download_rate, upload_rate)),
+            base: Mutex::new((download_rate, upload_rate)),
+            profile: Mutex::new(None),
+            torrents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The limits from the settings, even while a profile is active.
+    pub fn global_limits(&self) -> (u64, u64) {
+        *self.base.lock().unwrap()
+    }
+
+    pub fn set_global_limits(&self, download_rate: u64, upload_rate: u64) {
+        let profile_guard = self.profile.lock().unwrap();
+
+        *self.base.lock().unwrap() = (download_rate, upload_rate);
+
+        if profile_guard.is_none() {
+            self.global.set_limits(download_rate, upload_rate);
+        }
+    }
+
+    pub fn active_profile(&self) -> Option<Profile> {
+        self.profile.lock().unwrap().clone()
+    }
+
+    pub fn apply_profile(&self, profile: Option<Profile>) {
+        let mut profile_guard = self.profile.lock().unwrap();
+
+        match &profile {
+            Some(profile) => self
+                .global
+                .set_limits(profile.download_rate, profile.upload_rate),
+            None => {
+                let (download_rate, upload_rate) = *self.base.lock().unwrap();
+                self.global.set_limits(download_rate, upload_rate);
+            }
+        }
+
+        *profile_guard = profile;
+    }
+
+    pub fn torrent_limits(&self, torrent_pathname: &str) -> (u64, u64) {
+        match self.torrents.lock().unwrap().get(torrent_pathname) {
+            Some(limiter) => limiter.limits(),
+            None => (0, 0),
+        }
+    }
+
+    pub fn set_torrent_limits(&self, torrent_pathname: &str, download_rate: u64, upload_rate: u64) {
+        self.torrent_limiter(torrent_pathname)
+            .set_limits(download_rate, upload_rate);
+    }
+
+    pub fn remove_torrent(&self, torrent_pathname: &str) {
+        self.torrents.lock().unwrap().remove(torrent_pathname);
+    }
+
+    pub fn for_torrent(&self, torrent_pathname: &str) -> TorrentBandwidth {
+        TorrentBandwidth {
+            global: Arc::clone(&self.global),
+            torrent: self.torrent_limiter(torrent_pathname),
+        }
+    }
+
+    fn torrent_limiter(&self, torrent_pathname: &str) -> Arc<RateLimiter> {
+        Arc::clone(
+            self.torrents
+                .lock()
+                .unwrap()
+                .entry(torrent_pathname.to_string())
+                .or_insert_with(|| Arc::new(RateLimiter::unlimited())),
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TorrentBandwidth {
+    global: Arc<RateLimiter>,
+    torrent: Arc<RateLimiter>,
+}
+
+impl TorrentBandwidth {
+    pub fn throttle(&self, direction: Direction, bytes: usize) {
+        self.torrent.throttle(direction, bytes);
+        self.global.throttle(direction, bytes);
+    }
+}
@@ -0,0 +1,11 @@
+pub use index::{BandwidthLimits, TorrentBandwidth};
+pub use rate_limiter::{Direction, RateLimiter};
+pub use schedule::{Profile, Schedule};
+pub use scheduler::BandwidthScheduler;
+pub use token_bucket::TokenBucket;
+
+mod index;
+mod rate_limiter;
+mod schedule;
+mod scheduler;
+mod token_bucket;
@@ -0,0 +1,42 @@
+use super::TokenBucket;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Direction {
+    Download,
+    Upload,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    download: TokenBucket,
+    upload: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new(download_rate: u64, upload_rate: u64) -> Self {
+        Self {
+            download: TokenBucket::new(download_rate),
+            upload: TokenBucket::new(upload_rate),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0, 0)
+    }
+
+    pub fn limits(&self) -> (u64, u64) {
+        (self.download.rate(), self.upload.rate())
+    }
+
+    pub fn set_limits(&self, download_rate: u64, upload_rate: u64) {
+        self.download.set_rate(download_rate);
+        self.upload.set_rate(upload_rate);
+    }
+
+    pub fn throttle(&self, direction: Direction, bytes: usize) {
+        match direction {
+            Direction::Download => self.download.consume(bytes),
+            Direction::Upload => self.upload.consume(bytes),
+        }
+    }
+}
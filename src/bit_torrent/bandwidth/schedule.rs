@@ -0,0 +1,237 @@
+use crate::utils::env_setting;
+use std::collections::HashMap;
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub download_rate: u64,
+    pub upload_rate: u64,
+}
+
+#[derive(Clone, Debug)]
+struct TimeRange {
+    days: [bool; 7],
+    start: u32,
+    end: u32,
+    profile: String,
+}
+
+impl TimeRange {
+    fn contains(&self, weekday: usize, minute: u32) -> bool {
+        if self.start < self.end {
+            return self.days[weekday] && minute >= self.start && minute < self.end;
+        }
+
+        // Ranges such as 22:00-06:00 run past midnight into the following day.
+        let previous_day = (weekday + 6) % 7;
+
+        (self.days[weekday] && minute >= self.start)
+            || (self.days[previous_day] && minute < self.end)
+    }
+}
+
+// When ranges overlap, the first one listed wins.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    profiles: HashMap<String, Profile>,
+    ranges: Vec<TimeRange>,
+}
+
+impl Schedule {
+    pub fn new_from_env() -> Result<Self, String> {
+        Self::new(
+            &env_setting("BANDWIDTH_PROFILES", String::new()),
+            &env_setting("BANDWIDTH_SCHEDULE", String::new()),
+        )
+    }
+
+    pub fn new(profiles: &str, ranges: &str) -> Result<Self, String> {
+        let mut schedule = Self::default();
+
+        for profile in entries(profiles) {
+            let profile = Self::parse_profile(profile)?;
+            schedule.profiles.insert(profile.name.clone(), profile);
+        }
+
+        for range in entries(ranges) {
+            let range = Self::parse_range(range)?;
+
+            if !schedule.profiles.contains_key(&range.profile) {
+                return Err(format!("Unknown bandwidth profile: {}", range.profile));
+            }
+
+            schedule.ranges.push(range);
+        }
+
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Monday is weekday 0.
+    pub fn active_profile(&self, weekday: usize, minute: u32) -> Option<&Profile> {
+        self.ranges
+            .iter()
+            .find(|range| range.contains(weekday, minute))
+            .and_then(|range| self.profiles.get(&range.profile))
+    }
+
+    fn parse_profile(profile: &str) -> Result<Profile, String> {
+        let (name, limits) = profile
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid bandwidth profile: {}", profile))?;
+
+        let (download_rate, upload_rate) = limits
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid bandwidth profile limits: {}", limits))?;
+
+        let parse_rate = |rate: &str| {
+            rate.trim()
+                .parse::<u64>()
+                .ok()
+                .and_then(|rate| rate.checked_mul(1024))
+                .ok_or_else(|| format!("Invalid bandwidth profile limit: {}", rate))
+        };
+
+        Ok(Profile {
+            name: name.trim().to_string(),
+            download_rate: parse_rate(download_rate)?,
+            upload_rate: parse_rate(upload_rate)?,
+        })
+    }
+
+    fn parse_range(range: &str) -> Result<TimeRange, String> {
+        let fields: Vec<&str> = range.split_whitespace().collect();
+
+        if fields.len() != 3 {
+            return Err(format!("Invalid bandwidth schedule entry: {}", range));
+        }
+
+        let (start, end) = fields[1]
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid bandwidth schedule hours: {}", fields[1]))?;
+
+        Ok(TimeRange {
+            days: parse_days(fields[0])?,
+            start: parse_minute(start)?,
+            end: parse_minute(end)?,
+            profile: fields[2].to_string(),
+        })
+    }
+}
+
+fn entries(list: &str) -> impl Iterator<Item = &str> {
+    list.split(';')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+}
+
+fn day_index(day: &str) -> Result<usize, String> {
+    DAYS.iter()
+        .position(|some_day| *some_day == day.to_lowercase())
+        .ok_or_else(|| format!("Invalid day: {}", day))
+}
+
+fn parse_days(days: &str) -> Result<[bool; 7], String> {
+    let mut parsed = [false; 7];
+
+    if days == "all" {
+        return Ok([true; 7]);
+    }
+
+    for part in days.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day_index(from)?, day_index(to)?);
+                let mut day = from;
+
+                loop {
+                    parsed[day] = true;
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => parsed[day_index(part)?] = true,
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_minute(time: &str) -> Result<u32, String> {
+    let (hours, minutes) = time
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid time: {}", time))?;
+
+    let hours = hours
+        .parse::<u32>()
+        .or(Err(format!("Invalid time: {}", time)))?;
+    let minutes = minutes
+        .parse::<u32>()
+        .or(Err(format!("Invalid time: {}", time)))?;
+
+    let minute = hours * 60 + minutes;
+
+    if minutes >= 60 || minute > MINUTES_PER_DAY {
+        return Err(format!("Invalid time: {}", time));
+    }
+
+    Ok(minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_office_hours_profile_is_active_on_weekdays() {
+        let schedule = Schedule::new("office=0/1024", "mon-fri 09:00-18:00 office").unwrap();
+
+        let profile = schedule.active_profile(2, 10 * 60).unwrap();
+
+        assert_eq!(profile.name, "office");
+        assert_eq!(profile.download_rate, 0);
+        assert_eq!(profile.upload_rate, 1024 * 1024);
+        assert!(schedule.active_profile(2, 20 * 60).is_none());
+        assert!(schedule.active_profile(5, 10 * 60).is_none());
+    }
+
+    #[test]
+    fn test2_range_past_midnight_spills_into_next_day() {
+        let schedule = Schedule::new("night=100/100", "fri 22:00-06:00 night").unwrap();
+
+        assert!(schedule.active_profile(4, 23 * 60).is_some());
+        assert!(schedule.active_profile(5, 5 * 60).is_some());
+        assert!(schedule.active_profile(5, 7 * 60).is_none());
+        assert!(schedule.active_profile(4, 5 * 60).is_none());
+    }
+
+    #[test]
+    fn test3_day_lists_and_wrapping_ranges() {
+        let schedule = Schedule::new(
+            "weekend=0/0;late=10/10",
+            "sat,sun 00:00-24:00 weekend; fri-mon 20:00-23:00 late",
+        )
+        .unwrap();
+
+        assert_eq!(schedule.active_profile(6, 0).unwrap().name, "weekend");
+        assert_eq!(schedule.active_profile(0, 21 * 60).unwrap().name, "late");
+        assert!(schedule.active_profile(2, 21 * 60).is_none());
+    }
+
+    #[test]
+    fn test4_rejects_unknown_profiles_and_bad_times() {
+        assert!(Schedule::new("", "mon 09:00-18:00 office").is_err());
+        assert!(Schedule::new("office=0/1", "mon 09:00-25:00 office").is_err());
+        assert!(Schedule::new("office=0", "").is_err());
+        assert!(Schedule::new("office=18014398509481985/1", "").is_err());
+        assert!(Schedule::new("", "").unwrap().is_empty());
+    }
+}
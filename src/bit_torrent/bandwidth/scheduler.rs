@@ -0,0 +1,72 @@
+use super::{BandwidthLimits, Schedule};
+use crate::frontend::status::StatusData;
+use chrono::{Datelike, Local, Timelike};
+use gtk::glib::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+pub struct BandwidthScheduler {
+    limits: Arc<BandwidthLimits>,
+    schedule: Schedule,
+    tx_status: Sender<StatusData>,
+}
+
+impl BandwidthScheduler {
+    pub fn new(
+        limits: Arc<BandwidthLimits>,
+        schedule: Schedule,
+        tx_status: Sender<StatusData>,
+    ) -> Self {
+        Self {
+            limits,
+            schedule,
+            tx_status,
+        }
+    }
+
+    pub fn activate(self) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut current: Option<String> = None;
+
+            if self
+                .tx_status
+                .send(StatusData::BandwidthProfile(None))
+                .is_err()
+            {
+                return;
+            }
+
+            loop {
+                let now = Local::now();
+                let weekday = now.weekday().num_days_from_monday() as usize;
+                let minute = now.hour() * 60 + now.minute();
+
+                let profile = self.schedule.active_profile(weekday, minute).cloned();
+                let name = profile.as_ref().map(|profile| profile.name.clone());
+
+                if name != current {
+                    log::info!(
+                        "BandwidthScheduler::activate() - switching to profile {:?}",
+                        name
+                    );
+
+                    self.limits.apply_profile(profile);
+                    current = name.clone();
+
+                    if self
+                        .tx_status
+                        .send(StatusData::BandwidthProfile(name))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+
+                thread::sleep(CHECK_INTERVAL);
+            }
+        })
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+    next_ticket: u64,
+    serving: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+    }
+}
+
+// Holds at most one second worth of tokens. Callers are served in ticket order so connections
+// share the bandwidth round-robin.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bucket: Mutex<Bucket>,
+    turn: Condvar,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+                next_ticket: 0,
+                serving: 0,
+            }),
+            turn: Condvar::new(),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().unwrap().rate
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+
+        bucket.refill();
+        bucket.rate = rate;
+        bucket.tokens = bucket.tokens.min(rate as f64);
+
+        self.turn.notify_all();
+    }
+
+    pub fn consume(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().unwrap();
+
+        if bucket.rate == 0 && bucket.serving == bucket.next_ticket {
+            return;
+        }
+
+        let ticket = bucket.next_ticket;
+        bucket.next_ticket += 1;
+
+        while bucket.serving != ticket {
+            bucket = self.turn.wait(bucket).unwrap();
+        }
+
+        let mut remaining = bytes as f64;
+
+        loop {
+            bucket.refill();
+
+            if bucket.rate == 0 {
+                break;
+            }
+
+            let granted = remaining.min(bucket.tokens);
+            bucket.tokens -= granted;
+            remaining -= granted;
+
+            if remaining <= 0.0 {
+                break;
+            }
+
+            let missing = remaining.min(bucket.rate as f64);
+            let wait = Duration::from_secs_f64(missing / bucket.rate as f64);
+
+            bucket = self.turn.wait_timeout(bucket, wait).unwrap().0;
+        }
+
+        bucket.serving += 1;
+        self.turn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test1_unlimited_bucket_does_not_block() {
+        let bucket = TokenBucket::new(0);
+        let start = Instant::now();
+
+        bucket.consume(100 * 1024 * 1024);
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test2_bucket_throttles_to_rate() {
+        let bucket = TokenBucket::new(10_000);
+        let start = Instant::now();
+
+        // The first 10_000 bytes are the initial burst, the next 5_000 take half a second.
+        bucket.consume(10_000);
+        bucket.consume(5_000);
+
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[test]
+    fn test3_bucket_serves_waiters_in_turns() {
+        let bucket = Arc::new(TokenBucket::new(20_000));
+        bucket.consume(20_000);
+
+        let order = Arc::new(Mutex::new(vec![]));
+
+        let handles: Vec<thread::JoinHandle<()>> = (0..2)
+            .map(|connection| {
+                let bucket = Arc::clone(&bucket);
+                let order = Arc::clone(&order);
+
+                thread::spawn(move || {
+                    for _ in 0..3 {
+                        bucket.consume(2_000);
+                        order.lock().unwrap().push(connection);
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let order = order.lock().unwrap();
+        let first_half: Vec<&usize> = order.iter().take(4).collect();
+
+        assert!(first_half.contains(&&0) && first_half.contains(&&1));
+    }
+
+    #[test]
+    fn test4_lowering_rate_caps_stored_tokens() {
+        let bucket = TokenBucket::new(1_000_000);
+        bucket.set_rate(1_000);
+
+        let start = Instant::now();
+        bucket.consume(1_500);
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
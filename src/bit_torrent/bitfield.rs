@@ -65,6 +65,13 @@ impl Bitfield {
         self.downloading[piece_index] &= !(128 >> piece_subindex);
     }
 
+    pub fn unset(&mut self, index: usize) {
+        let piece_index = index / 8;
+        let piece_subindex = index % 8;
+
+        self.have[piece_index] &= !(128 >> piece_subindex);
+    }
+
     pub fn set_downloading(&mut self, index: usize) {
         let piece_index = index / 8;
         let piece_subindex = index % 8;
@@ -110,12 +117,6 @@ impl Bitfield {
     }
 
     pub fn first_needed_available_piece(&self, need: &Bitfield) -> Option<usize> {
-        for i in 0..self.total_pieces {
-            if self.has(i) && !need.has(i) && !need.is_downloading(i) {
-                return Some(i);
-            }
-        }
-
-        None
+        (0..self.total_pieces).find(|&i| self.has(i) && !need.has(i) && !need.is_downloading(i))
     }
 }
@@ -0,0 +1,166 @@
+use crate::utils::env_setting;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_MAX_CONNECTIONS: usize = 200;
+const DEFAULT_MAX_CONNECTIONS_PER_TORRENT: usize = 30;
+const DEFAULT_MAX_HALF_OPEN: usize = 8;
+
+fn limit_from_env(key: &str, default: usize) -> usize {
+    match env_setting(key, default) {
+        0 => default,
+        limit => limit,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    open: usize,
+    half_open: usize,
+}
+
+#[derive(Debug)]
+pub struct ConnectionLimits {
+    max_connections: usize,
+    max_connections_per_torrent: usize,
+    max_half_open: usize,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionLimits {
+    pub fn new() -> Self {
+        Self::new_with_limits(
+            limit_from_env("MAX_CONNECTIONS", DEFAULT_MAX_CONNECTIONS),
+            limit_from_env(
+                "MAX_CONNECTIONS_PER_TORRENT",
+                DEFAULT_MAX_CONNECTIONS_PER_TORRENT,
+            ),
+            limit_from_env("MAX_HALF_OPEN", DEFAULT_MAX_HALF_OPEN),
+        )
+    }
+
+    pub fn new_with_limits(
+        max_connections: usize,
+        max_connections_per_torrent: usize,
+        max_half_open: usize,
+    ) -> Self {
+        Self {
+            max_connections,
+            max_connections_per_torrent,
+            max_half_open,
+            counts: Arc::new(Mutex::new(Counts::default())),
+        }
+    }
+
+    pub fn max_connections_per_torrent(&self) -> usize {
+        self.max_connections_per_torrent
+    }
+
+    pub fn open(&self) -> usize {
+        self.counts.lock().unwrap().open
+    }
+
+    pub fn half_open(&self) -> usize {
+        self.counts.lock().unwrap().half_open
+    }
+
+    pub fn try_connect(&self) -> Option<ConnectionSlot> {
+        let mut counts = self.counts.lock().unwrap();
+
+        if counts.open >= self.max_connections || counts.half_open >= self.max_half_open {
+            return None;
+        }
+
+        counts.open += 1;
+        counts.half_open += 1;
+
+        Some(ConnectionSlot {
+            counts: Arc::clone(&self.counts),
+            half_open: true,
+        })
+    }
+
+    // Incoming connections are never half-open.
+    pub fn try_accept(&self) -> Option<ConnectionSlot> {
+        let mut counts = self.counts.lock().unwrap();
+
+        if counts.open >= self.max_connections {
+            return None;
+        }
+
+        counts.open += 1;
+
+        Some(ConnectionSlot {
+            counts: Arc::clone(&self.counts),
+            half_open: false,
+        })
+    }
+}
+
+// Released when dropped.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    counts: Arc<Mutex<Counts>>,
+    half_open: bool,
+}
+
+impl ConnectionSlot {
+    pub fn established(&mut self) {
+        if self.half_open {
+            self.half_open = false;
+            self.counts.lock().unwrap().half_open -= 1;
+        }
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+
+        counts.open -= 1;
+        if self.half_open {
+            counts.half_open -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_half_open_slots_are_capped_until_established() {
+        let limits = ConnectionLimits::new_with_limits(10, 10, 2);
+
+        let mut first = limits.try_connect().unwrap();
+        let _second = limits.try_connect().unwrap();
+
+        assert!(limits.try_connect().is_none());
+
+        first.established();
+
+        assert!(limits.try_connect().is_some());
+        assert_eq!(limits.half_open(), 1);
+    }
+
+    #[test]
+    fn test2_dropping_slots_releases_them() {
+        let limits = ConnectionLimits::new_with_limits(2, 10, 2);
+
+        let outgoing = limits.try_connect().unwrap();
+        let _incoming = limits.try_accept().unwrap();
+
+        assert!(limits.try_accept().is_none());
+
+        drop(outgoing);
+
+        assert_eq!(limits.open(), 1);
+        assert_eq!(limits.half_open(), 0);
+        assert!(limits.try_accept().is_some());
+    }
+}
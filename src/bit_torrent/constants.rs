@@ -1,2 +1,16 @@
 pub const BLOCK_LENGTH: u32 = 2_u32.pow(14);
 pub const BLOCK_LENGTH_B: [u8; 4] = BLOCK_LENGTH.to_be_bytes();
+pub const RECONNECT_BASE_DELAY: u64 = 15;
+pub const RECONNECT_MAX_DELAY: u64 = 30 * 60;
+pub const MAX_PEER_FAILURES: u32 = 6;
+pub const MAX_QUEUED_REQUESTS: usize = 250;
+pub const COMPACT_PEER_LENGTH: usize = 6;
+pub const COMPACT_PEER6_LENGTH: usize = 18;
+pub const UDP_TRACKER_TIMEOUT: u64 = 5;
+pub const UDP_TRACKER_RETRANSMISSIONS: u32 = 1;
+pub const STOPPED_ANNOUNCE_TIMEOUT: u64 = 5;
+pub const MIN_ANNOUNCE_INTERVAL: u64 = 60;
+pub const MAX_ANNOUNCE_INTERVAL: u64 = 2 * 60 * 60;
+pub const MIN_SCRAPE_INTERVAL: u64 = 5 * 60;
+pub const DHT_ANNOUNCE_INTERVAL: u64 = 15 * 60;
+pub const TRACKER_RETRY_INTERVAL: u64 = 5 * 60;
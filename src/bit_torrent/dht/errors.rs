@@ -0,0 +1,9 @@
+#[derive(Debug)]
+
+pub enum DhtError {
+    FailedToBind,
+    FailedToSend,
+    InvalidMessage,
+    InvalidNodeAddress,
+    FailedToSaveNodes,
+}
@@ -0,0 +1,646 @@
+use super::krpc::{decode_address, decode_nodes, encode_address, encode_nodes};
+use super::{DhtError, Krpc, NodeId, RoutingTable, K};
+use crate::bencoder::Types;
+use crate::utils::env_setting;
+use rand::random;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet, LinkedList};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ALPHA: usize = 3;
+const MAX_LOOKUP_ROUNDS: usize = 16;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(500);
+const SECRET_LIFETIME: Duration = Duration::from_secs(5 * 60);
+const PEER_LIFETIME: Duration = Duration::from_secs(30 * 60);
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// Keeps get_peers answers within a single UDP packet.
+const MAX_VALUES: usize = 50;
+const MAX_PACKET_SIZE: usize = 1500;
+
+type Dictionary = HashMap<Vec<u8>, Types>;
+
+// Answer to one of our queries, None for KRPC errors.
+type Reply = (Vec<u8>, Option<Dictionary>);
+
+#[derive(Debug)]
+struct Secrets {
+    current: [u8; 8],
+    previous: [u8; 8],
+    rotated_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Lookup {
+    peers: Vec<SocketAddr>,
+    tokens: Vec<(NodeId, SocketAddr, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+pub struct Dht {
+    socket: UdpSocket,
+    own_id: NodeId,
+    routing_table: Mutex<RoutingTable>,
+    pending: Mutex<HashMap<Vec<u8>, Sender<Reply>>>,
+    next_transaction: AtomicU16,
+    secrets: Mutex<Secrets>,
+    peers: Mutex<HashMap<NodeId, Vec<(SocketAddr, Instant)>>>,
+    nodes_pathname: Option<String>,
+}
+
+impl Dht {
+    pub fn new_from_env() -> Option<Arc<Self>> {
+        let port = env_setting("DHT_PORT", 0_u16);
+
+        if port == 0 {
+            return None;
+        }
+
+        let nodes_pathname = env::var("DHT_NODES_PATH")
+            .ok()
+            .filter(|pathname| !pathname.is_empty());
+
+        let dht = match Self::bind(&format!("0.0.0.0:{}", port), nodes_pathname) {
+            Ok(dht) => dht,
+            Err(error) => {
+                log::error!("Failed to start the DHT on port {}: {:?}", port, error);
+                return None;
+            }
+        };
+
+        let bootstrap_nodes: Vec<String> = env::var("DHT_BOOTSTRAP_NODES")
+            .unwrap_or_default()
+            .split(';')
+            .map(|node| node.trim().to_string())
+            .filter(|node| !node.is_empty())
+            .collect();
+
+        let weak = Arc::downgrade(&dht);
+
+        thread::spawn(move || {
+            if let Some(dht) = weak.upgrade() {
+                dht.bootstrap(&bootstrap_nodes);
+            }
+
+            loop {
+                thread::sleep(MAINTENANCE_INTERVAL);
+
+                let dht = match weak.upgrade() {
+                    Some(dht) => dht,
+                    None => break,
+                };
+
+                if dht.routing_table.lock().unwrap().len() < K {
+                    dht.bootstrap(&bootstrap_nodes);
+                }
+
+                if let Err(error) = dht.save_nodes() {
+                    log::warn!("Failed to save DHT nodes: {:?}", error);
+                }
+            }
+        });
+
+        Some(dht)
+    }
+
+    // Saved nodes are only used once bootstrap is called.
+    pub fn bind(address: &str, nodes_pathname: Option<String>) -> Result<Arc<Self>, DhtError> {
+        let socket = UdpSocket::bind(address).or(Err(DhtError::FailedToBind))?;
+        socket
+            .set_read_timeout(Some(RECEIVE_TIMEOUT))
+            .or(Err(DhtError::FailedToBind))?;
+
+        let own_id = NodeId::random();
+
+        let dht = Arc::new(Self {
+            socket,
+            own_id,
+            routing_table: Mutex::new(RoutingTable::new(own_id)),
+            pending: Mutex::new(HashMap::new()),
+            next_transaction: AtomicU16::new(random()),
+            secrets: Mutex::new(Secrets {
+                current: random(),
+                previous: random(),
+                rotated_at: Instant::now(),
+            }),
+            peers: Mutex::new(HashMap::new()),
+            nodes_pathname,
+        });
+
+        let weak = Arc::downgrade(&dht);
+        thread::spawn(move || Self::receive(weak));
+
+        log::info!("DHT node {:?} listening on {:?}", own_id, dht.local_addr());
+
+        Ok(dht)
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.routing_table.lock().unwrap().len()
+    }
+
+    // Looking up our own id lets nearby nodes learn about us.
+    pub fn bootstrap(&self, bootstrap_nodes: &[String]) {
+        let mut addresses = self.load_nodes();
+
+        for node in bootstrap_nodes {
+            match node.to_socket_addrs() {
+                Ok(resolved) => addresses.extend(resolved.filter(|address| address.is_ipv4())),
+                Err(error) => log::warn!("Failed to resolve DHT node {}: {}", node, error),
+            }
+        }
+
+        let queries = addresses
+            .into_iter()
+            .map(|address| {
+                (
+                    address,
+                    b"find_node".to_vec(),
+                    self.target_arguments(b"target", &self.own_id),
+                )
+            })
+            .collect();
+
+        // Answers land in the routing table on their own.
+        self.query_all(queries);
+        self.lookup(&self.own_id, b"find_node");
+
+        log::info!("DHT bootstrapped with {} nodes", self.nodes());
+    }
+
+    pub fn announce(&self, info_hash: &[u8], port: u16) -> Vec<SocketAddr> {
+        let info_hash = match NodeId::from_bytes(info_hash) {
+            Some(info_hash) => info_hash,
+            None => return vec![],
+        };
+
+        let lookup = self.lookup(&info_hash, b"get_peers");
+
+        let queries = lookup
+            .tokens
+            .into_iter()
+            .map(|(_, address, token)| {
+                let mut arguments = self.target_arguments(b"info_hash", &info_hash);
+                arguments.insert(b"port".to_vec(), Types::Integer(port as i64));
+                arguments.insert(b"token".to_vec(), Types::String(token));
+                arguments.insert(b"implied_port".to_vec(), Types::Integer(0));
+
+                (address, b"announce_peer".to_vec(), arguments)
+            })
+            .collect();
+
+        self.query_all(queries);
+
+        lookup.peers
+    }
+
+    pub fn save_nodes(&self) -> Result<(), DhtError> {
+        let pathname = match &self.nodes_pathname {
+            Some(pathname) => pathname,
+            None => return Ok(()),
+        };
+
+        let nodes: Vec<String> = self
+            .routing_table
+            .lock()
+            .unwrap()
+            .nodes()
+            .iter()
+            .map(|node| node.address.to_string())
+            .collect();
+
+        if let Some(directory) = Path::new(pathname).parent() {
+            fs::create_dir_all(directory).or(Err(DhtError::FailedToSaveNodes))?;
+        }
+
+        fs::write(pathname, nodes.join("\n")).or(Err(DhtError::FailedToSaveNodes))
+    }
+
+    fn load_nodes(&self) -> Vec<SocketAddr> {
+        self.nodes_pathname
+            .as_ref()
+            .and_then(|pathname| fs::read_to_string(pathname).ok())
+            .map(|nodes| {
+                nodes
+                    .lines()
+                    .filter_map(|node| node.trim().parse::<SocketAddr>().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn lookup(&self, target: &NodeId, method: &[u8]) -> Lookup {
+        let argument = match method {
+            b"get_peers" => b"info_hash".as_slice(),
+            _ => b"target".as_slice(),
+        };
+
+        let mut candidates = self.routing_table.lock().unwrap().closest(target, K);
+        let mut queried = HashSet::new();
+        let mut lookup = Lookup::default();
+
+        for _ in 0..MAX_LOOKUP_ROUNDS {
+            let queries: Vec<_> = candidates
+                .iter()
+                .filter(|node| !queried.contains(&node.address))
+                .take(ALPHA)
+                .map(|node| {
+                    (
+                        node.address,
+                        method.to_vec(),
+                        self.target_arguments(argument, target),
+                    )
+                })
+                .collect();
+
+            if queries.is_empty() {
+                break;
+            }
+
+            for (address, reply) in self.query_all(queries) {
+                queried.insert(address);
+
+                let values = match reply {
+                    Some(values) => values,
+                    None => {
+                        self.routing_table.lock().unwrap().remove(&address);
+                        candidates.retain(|node| node.address != address);
+                        continue;
+                    }
+                };
+
+                if let Some(Types::String(nodes)) = values.get(b"nodes".as_slice()) {
+                    for (id, address) in decode_nodes(nodes) {
+                        if id != self.own_id && !candidates.iter().any(|node| node.id == id) {
+                            candidates.push(super::Node {
+                                id,
+                                address,
+                                last_seen: Instant::now(),
+                            });
+                        }
+                    }
+                }
+
+                if let Some(Types::List(peers)) = values.get(b"values".as_slice()) {
+                    for peer in peers {
+                        if let Some(peer) = peer.get_string().and_then(|peer| decode_address(&peer))
+                        {
+                            if !lookup.peers.contains(&peer) {
+                                lookup.peers.push(peer);
+                            }
+                        }
+                    }
+                }
+
+                let id = values
+                    .get(b"id".as_slice())
+                    .and_then(|id| id.get_string())
+                    .and_then(|id| NodeId::from_bytes(&id));
+
+                if let (Some(id), Some(Types::String(token))) =
+                    (id, values.get(b"token".as_slice()))
+                {
+                    lookup.tokens.push((id, address, token.clone()));
+                }
+            }
+
+            candidates.sort_by_key(|node| node.id.distance(target));
+            candidates.truncate(K);
+        }
+
+        lookup.tokens.sort_by_key(|(id, _, _)| id.distance(target));
+        lookup.tokens.truncate(K);
+
+        lookup
+    }
+
+    // Queries left unanswered within QUERY_TIMEOUT come back with None.
+    fn query_all(
+        &self,
+        queries: Vec<(SocketAddr, Vec<u8>, Dictionary)>,
+    ) -> Vec<(SocketAddr, Option<Dictionary>)> {
+        let (tx, rx) = mpsc::channel();
+        let mut outstanding = HashMap::new();
+
+        for (address, method, arguments) in queries {
+            let transaction_id = self
+                .next_transaction
+                .fetch_add(1, Ordering::Relaxed)
+                .to_be_bytes()
+                .to_vec();
+
+            let message = Krpc::Query {
+                transaction_id: transaction_id.clone(),
+                method,
+                arguments,
+            }
+            .encode();
+
+            self.pending
+                .lock()
+                .unwrap()
+                .insert(transaction_id.clone(), tx.clone());
+
+            if self.socket.send_to(&message, address).is_ok() {
+                outstanding.insert(transaction_id, address);
+            } else {
+                self.pending.lock().unwrap().remove(&transaction_id);
+            }
+        }
+
+        let deadline = Instant::now() + QUERY_TIMEOUT;
+        let mut replies = vec![];
+
+        while !outstanding.is_empty() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match rx.recv_timeout(timeout) {
+                Ok((transaction_id, reply)) => {
+                    if let Some(address) = outstanding.remove(&transaction_id) {
+                        replies.push((address, reply));
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+
+        for (transaction_id, address) in outstanding {
+            pending.remove(&transaction_id);
+            replies.push((address, None));
+        }
+
+        replies
+    }
+
+    fn receive(weak: Weak<Self>) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        loop {
+            let dht = match weak.upgrade() {
+                Some(dht) => dht,
+                None => break,
+            };
+
+            match dht.socket.recv_from(&mut buffer) {
+                Ok((length, from)) => dht.handle(&buffer[..length], from),
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(error) => log::debug!("Dht::receive() - {}", error),
+            }
+        }
+    }
+
+    fn handle(&self, bytes: &[u8], from: SocketAddr) {
+        match Krpc::decode(bytes) {
+            Ok(Krpc::Query {
+                transaction_id,
+                method,
+                arguments,
+            }) => {
+                let response = match self.answer(&method, &arguments, from) {
+                    Ok(values) => Krpc::Response {
+                        transaction_id,
+                        values,
+                    },
+                    Err((code, message)) => Krpc::Error {
+                        transaction_id,
+                        code,
+                        message: message.to_string(),
+                    },
+                };
+
+                let _ = self.socket.send_to(&response.encode(), from);
+            }
+            Ok(Krpc::Response {
+                transaction_id,
+                values,
+            }) => {
+                // Unsolicited responses are not worth a place in the routing table.
+                if !self.pending.lock().unwrap().contains_key(&transaction_id) {
+                    return;
+                }
+
+                if let Some(id) = values
+                    .get(b"id".as_slice())
+                    .and_then(|id| id.get_string())
+                    .and_then(|id| NodeId::from_bytes(&id))
+                {
+                    self.routing_table.lock().unwrap().insert(id, from);
+                }
+
+                self.reply(transaction_id, Some(values));
+            }
+            Ok(Krpc::Error {
+                transaction_id,
+                code,
+                message,
+            }) => {
+                log::debug!("DHT node {} answered {} {}", from, code, message);
+                self.reply(transaction_id, None);
+            }
+            Err(_) => log::debug!("Invalid DHT message from {}", from),
+        }
+    }
+
+    fn reply(&self, transaction_id: Vec<u8>, reply: Option<Dictionary>) {
+        if let Some(tx) = self.pending.lock().unwrap().remove(&transaction_id) {
+            let _ = tx.send((transaction_id, reply));
+        }
+    }
+
+    fn answer(
+        &self,
+        method: &[u8],
+        arguments: &Dictionary,
+        from: SocketAddr,
+    ) -> Result<Dictionary, (i64, &'static str)> {
+        let id = argument_id(arguments, b"id").ok_or((203, "Protocol Error"))?;
+
+        self.routing_table.lock().unwrap().insert(id, from);
+
+        let mut values = HashMap::new();
+        values.insert(b"id".to_vec(), Types::String(self.own_id.0.to_vec()));
+
+        match method {
+            b"ping" => {}
+            b"find_node" => {
+                let target = argument_id(arguments, b"target").ok_or((203, "Protocol Error"))?;
+                values.insert(
+                    b"nodes".to_vec(),
+                    Types::String(self.closest_nodes(&target)),
+                );
+            }
+            b"get_peers" => {
+                let info_hash =
+                    argument_id(arguments, b"info_hash").ok_or((203, "Protocol Error"))?;
+
+                values.insert(b"token".to_vec(), Types::String(self.token(from.ip())));
+                values.insert(
+                    b"nodes".to_vec(),
+                    Types::String(self.closest_nodes(&info_hash)),
+                );
+
+                let peers = self.stored_peers(&info_hash);
+
+                if !peers.is_empty() {
+                    values.insert(b"values".to_vec(), Types::List(peers));
+                }
+            }
+            b"announce_peer" => {
+                let info_hash =
+                    argument_id(arguments, b"info_hash").ok_or((203, "Protocol Error"))?;
+
+                let token = arguments
+                    .get(b"token".as_slice())
+                    .and_then(|token| token.get_string())
+                    .ok_or((203, "Protocol Error"))?;
+
+                if !self.valid_token(from.ip(), &token) {
+                    return Err((203, "Bad Token"));
+                }
+
+                let implied_port = arguments
+                    .get(b"implied_port".as_slice())
+                    .and_then(|implied_port| implied_port.get_integrer())
+                    .unwrap_or(0);
+
+                let port = match implied_port {
+                    0 => arguments
+                        .get(b"port".as_slice())
+                        .and_then(|port| port.get_integrer())
+                        .and_then(|port| u16::try_from(port).ok())
+                        .ok_or((203, "Protocol Error"))?,
+                    _ => from.port(),
+                };
+
+                self.store_peer(info_hash, SocketAddr::new(from.ip(), port));
+            }
+            _ => return Err((204, "Method Unknown")),
+        }
+
+        Ok(values)
+    }
+
+    fn target_arguments(&self, name: &[u8], target: &NodeId) -> Dictionary {
+        let mut arguments = HashMap::new();
+        arguments.insert(b"id".to_vec(), Types::String(self.own_id.0.to_vec()));
+        arguments.insert(name.to_vec(), Types::String(target.0.to_vec()));
+        arguments
+    }
+
+    fn closest_nodes(&self, target: &NodeId) -> Vec<u8> {
+        encode_nodes(&self.routing_table.lock().unwrap().closest(target, K))
+    }
+
+    fn stored_peers(&self, info_hash: &NodeId) -> LinkedList<Types> {
+        let mut peers = self.peers.lock().unwrap();
+
+        match peers.get_mut(info_hash) {
+            Some(stored) => {
+                stored.retain(|(_, announced_at)| announced_at.elapsed() < PEER_LIFETIME);
+
+                stored
+                    .iter()
+                    .take(MAX_VALUES)
+                    .filter_map(|(peer, _)| encode_address(peer))
+                    .map(Types::String)
+                    .collect()
+            }
+            None => LinkedList::new(),
+        }
+    }
+
+    fn store_peer(&self, info_hash: NodeId, peer: SocketAddr) {
+        let mut peers = self.peers.lock().unwrap();
+        let stored = peers.entry(info_hash).or_default();
+
+        stored.retain(|(stored_peer, _)| *stored_peer != peer);
+        stored.push((peer, Instant::now()));
+    }
+
+    // Tokens hash the asking node's IP with a secret that changes every five minutes. The
+    // previous secret is still accepted, so tokens last five to ten minutes.
+    fn token(&self, ip: IpAddr) -> Vec<u8> {
+        let secrets = self.rotated_secrets();
+        hash_token(&secrets.current, ip)
+    }
+
+    fn valid_token(&self, ip: IpAddr, token: &[u8]) -> bool {
+        let secrets = self.rotated_secrets();
+        token == hash_token(&secrets.current, ip) || token == hash_token(&secrets.previous, ip)
+    }
+
+    fn rotated_secrets(&self) -> std::sync::MutexGuard<'_, Secrets> {
+        let mut secrets = self.secrets.lock().unwrap();
+
+        if secrets.rotated_at.elapsed() >= SECRET_LIFETIME {
+            secrets.previous = secrets.current;
+            secrets.current = random();
+            secrets.rotated_at = Instant::now();
+        }
+
+        secrets
+    }
+}
+
+fn argument_id(arguments: &Dictionary, name: &[u8]) -> Option<NodeId> {
+    arguments
+        .get(name)
+        .and_then(|id| id.get_string())
+        .and_then(|id| NodeId::from_bytes(&id))
+}
+
+fn hash_token(secret: &[u8], ip: IpAddr) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(secret);
+
+    match ip {
+        IpAddr::V4(ip) => hasher.update(ip.octets()),
+        IpAddr::V6(ip) => hasher.update(ip.octets()),
+    }
+
+    hasher.finalize()[..8].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_local_nodes_find_announced_peers() {
+        let nodes: Vec<Arc<Dht>> = (0..5)
+            .map(|_| Dht::bind("127.0.0.1:0", None).unwrap())
+            .collect();
+
+        let bootstrap = vec![nodes[0].local_addr().unwrap().to_string()];
+
+        for node in &nodes[1..] {
+            node.bootstrap(&bootstrap);
+        }
+
+        assert!(nodes.iter().all(|node| node.nodes() > 0));
+
+        let info_hash = [42; 20];
+
+        assert!(nodes[1].announce(&info_hash, 5000).is_empty());
+
+        let peers = nodes[4].announce(&info_hash, 6000);
+
+        assert_eq!(peers, vec![SocketAddr::from(([127, 0, 0, 1], 5000))]);
+    }
+}
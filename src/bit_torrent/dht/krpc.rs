@@ -0,0 +1,234 @@
+use super::node_id::ID_LENGTH;
+use super::{DhtError, Node, NodeId};
+use crate::bencoder::{Decoder, Encoder, Types};
+use std::collections::{HashMap, LinkedList};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+const COMPACT_NODE_LENGTH: usize = ID_LENGTH + 6;
+
+#[derive(Debug)]
+pub enum Krpc {
+    Query {
+        transaction_id: Vec<u8>,
+        method: Vec<u8>,
+        arguments: HashMap<Vec<u8>, Types>,
+    },
+    Response {
+        transaction_id: Vec<u8>,
+        values: HashMap<Vec<u8>, Types>,
+    },
+    Error {
+        transaction_id: Vec<u8>,
+        code: i64,
+        message: String,
+    },
+}
+
+impl Krpc {
+    pub fn decode(bytes: &[u8]) -> Result<Self, DhtError> {
+        let mut message = match Decoder::new_from_bytes(bytes).decode() {
+            Ok(Types::Dictionary(message)) => message,
+            _ => return Err(DhtError::InvalidMessage),
+        };
+
+        let transaction_id = match message.remove(b"t".as_slice()) {
+            Some(Types::String(transaction_id)) => transaction_id,
+            _ => return Err(DhtError::InvalidMessage),
+        };
+
+        let kind = message
+            .get(b"y".as_slice())
+            .and_then(|kind| kind.get_string())
+            .ok_or(DhtError::InvalidMessage)?;
+
+        match kind.as_slice() {
+            b"q" => {
+                let method = message
+                    .get(b"q".as_slice())
+                    .and_then(|method| method.get_string())
+                    .ok_or(DhtError::InvalidMessage)?;
+
+                match message.remove(b"a".as_slice()) {
+                    Some(Types::Dictionary(arguments)) => Ok(Self::Query {
+                        transaction_id,
+                        method,
+                        arguments,
+                    }),
+                    _ => Err(DhtError::InvalidMessage),
+                }
+            }
+            b"r" => match message.remove(b"r".as_slice()) {
+                Some(Types::Dictionary(values)) => Ok(Self::Response {
+                    transaction_id,
+                    values,
+                }),
+                _ => Err(DhtError::InvalidMessage),
+            },
+            b"e" => {
+                let error = message
+                    .get(b"e".as_slice())
+                    .and_then(|error| error.get_list())
+                    .ok_or(DhtError::InvalidMessage)?;
+                let mut error = error.iter();
+
+                Ok(Self::Error {
+                    transaction_id,
+                    code: error
+                        .next()
+                        .and_then(|code| code.get_integrer())
+                        .unwrap_or(0),
+                    message: error
+                        .next()
+                        .and_then(|message| message.get_string())
+                        .map(|message| String::from_utf8_lossy(&message).to_string())
+                        .unwrap_or_default(),
+                })
+            }
+            _ => Err(DhtError::InvalidMessage),
+        }
+    }
+
+    pub fn encode(self) -> Vec<u8> {
+        let mut message = HashMap::new();
+
+        match self {
+            Self::Query {
+                transaction_id,
+                method,
+                arguments,
+            } => {
+                message.insert(b"t".to_vec(), Types::String(transaction_id));
+                message.insert(b"y".to_vec(), Types::String(b"q".to_vec()));
+                message.insert(b"q".to_vec(), Types::String(method));
+                message.insert(b"a".to_vec(), Types::Dictionary(arguments));
+            }
+            Self::Response {
+                transaction_id,
+                values,
+            } => {
+                message.insert(b"t".to_vec(), Types::String(transaction_id));
+                message.insert(b"y".to_vec(), Types::String(b"r".to_vec()));
+                message.insert(b"r".to_vec(), Types::Dictionary(values));
+            }
+            Self::Error {
+                transaction_id,
+                code,
+                message: error,
+            } => {
+                message.insert(b"t".to_vec(), Types::String(transaction_id));
+                message.insert(b"y".to_vec(), Types::String(b"e".to_vec()));
+                message.insert(
+                    b"e".to_vec(),
+                    Types::List(LinkedList::from([
+                        Types::Integer(code),
+                        Types::String(error.into_bytes()),
+                    ])),
+                );
+            }
+        }
+
+        Encoder::new(Types::Dictionary(message))
+            .encode()
+            .expect("Bencoding a KRPC message cannot fail")
+    }
+}
+
+// Compact node info, only IPv4 nodes are encoded.
+pub fn encode_nodes(nodes: &[Node]) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    for node in nodes {
+        if let Some(address) = encode_address(&node.address) {
+            bytes.extend_from_slice(&node.id.0);
+            bytes.extend_from_slice(&address);
+        }
+    }
+
+    bytes
+}
+
+pub fn decode_nodes(bytes: &[u8]) -> Vec<(NodeId, SocketAddr)> {
+    bytes
+        .chunks_exact(COMPACT_NODE_LENGTH)
+        .filter_map(|node| {
+            let (id, address) = node.split_at(ID_LENGTH);
+
+            Some((NodeId::from_bytes(id)?, decode_address(address)?))
+        })
+        .collect()
+}
+
+pub fn encode_address(address: &SocketAddr) -> Option<Vec<u8>> {
+    match address {
+        SocketAddr::V4(address) => {
+            let mut bytes = address.ip().octets().to_vec();
+            bytes.extend_from_slice(&address.port().to_be_bytes());
+            Some(bytes)
+        }
+        SocketAddr::V6(_) => None,
+    }
+}
+
+pub fn decode_address(bytes: &[u8]) -> Option<SocketAddr> {
+    match bytes {
+        [a, b, c, d, high, low] => Some(SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::new(*a, *b, *c, *d),
+            u16::from_be_bytes([*high, *low]),
+        ))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test1_round_trips_queries_errors_and_compact_nodes() {
+        let mut arguments = HashMap::new();
+        arguments.insert(b"id".to_vec(), Types::String(vec![0xff; ID_LENGTH]));
+
+        let encoded = Krpc::Query {
+            transaction_id: b"aa".to_vec(),
+            method: b"ping".to_vec(),
+            arguments,
+        }
+        .encode();
+
+        assert_eq!(
+            encoded,
+            [
+                b"d1:ad2:id20:".as_slice(),
+                &[0xff; ID_LENGTH],
+                b"e1:q4:ping1:t2:aa1:y1:qe"
+            ]
+            .concat()
+        );
+
+        match Krpc::decode(b"d1:eli201e5:Errore1:t2:aa1:y1:ee").unwrap() {
+            Krpc::Error {
+                transaction_id,
+                code,
+                message,
+            } => {
+                assert_eq!(transaction_id, b"aa".to_vec());
+                assert_eq!(code, 201);
+                assert_eq!(message, "Error");
+            }
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        let nodes = [Node {
+            id: NodeId([7; ID_LENGTH]),
+            address: SocketAddr::from(([10, 0, 0, 1], 6881)),
+            last_seen: Instant::now(),
+        }];
+
+        assert_eq!(
+            decode_nodes(&encode_nodes(&nodes)),
+            vec![(nodes[0].id, nodes[0].address)]
+        );
+        assert!(Krpc::decode(b"d1:t2:aa1:y1:qe").is_err());
+    }
+}
@@ -0,0 +1,11 @@
+pub use errors::DhtError;
+pub use index::Dht;
+pub use krpc::Krpc;
+pub use node_id::NodeId;
+pub use routing_table::{Node, RoutingTable, K};
+
+mod errors;
+mod index;
+mod krpc;
+mod node_id;
+mod routing_table;
@@ -0,0 +1,48 @@
+use rand::random;
+use std::fmt::{Debug, Error, Formatter};
+
+pub const ID_LENGTH: usize = 20;
+
+// Shared by DHT nodes and info hashes, compared by XOR distance.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub [u8; ID_LENGTH]);
+
+impl Debug for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl NodeId {
+    pub fn random() -> Self {
+        Self(random())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    pub fn distance(&self, other: &NodeId) -> NodeId {
+        let mut distance = [0; ID_LENGTH];
+
+        for (index, byte) in distance.iter_mut().enumerate() {
+            *byte = self.0[index] ^ other.0[index];
+        }
+
+        NodeId(distance)
+    }
+
+    // 160 when both are equal.
+    pub fn shared_prefix(&self, other: &NodeId) -> usize {
+        let distance = self.distance(other);
+
+        match distance.0.iter().position(|byte| *byte != 0) {
+            Some(index) => index * 8 + distance.0[index].leading_zeros() as usize,
+            None => ID_LENGTH * 8,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::node_id::ID_LENGTH;
+use super::NodeId;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+pub const K: usize = 8;
+
+// Nodes not heard from in this long may be replaced by new ones.
+const QUESTIONABLE_AFTER: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: NodeId,
+    pub address: SocketAddr,
+    pub last_seen: Instant,
+}
+
+// Full buckets only take new nodes in place of questionable ones, the nodes that keep
+// answering are never evicted.
+#[derive(Debug)]
+pub struct RoutingTable {
+    own_id: NodeId,
+    buckets: Vec<Vec<Node>>,
+}
+
+impl RoutingTable {
+    pub fn new(own_id: NodeId) -> Self {
+        Self {
+            own_id,
+            buckets: vec![Vec::new(); ID_LENGTH * 8],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+
+    // Returns whether the node is in the table.
+    pub fn insert(&mut self, id: NodeId, address: SocketAddr) -> bool {
+        if id == self.own_id {
+            return false;
+        }
+
+        let index = self.own_id.shared_prefix(&id).min(ID_LENGTH * 8 - 1);
+        let bucket = &mut self.buckets[index];
+
+        if let Some(node) = bucket.iter_mut().find(|node| node.id == id) {
+            node.address = address;
+            node.last_seen = Instant::now();
+            return true;
+        }
+
+        if bucket.len() >= K {
+            match bucket
+                .iter()
+                .position(|node| node.last_seen.elapsed() >= QUESTIONABLE_AFTER)
+            {
+                Some(questionable) => {
+                    bucket.remove(questionable);
+                }
+                None => return false,
+            }
+        }
+
+        bucket.push(Node {
+            id,
+            address,
+            last_seen: Instant::now(),
+        });
+
+        true
+    }
+
+    pub fn remove(&mut self, address: &SocketAddr) {
+        for bucket in &mut self.buckets {
+            bucket.retain(|node| node.address != *address);
+        }
+    }
+
+    // Closest to target first.
+    pub fn closest(&self, target: &NodeId, count: usize) -> Vec<Node> {
+        let mut nodes = self.nodes();
+
+        nodes.sort_by_key(|node| node.id.distance(target));
+        nodes.truncate(count);
+
+        nodes
+    }
+
+    pub fn nodes(&self) -> Vec<Node> {
+        self.buckets.iter().flatten().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(first: u8) -> NodeId {
+        let mut id = [0; ID_LENGTH];
+        id[0] = first;
+        NodeId(id)
+    }
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test1_full_buckets_keep_their_nodes_and_closest_sorts_by_distance() {
+        let mut table = RoutingTable::new(id(0));
+
+        // Every id starting with a 1 bit shares no prefix with ours, so they share a bucket.
+        for first in 0x80..0x80 + K as u8 {
+            assert!(table.insert(id(first), address(first as u16)));
+        }
+
+        assert!(!table.insert(id(0xff), address(1)));
+        assert!(table.insert(id(0x01), address(2)));
+        assert!(!table.insert(id(0), address(3)));
+        assert_eq!(table.len(), K + 1);
+
+        let closest = table.closest(&id(0x81), 2);
+
+        assert_eq!(closest[0].id, id(0x81));
+        assert_eq!(closest[1].id, id(0x80));
+
+        table.remove(&address(0x81));
+        assert_eq!(table.closest(&id(0x81), 1)[0].id, id(0x80));
+    }
+}
@@ -1,9 +1,93 @@
 pub struct Handshake {
-    pub address: String,
     pub id: String,
     pub info_hash: String,
     pub port: u16,
     pub left: u64,
     pub downloaded: u64,
+    pub uploaded: u64,
+    // Empty for regular announces.
     pub event: String,
+    pub key: u32,
+    // Already URL encoded.
+    pub tracker_id: Option<String>,
+    // Left out to let the tracker use the address the request came from.
+    pub ip: Option<String>,
+    // Sent whichever family we announce over.
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+}
+
+impl Handshake {
+    pub fn query(&self) -> String {
+        let mut query = format!(
+            "peer_id={}&numwant=100&compact=1&info_hash={}&port={}&uploaded={}&downloaded={}&left={}&key={:08X}",
+            self.id, self.info_hash, self.port, self.uploaded, self.downloaded, self.left, self.key,
+        );
+
+        if !self.event.is_empty() {
+            query.push_str(&format!("&event={}", self.event));
+        }
+
+        if let Some(tracker_id) = &self.tracker_id {
+            query.push_str(&format!("&trackerid={}", tracker_id));
+        }
+
+        if let Some(ip) = &self.ip {
+            query.push_str(&format!("&ip={}", ip));
+        }
+
+        if let Some(ipv4) = &self.ipv4 {
+            query.push_str(&format!("&ipv4={}", ipv4));
+        }
+
+        if let Some(ipv6) = &self.ipv6 {
+            query.push_str(&format!("&ipv6={}", ipv6.replace(':', "%3A")));
+        }
+
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_announce_query_reports_both_families() {
+        for (ipv4, ipv6, expected) in [
+            (None, None, "&key=0000002A"),
+            (Some("1.2.3.4"), None, "&key=0000002A&ipv4=1.2.3.4"),
+            (
+                None,
+                Some("2001:db8::1"),
+                "&key=0000002A&ipv6=2001%3Adb8%3A%3A1",
+            ),
+            (
+                Some("1.2.3.4"),
+                Some("::1"),
+                "&key=0000002A&ipv4=1.2.3.4&ipv6=%3A%3A1",
+            ),
+        ] {
+            let handshake = Handshake {
+                id: "id".to_string(),
+                info_hash: "hash".to_string(),
+                port: 6881,
+                left: 0,
+                downloaded: 0,
+                uploaded: 0,
+                event: String::new(),
+                key: 42,
+                tracker_id: None,
+                ip: None,
+                ipv4: ipv4.map(str::to_string),
+                ipv6: ipv6.map(str::to_string),
+            };
+
+            assert!(
+                handshake.query().ends_with(expected),
+                "{}",
+                handshake.query()
+            );
+        }
+    }
 }
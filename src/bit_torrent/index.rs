@@ -1,26 +1,45 @@
 use crate::frontend::peers::PeersData;
 
-use super::{Peer, TorrentData};
+use super::{BandwidthLimits, IpFilter, Peer, Session, TorrentData};
 
 use gtk::glib::Sender;
 use std::env;
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-
-#[derive(Default)]
+use std::time::Duration;
 
 pub struct BitTorrent {
     processes: Vec<JoinHandle<()>>,
+    session: Session,
+}
+
+impl Default for BitTorrent {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BitTorrent {
     pub fn new() -> Self {
         Self {
             processes: Vec::default(),
+            session: Session::new(),
         }
     }
 
+    pub fn bandwidth(&self) -> Arc<BandwidthLimits> {
+        Arc::clone(&self.session.bandwidth)
+    }
+
+    pub fn ip_filter(&self) -> Arc<IpFilter> {
+        Arc::clone(&self.session.ip_filter)
+    }
+
+    pub fn shutdown(&self, timeout: Duration) {
+        self.session.shutdown(timeout);
+    }
+
     pub fn new_process(
         &mut self,
         torrent_pathname: &str,
@@ -38,6 +57,7 @@ impl BitTorrent {
                 .as_str(),
             sender_torrent,
             sender_peers,
+            &self.session,
         );
 
         let handle = new_process.activate(remove_rx);
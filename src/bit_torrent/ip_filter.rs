@@ -0,0 +1,287 @@
+use crate::utils::env_setting;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const EMULE_BLOCK_LEVEL: u32 = 127;
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+// IPv4 addresses are kept as IPv4-mapped IPv6 ones, so both families share the ranges.
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    paths: Vec<PathBuf>,
+    // Disjoint ranges, keyed by their first address.
+    ranges: RwLock<BTreeMap<u128, u128>>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+    blocked: AtomicU64,
+}
+
+impl IpFilter {
+    pub fn new_from_env() -> Arc<Self> {
+        let paths = env_setting("IP_FILTER_PATH", String::new())
+            .split(';')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect();
+
+        Self::new_with(paths)
+    }
+
+    pub fn new_with(paths: Vec<PathBuf>) -> Arc<Self> {
+        let filter = Arc::new(Self {
+            paths,
+            ..Self::default()
+        });
+
+        if !filter.paths.is_empty() {
+            filter.reload();
+
+            let weak = Arc::downgrade(&filter);
+            thread::spawn(move || Self::watch(weak));
+        }
+
+        filter
+    }
+
+    // Returns how many ranges are blocked afterwards.
+    pub fn reload(&self) -> usize {
+        let mut ranges = vec![];
+        let mut modified = vec![];
+
+        for path in &self.paths {
+            modified.push(fs::metadata(path).and_then(|data| data.modified()).ok());
+
+            match fs::read(path) {
+                Ok(contents) => ranges.extend(parse(&String::from_utf8_lossy(&contents))),
+                Err(error) => log::error!("Failed to read IP filter {:?}: {}", path, error),
+            }
+        }
+
+        let ranges = merge(ranges);
+        let count = ranges.len();
+
+        *self.ranges.write().unwrap() = ranges;
+        *self.modified.lock().unwrap() = modified;
+
+        log::info!("Loaded IP filter with {} blocked ranges", count);
+
+        count
+    }
+
+    // Counts the attempt when ip is blocked.
+    pub fn block(&self, ip: IpAddr) -> bool {
+        let blocked = self.contains(ip);
+
+        if blocked {
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+        }
+
+        blocked
+    }
+
+    // Unlike block, not counted as a refused attempt.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = to_u128(ip);
+
+        matches!(
+            self.ranges.read().unwrap().range(..=ip).next_back(),
+            Some((_, last)) if ip <= *last
+        )
+    }
+
+    pub fn blocked(&self) -> u64 {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    fn watch(weak: Weak<Self>) {
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            let filter = match weak.upgrade() {
+                Some(filter) => filter,
+                None => break,
+            };
+
+            let modified: Vec<Option<SystemTime>> = filter
+                .paths
+                .iter()
+                .map(|path| fs::metadata(path).and_then(|data| data.modified()).ok())
+                .collect();
+
+            if modified != *filter.modified.lock().unwrap() {
+                filter.reload();
+            }
+        }
+    }
+}
+
+fn parse(list: &str) -> Vec<(u128, u128)> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+        .filter_map(|line| {
+            let range = parse_cidr(line)
+                .or_else(|| parse_emule(line))
+                .or_else(|| parse_p2p(line));
+
+            if range.is_none() {
+                log::debug!("parse() - skipping IP filter line {:?}", line);
+            }
+
+            range
+        })
+        .flatten()
+        .collect()
+}
+
+fn parse_cidr(line: &str) -> Option<Option<(u128, u128)>> {
+    let (address, prefix) = match line.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.trim().parse::<u32>().ok()?)),
+        None => (line, None),
+    };
+
+    let (address, bits) = match parse_ip(address)? {
+        ip @ IpAddr::V4(_) => (to_u128(ip), prefix.map(|prefix| prefix + 96)),
+        ip @ IpAddr::V6(_) => (to_u128(ip), prefix),
+    };
+
+    let host_mask = match bits.unwrap_or(128) {
+        0 => u128::MAX,
+        bits if bits <= 128 => u128::MAX >> bits,
+        _ => return None,
+    };
+
+    Some(Some((address & !host_mask, address | host_mask)))
+}
+
+// eMule levels below EMULE_BLOCK_LEVEL are blocked.
+fn parse_emule(line: &str) -> Option<Option<(u128, u128)>> {
+    let mut fields = line.split(',');
+    let range = parse_range(fields.next()?)?;
+
+    let level = match fields.next() {
+        Some(level) => level.trim().parse::<u32>().ok()?,
+        None => 0,
+    };
+
+    Some((level < EMULE_BLOCK_LEVEL).then_some(range))
+}
+
+// The description may hold colons itself.
+fn parse_p2p(line: &str) -> Option<Option<(u128, u128)>> {
+    let (_, range) = line.rsplit_once(':')?;
+
+    parse_range(range).map(Some)
+}
+
+fn parse_range(range: &str) -> Option<(u128, u128)> {
+    let (first, last) = range.split_once('-')?;
+    let (first, last) = (to_u128(parse_ip(first)?), to_u128(parse_ip(last)?));
+
+    (first <= last).then_some((first, last))
+}
+
+// eMule lists zero pad the IPv4 octets.
+fn parse_ip(ip: &str) -> Option<IpAddr> {
+    let ip = ip.trim();
+
+    if ip.contains(':') {
+        return ip.parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+
+    let octets: Vec<u8> = ip
+        .split('.')
+        .map(|octet| octet.parse::<u8>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let octets: [u8; 4] = octets.try_into().ok()?;
+
+    Some(IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn merge(mut ranges: Vec<(u128, u128)>) -> BTreeMap<u128, u128> {
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u128, u128)> = vec![];
+
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some((_, previous_last)) if first <= previous_last.saturating_add(1) => {
+                *previous_last = (*previous_last).max(last)
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+
+    merged.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test1_blocks_ranges_from_every_format() {
+        let path = env::temp_dir().join(format!("ip_filter_test_{}", std::process::id()));
+
+        fs::write(
+            &path,
+            "# CIDR\n\
+             10.0.0.0/8\n\
+             2001:db8::/32\n\
+             001.002.003.000 - 001.002.003.255 , 000 , eMule range\n\
+             004.000.000.000 - 004.255.255.255 , 200 , allowed eMule range\n\
+             Some: company:192.168.1.10-192.168.1.20\n\
+             192.168.1.21-192.168.1.30\n\
+             not a range\n",
+        )
+        .unwrap();
+
+        let filter = IpFilter::new_with(vec![path.clone()]);
+        fs::remove_file(&path).unwrap();
+
+        let blocked = [
+            "10.200.0.1",
+            "2001:db8::1",
+            "::ffff:10.0.0.1",
+            "1.2.3.4",
+            "192.168.1.10",
+            "192.168.1.30",
+        ];
+        let allowed = ["11.0.0.1", "4.4.4.4", "192.168.1.31", "2001:db9::1"];
+
+        for ip in blocked {
+            assert!(
+                filter.block(ip.parse().unwrap()),
+                "{} should be blocked",
+                ip
+            );
+        }
+
+        for ip in allowed {
+            assert!(
+                !filter.block(ip.parse().unwrap()),
+                "{} should be allowed",
+                ip
+            );
+        }
+
+        assert_eq!(filter.ranges.read().unwrap().len(), 4);
+        assert_eq!(filter.blocked(), blocked.len() as u64);
+    }
+}
@@ -0,0 +1,337 @@
+use super::{
+    networking::utils::get_available_port, ConnectionLimits, ConnectionSlot, IpFilter, Message,
+};
+use crate::utils::env_setting;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_LISTEN_PORT: u16 = 6881;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type IncomingHandler = dyn Fn(TcpStream, ConnectionSlot, Vec<u8>) + Send + Sync;
+
+// Hands each connection to the torrent whose info hash its handshake asks for. Listening on an
+// unspecified address accepts both IPv4 and IPv6 peers.
+pub struct Listener {
+    // One dual-stack socket, or one per family where the system does not allow them.
+    sockets: Vec<TcpListener>,
+    connection_limits: Arc<ConnectionLimits>,
+    ip_filter: Arc<IpFilter>,
+    torrents: Mutex<HashMap<Vec<u8>, Arc<IncomingHandler>>>,
+}
+
+impl std::fmt::Debug for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Listener")
+            .field("addresses", &self.local_addrs())
+            .finish()
+    }
+}
+
+// Keeps a torrent reachable through the listener until dropped.
+pub struct ListenerRegistration {
+    listener: Arc<Listener>,
+    info_hash: Vec<u8>,
+}
+
+impl Drop for ListenerRegistration {
+    fn drop(&mut self) {
+        self.listener
+            .torrents
+            .lock()
+            .unwrap()
+            .remove(&self.info_hash);
+    }
+}
+
+impl Listener {
+    pub fn new_from_env(
+        interface: IpAddr,
+        connection_limits: Arc<ConnectionLimits>,
+        ip_filter: Arc<IpFilter>,
+    ) -> Option<Arc<Self>> {
+        let port = env_setting("LISTEN_PORT", DEFAULT_LISTEN_PORT);
+
+        let listener = Self::bind(
+            SocketAddr::new(interface, port),
+            Arc::clone(&connection_limits),
+            Arc::clone(&ip_filter),
+        )
+        .or_else(|error| {
+            log::warn!("Failed to listen on port {}: {}", port, error);
+
+            let port = get_available_port(interface)
+                .ok_or_else(|| Error::new(ErrorKind::AddrInUse, "No free port to listen on"))?;
+
+            Self::bind(
+                SocketAddr::new(interface, port),
+                connection_limits,
+                ip_filter,
+            )
+        });
+
+        match listener {
+            Ok(listener) => {
+                log::info!("Listening for peers on {:?}", listener.local_addrs());
+                Some(listener)
+            }
+            Err(error) => {
+                log::error!("Failed to listen for peers: {}", error);
+                None
+            }
+        }
+    }
+
+    pub fn bind(
+        address: SocketAddr,
+        connection_limits: Arc<ConnectionLimits>,
+        ip_filter: Arc<IpFilter>,
+    ) -> Result<Arc<Self>, Error> {
+        let sockets = match address.ip().is_unspecified() {
+            true => Self::bind_dual_stack(address.port())?,
+            false => vec![TcpListener::bind(address)?],
+        };
+
+        for socket in &sockets {
+            socket.set_nonblocking(true)?;
+        }
+
+        let listener = Arc::new(Self {
+            sockets,
+            connection_limits,
+            ip_filter,
+            torrents: Mutex::new(HashMap::new()),
+        });
+
+        let weak = Arc::downgrade(&listener);
+        thread::spawn(move || Self::run(weak));
+
+        Ok(listener)
+    }
+
+    // Most systems accept IPv4 peers on the IPv6 socket too, in which case binding IPv4 on its
+    // own fails and is not needed.
+    fn bind_dual_stack(port: u16) -> Result<Vec<TcpListener>, Error> {
+        let ipv6 = match TcpListener::bind((Ipv6Addr::UNSPECIFIED, port)) {
+            Ok(socket) => socket,
+            Err(error) => {
+                log::debug!("Listener::bind_dual_stack() - no IPv6: {}", error);
+                return Ok(vec![TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?]);
+            }
+        };
+
+        let port = ipv6.local_addr()?.port();
+
+        match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)) {
+            Ok(ipv4) => Ok(vec![ipv6, ipv4]),
+            Err(error) if error.kind() == ErrorKind::AddrInUse => Ok(vec![ipv6]),
+            Err(error) => {
+                log::debug!("Listener::bind_dual_stack() - no IPv4: {}", error);
+                Ok(vec![ipv6])
+            }
+        }
+    }
+
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.sockets
+            .iter()
+            .filter_map(|socket| socket.local_addr().ok())
+            .collect()
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addrs().first().copied()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.local_addr().map_or(0, |address| address.port())
+    }
+
+    pub fn register(
+        self: &Arc<Self>,
+        info_hash: &[u8],
+        handler: Arc<IncomingHandler>,
+    ) -> ListenerRegistration {
+        self.torrents
+            .lock()
+            .unwrap()
+            .insert(info_hash.to_vec(), handler);
+
+        ListenerRegistration {
+            listener: Arc::clone(self),
+            info_hash: info_hash.to_vec(),
+        }
+    }
+
+    fn run(weak: Weak<Self>) {
+        loop {
+            let listener = match weak.upgrade() {
+                Some(listener) => listener,
+                None => break,
+            };
+
+            let mut accepted = false;
+
+            for socket in &listener.sockets {
+                match socket.accept() {
+                    Ok((stream, address)) => {
+                        accepted = true;
+                        listener.accept(stream, address);
+                    }
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                    Err(error) => log::debug!("Listener::run() - {}", error),
+                }
+            }
+
+            if !accepted {
+                drop(listener);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn accept(self: &Arc<Self>, stream: TcpStream, address: SocketAddr) {
+        // IPv4 peers reach the dual-stack socket as IPv4-mapped IPv6 addresses.
+        let address = SocketAddr::new(address.ip().to_canonical(), address.port());
+
+        if self.ip_filter.block(address.ip()) {
+            log::debug!("Listener::accept() - rejecting blocked peer {}", address);
+            return;
+        }
+
+        match self.connection_limits.try_accept() {
+            Some(slot) => {
+                let listener = Arc::clone(self);
+                thread::spawn(move || listener.route(stream, slot, address));
+            }
+            None => log::debug!("Listener::accept() - connection limit reached, rejecting peer"),
+        }
+    }
+
+    fn route(&self, mut stream: TcpStream, slot: ConnectionSlot, address: SocketAddr) {
+        if stream.set_nonblocking(false).is_err()
+            || stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).is_err()
+        {
+            return;
+        }
+
+        let (info_hash, peer_id) = match Message::read_handshake_from_stream(&mut stream) {
+            Ok(Message::Handshake(info_hash, peer_id)) => (info_hash, peer_id),
+            _ => return,
+        };
+
+        let handler = match self.torrents.lock().unwrap().get(&info_hash) {
+            Some(handler) => Arc::clone(handler),
+            None => {
+                log::debug!(
+                    "Listener::route() - {} asked for an unknown torrent",
+                    address
+                );
+                return;
+            }
+        };
+
+        if stream.set_read_timeout(None).is_ok() {
+            handler(stream, slot, peer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::BitTorrent;
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test1_connections_are_routed_by_info_hash() {
+        let listener = Listener::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            Arc::new(ConnectionLimits::new_with_limits(10, 10, 10)),
+            Arc::new(IpFilter::default()),
+        )
+        .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+
+        let _registration = listener.register(
+            &[1; 20],
+            Arc::new(
+                move |_stream: TcpStream, _slot: ConnectionSlot, peer_id: Vec<u8>| {
+                    sender.lock().unwrap().send(peer_id).unwrap();
+                },
+            ),
+        );
+
+        let mut unknown = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        unknown
+            .write_all(&BitTorrent::format_handshake_message(&[2; 20], &[3; 20]))
+            .unwrap();
+        unknown
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(unknown.read(&mut [0; 1]).unwrap(), 0);
+
+        let mut known = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        known
+            .write_all(&BitTorrent::format_handshake_message(&[1; 20], &[4; 20]))
+            .unwrap();
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            vec![4; 20]
+        );
+    }
+
+    #[test]
+    fn test2_unspecified_address_accepts_both_families() {
+        let listener = Listener::bind(
+            "0.0.0.0:0".parse().unwrap(),
+            Arc::new(ConnectionLimits::new_with_limits(10, 10, 10)),
+            Arc::new(IpFilter::default()),
+        )
+        .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+
+        let _registration = listener.register(
+            &[1; 20],
+            Arc::new(
+                move |stream: TcpStream, _slot: ConnectionSlot, _: Vec<u8>| {
+                    let ip = stream.peer_addr().unwrap().ip().to_canonical();
+                    sender.lock().unwrap().send(ip).unwrap();
+                },
+            ),
+        );
+
+        let mut loopbacks = vec![IpAddr::from(Ipv4Addr::LOCALHOST)];
+
+        if listener
+            .local_addrs()
+            .iter()
+            .any(|address| address.is_ipv6())
+        {
+            loopbacks.push(IpAddr::from(Ipv6Addr::LOCALHOST));
+        }
+
+        for loopback in loopbacks {
+            let mut stream = TcpStream::connect((loopback, listener.port())).unwrap();
+            stream
+                .write_all(&BitTorrent::format_handshake_message(&[1; 20], &[4; 20]))
+                .unwrap();
+
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+                loopback
+            );
+        }
+    }
+}
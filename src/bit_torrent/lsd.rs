@@ -0,0 +1,269 @@
+use super::{PeerList, PeerRecord};
+use crate::utils::{decode_hex, encode_hex, env_setting};
+use rand::random;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MULTICAST_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 192, 152, 143);
+const LSD_PORT: u16 = 6771;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// BEP 14 asks for at most one announce per torrent and minute.
+const MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_PACKET_SIZE: usize = 1500;
+
+struct LocalTorrent {
+    port: u16,
+    peers: Arc<Mutex<PeerList>>,
+    total_pieces: usize,
+    last_announce: Option<Instant>,
+}
+
+pub struct LocalDiscovery {
+    socket: UdpSocket,
+    target: SocketAddr,
+    cookie: String,
+    torrents: Mutex<HashMap<Vec<u8>, LocalTorrent>>,
+}
+
+impl std::fmt::Debug for LocalDiscovery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalDiscovery")
+            .field("target", &self.target)
+            .field("cookie", &self.cookie)
+            .finish()
+    }
+}
+
+// Keeps a torrent announced on the LAN until dropped.
+pub struct LsdRegistration {
+    lsd: Arc<LocalDiscovery>,
+    info_hash: Vec<u8>,
+}
+
+impl Drop for LsdRegistration {
+    fn drop(&mut self) {
+        self.lsd.torrents.lock().unwrap().remove(&self.info_hash);
+    }
+}
+
+impl LocalDiscovery {
+    pub fn new_from_env() -> Option<Arc<Self>> {
+        if !env_setting("LSD_ENABLED", false) {
+            return None;
+        }
+
+        match Self::bind(
+            &format!("0.0.0.0:{}", LSD_PORT),
+            SocketAddr::from((MULTICAST_ADDRESS, LSD_PORT)),
+        ) {
+            Ok(lsd) => Some(lsd),
+            Err(error) => {
+                log::error!("Failed to start local service discovery: {}", error);
+                None
+            }
+        }
+    }
+
+    // Joins the group of target when it is a multicast address.
+    pub fn bind(address: &str, target: SocketAddr) -> Result<Arc<Self>, Error> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+
+        if let IpAddr::V4(group) = target.ip() {
+            if group.is_multicast() {
+                socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+                socket.set_multicast_loop_v4(true)?;
+            }
+        }
+
+        let lsd = Arc::new(Self {
+            socket,
+            target,
+            cookie: format!("{:08x}", random::<u32>()),
+            torrents: Mutex::new(HashMap::new()),
+        });
+
+        let weak = Arc::downgrade(&lsd);
+        thread::spawn(move || Self::run(weak));
+
+        Ok(lsd)
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()
+    }
+
+    pub fn register(
+        self: &Arc<Self>,
+        info_hash: &[u8],
+        port: u16,
+        peers: Arc<Mutex<PeerList>>,
+        total_pieces: usize,
+    ) -> LsdRegistration {
+        self.torrents.lock().unwrap().insert(
+            info_hash.to_vec(),
+            LocalTorrent {
+                port,
+                peers,
+                total_pieces,
+                last_announce: None,
+            },
+        );
+
+        LsdRegistration {
+            lsd: Arc::clone(self),
+            info_hash: info_hash.to_vec(),
+        }
+    }
+
+    fn run(weak: Weak<Self>) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        loop {
+            let lsd = match weak.upgrade() {
+                Some(lsd) => lsd,
+                None => break,
+            };
+
+            lsd.announce_due();
+
+            match lsd.socket.recv_from(&mut buffer) {
+                Ok((length, from)) => lsd.handle(&buffer[..length], from),
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(error) => log::debug!("LocalDiscovery::run() - {}", error),
+            }
+        }
+    }
+
+    fn announce_due(&self) {
+        let mut torrents = self.torrents.lock().unwrap();
+
+        for (info_hash, torrent) in torrents.iter_mut() {
+            if torrent
+                .last_announce
+                .is_some_and(|last_announce| last_announce.elapsed() < ANNOUNCE_INTERVAL)
+            {
+                continue;
+            }
+
+            let message = search_message(&self.target, torrent.port, info_hash, &self.cookie);
+
+            match self.socket.send_to(message.as_bytes(), self.target) {
+                Ok(_) => torrent.last_announce = Some(Instant::now()),
+                Err(error) => {
+                    log::warn!("Failed to send LSD announce: {}", error);
+                    // Try again once the minimum interval is over.
+                    torrent.last_announce =
+                        Instant::now().checked_sub(ANNOUNCE_INTERVAL - MIN_ANNOUNCE_INTERVAL);
+                }
+            }
+        }
+    }
+
+    fn handle(&self, bytes: &[u8], from: SocketAddr) {
+        let (port, info_hashes, cookie) = match parse_search(bytes) {
+            Some(search) => search,
+            None => return,
+        };
+
+        if cookie.as_deref() == Some(self.cookie.as_str()) {
+            return;
+        }
+
+        let torrents = self.torrents.lock().unwrap();
+
+        for info_hash in info_hashes {
+            if let Some(torrent) = torrents.get(&info_hash) {
+                log::info!("Found local peer {}:{}", from.ip(), port);
+
+                let mut peer = PeerRecord::new_from_address(
+                    SocketAddr::new(from.ip(), port),
+                    torrent.total_pieces,
+                );
+                peer.local = true;
+
+                torrent.peers.lock().unwrap().update(vec![peer]);
+            }
+        }
+    }
+}
+
+fn search_message(target: &SocketAddr, port: u16, info_hash: &[u8], cookie: &str) -> String {
+    format!(
+        "BT-SEARCH * HTTP/1.1\r\nHost: {}\r\nPort: {}\r\nInfohash: {}\r\ncookie: {}\r\n\r\n\r\n",
+        target,
+        port,
+        encode_hex(info_hash),
+        cookie
+    )
+}
+
+fn parse_search(bytes: &[u8]) -> Option<(u16, Vec<Vec<u8>>, Option<String>)> {
+    let message = std::str::from_utf8(bytes).ok()?;
+    let mut lines = message.split("\r\n");
+
+    if lines.next()? != "BT-SEARCH * HTTP/1.1" {
+        return None;
+    }
+
+    let mut port = None;
+    let mut info_hashes = vec![];
+    let mut cookie = None;
+
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        match name.as_str() {
+            "port" => port = value.parse::<u16>().ok(),
+            "infohash" if value.len() == 40 => info_hashes.extend(decode_hex(value)),
+            "cookie" => cookie = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    Some((port?, info_hashes, cookie))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_lan_peers_are_added_to_the_peer_list() {
+        let first = LocalDiscovery::bind("127.0.0.1:0", "127.0.0.1:9".parse().unwrap()).unwrap();
+        let second = LocalDiscovery::bind("127.0.0.1:0", first.local_addr().unwrap()).unwrap();
+
+        let info_hash = [0xab; 20];
+        let peers = Arc::new(Mutex::new(PeerList::new()));
+
+        let _first_registration = first.register(&info_hash, 7000, Arc::clone(&peers), 1);
+        let _second_registration =
+            second.register(&info_hash, 7001, Arc::new(Mutex::new(PeerList::new())), 1);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while peers.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let peer = peers.lock().unwrap().pop().unwrap();
+
+        assert_eq!(peer.get_address(), "127.0.0.1:7001");
+        assert!(peer.local);
+
+        let message = search_message(&first.target, 7001, &info_hash, &second.cookie);
+        assert_eq!(
+            parse_search(message.as_bytes()),
+            Some((7001, vec![info_hash.to_vec()], Some(second.cookie.clone())))
+        );
+    }
+}
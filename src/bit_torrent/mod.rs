@@ -1,30 +1,48 @@
 pub use super::*;
 pub use super::{
     bencoder::{common::Types, Decoder},
-    file_system::File,
+    file_system::{DiskIo, File, Storage, StorageKind, TorrentDisk},
     frontend::views::torrents::TorrentData,
     networking::{
-        BitTorrent as BTProtocol, HTTPSTracker, HTTPTracker, InterfaceProtocol, Message,
-        NetworkingError, Protocol,
+        BitTorrent as BTProtocol, HTTPSTracker, HTTPTracker, HttpClient, HttpError, HttpResponse,
+        InterfaceProtocol, Message, NetworkingError, Protocol, UDPAnnounce, UDPTracker, Url,
     },
     torrent_file::*,
     urlencoder::encode::UrlEncoder,
     utils,
 };
+pub use addresses::Addresses;
+pub use bandwidth::{BandwidthLimits, BandwidthScheduler, Direction, Schedule, TorrentBandwidth};
 pub use bitfield::Bitfield;
+pub use connection_limits::{ConnectionLimits, ConnectionSlot};
 pub use constants::*;
+pub use dht::Dht;
 pub use handshake::Handshake;
 pub use index::BitTorrent;
-pub use peer::{CommonInformation, Peer, PeerConnection, PeerList, State};
+pub use ip_filter::IpFilter;
+pub use listener::{IncomingHandler, Listener, ListenerRegistration};
+pub use lsd::{LocalDiscovery, LsdRegistration};
+pub use nat::PortMapper;
+pub use peer::{CommonInformation, Peer, PeerConnection, PeerList, ScrapeResponse, State};
 pub use peer_record::PeerRecord;
 pub use piece::Piece;
+pub use session::Session;
 pub use tracker::Tracker;
 
+mod addresses;
+mod bandwidth;
 mod bitfield;
+mod connection_limits;
 mod constants;
+mod dht;
 pub mod handshake;
 mod index;
+mod ip_filter;
+mod listener;
+mod lsd;
+mod nat;
 mod peer;
 mod peer_record;
 mod piece;
+mod session;
 mod tracker;
@@ -0,0 +1,12 @@
+#[derive(Debug)]
+
+pub enum NatError {
+    NoGateway,
+    FailedToSend,
+    NoResponse,
+    InvalidResponse,
+    UnsupportedVersion,
+    // With the gateway's result or UPnP error code.
+    Refused(u16),
+    FailedRequest,
+}
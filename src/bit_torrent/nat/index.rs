@@ -0,0 +1,239 @@
+use super::nat_pmp::NAT_PMP_PORT;
+use super::{NatError, NatPmp, Upnp};
+use crate::bit_torrent::Addresses;
+use crate::utils::{env_setting, optional_env_setting};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Lifetime asked for, mappings are renewed halfway through.
+const MAPPING_LIFETIME: u32 = 2 * 60 * 60;
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+enum Gateway {
+    Upnp(Upnp),
+    NatPmp(NatPmp),
+}
+
+#[derive(Debug)]
+struct Mapping {
+    gateway: Gateway,
+    external_port: u16,
+}
+
+#[derive(Debug)]
+pub struct PortMapper {
+    internal_port: u16,
+    addresses: Arc<Addresses>,
+    nat_pmp_gateway: Option<SocketAddr>,
+    // Only held to swap the mapping, never while talking to the gateway.
+    mapping: Mutex<Option<Mapping>>,
+    next_attempt: Mutex<Instant>,
+    // Set on shutdown, so that a mapping made meanwhile is removed rather than kept.
+    removed: AtomicBool,
+}
+
+impl Drop for PortMapper {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+impl PortMapper {
+    pub fn new_from_env(internal_port: u16, addresses: Arc<Addresses>) -> Option<Arc<Self>> {
+        if !env_setting("NAT_MAPPING", false) {
+            return None;
+        }
+
+        let nat_pmp_gateway = optional_env_setting::<IpAddr>("NAT_GATEWAY")
+            .or_else(NatPmp::default_gateway)
+            .map(|gateway| SocketAddr::new(gateway, NAT_PMP_PORT));
+
+        Some(Self::start(internal_port, addresses, nat_pmp_gateway))
+    }
+
+    // Tries UPnP first and then the NAT-PMP/PCP gateway, if any.
+    pub fn start(
+        internal_port: u16,
+        addresses: Arc<Addresses>,
+        nat_pmp_gateway: Option<SocketAddr>,
+    ) -> Arc<Self> {
+        let mapper = Arc::new(Self {
+            internal_port,
+            addresses,
+            nat_pmp_gateway,
+            mapping: Mutex::new(None),
+            next_attempt: Mutex::new(Instant::now()),
+            removed: AtomicBool::new(false),
+        });
+
+        let weak = Arc::downgrade(&mapper);
+        thread::spawn(move || Self::run(weak));
+
+        mapper
+    }
+
+    pub fn remove(&self) {
+        self.removed.store(true, Ordering::SeqCst);
+
+        let mapping = match self.mapping.lock().unwrap().take() {
+            Some(mapping) => mapping,
+            None => return,
+        };
+
+        self.addresses.set_mapped_port(None);
+        self.unmap(&mapping);
+    }
+
+    fn unmap(&self, mapping: &Mapping) {
+        let removed = match &mapping.gateway {
+            Gateway::Upnp(upnp) => upnp.delete_port_mapping(mapping.external_port),
+            Gateway::NatPmp(nat_pmp) => nat_pmp.map(self.internal_port, 0, 0).map(|_| ()),
+        };
+
+        match removed {
+            Ok(()) => log::info!("Removed port mapping for {}", mapping.external_port),
+            Err(error) => log::warn!("Failed to remove port mapping: {:?}", error),
+        }
+    }
+
+    fn run(weak: Weak<Self>) {
+        loop {
+            let mapper = match weak.upgrade() {
+                Some(mapper) => mapper,
+                None => break,
+            };
+
+            if mapper.removed.load(Ordering::SeqCst) {
+                break;
+            }
+
+            if Instant::now() >= *mapper.next_attempt.lock().unwrap() {
+                mapper.refresh();
+            }
+
+            drop(mapper);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn refresh(&self) {
+        let current = self.mapping.lock().unwrap().take();
+
+        let renewed = match current {
+            Some(mapping) => self.renew(mapping),
+            None => Err(NatError::NoGateway),
+        };
+
+        let result = renewed.or_else(|_| self.create());
+
+        let next_attempt = match result {
+            Ok((mapping, lifetime)) => {
+                let mut mapping_guard = self.mapping.lock().unwrap();
+
+                if self.removed.load(Ordering::SeqCst) {
+                    drop(mapping_guard);
+                    self.unmap(&mapping);
+                    return;
+                }
+
+                self.addresses.set_mapped_port(Some(mapping.external_port));
+                *mapping_guard = Some(mapping);
+
+                match lifetime {
+                    // Permanent mappings are still checked, in case the gateway restarted.
+                    0 => Duration::from_secs(MAPPING_LIFETIME as u64 / 2),
+                    lifetime => Duration::from_secs(lifetime as u64 / 2),
+                }
+            }
+            Err(error) => {
+                log::warn!("Failed to map port {}: {:?}", self.internal_port, error);
+                self.addresses.set_mapped_port(None);
+                RETRY_INTERVAL
+            }
+        };
+
+        *self.next_attempt.lock().unwrap() = Instant::now() + next_attempt;
+    }
+
+    fn renew(&self, mapping: Mapping) -> Result<(Mapping, u32), NatError> {
+        let lifetime = match &mapping.gateway {
+            Gateway::Upnp(upnp) => {
+                upnp.add_port_mapping(mapping.external_port, self.internal_port, MAPPING_LIFETIME)?
+            }
+            Gateway::NatPmp(nat_pmp) => {
+                let (external_port, lifetime) =
+                    nat_pmp.map(self.internal_port, mapping.external_port, MAPPING_LIFETIME)?;
+
+                if external_port != mapping.external_port {
+                    return Ok((
+                        Mapping {
+                            external_port,
+                            ..mapping
+                        },
+                        lifetime,
+                    ));
+                }
+
+                lifetime
+            }
+        };
+
+        Ok((mapping, lifetime))
+    }
+
+    fn create(&self) -> Result<(Mapping, u32), NatError> {
+        let upnp_error = match Upnp::discover().and_then(|upnp| {
+            let lifetime =
+                upnp.add_port_mapping(self.internal_port, self.internal_port, MAPPING_LIFETIME)?;
+            Ok((upnp, lifetime))
+        }) {
+            Ok((upnp, lifetime)) => {
+                match upnp.external_ip() {
+                    Ok(external_ip) => log::info!(
+                        "Mapped port {} through UPnP, reachable at {}",
+                        self.internal_port,
+                        external_ip
+                    ),
+                    Err(_) => log::info!("Mapped port {} through UPnP", self.internal_port),
+                }
+
+                return Ok((
+                    Mapping {
+                        gateway: Gateway::Upnp(upnp),
+                        external_port: self.internal_port,
+                    },
+                    lifetime,
+                ));
+            }
+            Err(error) => error,
+        };
+
+        let gateway = match self.nat_pmp_gateway {
+            Some(gateway) => gateway,
+            None => return Err(upnp_error),
+        };
+
+        let nat_pmp = NatPmp::new(gateway)?;
+        let (external_port, lifetime) =
+            nat_pmp.map(self.internal_port, self.internal_port, MAPPING_LIFETIME)?;
+
+        log::info!(
+            "Mapped port {} to {} through NAT-PMP/PCP",
+            self.internal_port,
+            external_port
+        );
+
+        Ok((
+            Mapping {
+                gateway: Gateway::NatPmp(nat_pmp),
+                external_port,
+            },
+            lifetime,
+        ))
+    }
+}
@@ -0,0 +1,9 @@
+pub use errors::NatError;
+pub use index::PortMapper;
+pub use nat_pmp::NatPmp;
+pub use upnp::Upnp;
+
+mod errors;
+mod index;
+mod nat_pmp;
+mod upnp;
@@ -0,0 +1,256 @@
+use super::NatError;
+use rand::random;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_VERSION: u8 = 0;
+const PCP_VERSION: u8 = 2;
+// NAT-PMP opcode mapping a TCP port.
+const MAP_TCP: u8 = 2;
+// PCP opcode creating a mapping.
+const MAP: u8 = 1;
+const RESPONSE: u8 = 0x80;
+const TCP: u8 = 6;
+const UNSUPPORTED_VERSION: u16 = 1;
+const NAT_PMP_RESPONSE_LENGTH: usize = 16;
+const PCP_RESPONSE_LENGTH: usize = 60;
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const ATTEMPTS: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Version {
+    Pcp,
+    NatPmp,
+}
+
+// PCP (RFC 6887), falling back to NAT-PMP (RFC 6886) for gateways that only speak the older
+// protocol.
+#[derive(Debug)]
+pub struct NatPmp {
+    socket: UdpSocket,
+    local_ip: Ipv4Addr,
+    nonce: [u8; 12],
+    version: Mutex<Option<Version>>,
+}
+
+impl NatPmp {
+    pub fn new(gateway: SocketAddr) -> Result<Self, NatError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").or(Err(NatError::FailedToSend))?;
+        socket.connect(gateway).or(Err(NatError::NoGateway))?;
+
+        let local_ip = match socket.local_addr() {
+            Ok(SocketAddr::V4(address)) => *address.ip(),
+            _ => return Err(NatError::NoGateway),
+        };
+
+        Ok(Self {
+            socket,
+            local_ip,
+            nonce: random(),
+            version: Mutex::new(None),
+        })
+    }
+
+    // As listed in /proc/net/route.
+    pub fn default_gateway() -> Option<IpAddr> {
+        let routes = fs::read_to_string("/proc/net/route").ok()?;
+
+        routes.lines().skip(1).find_map(|route| {
+            let fields: Vec<&str> = route.split_whitespace().collect();
+
+            match fields.as_slice() {
+                [_, "00000000", gateway, ..] => {
+                    let gateway = u32::from_str_radix(gateway, 16).ok()?;
+                    Some(IpAddr::V4(Ipv4Addr::from(gateway.to_le_bytes())))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    // Returns the external port and lifetime granted. A zero lifetime removes the mapping.
+    pub fn map(
+        &self,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, u32), NatError> {
+        let version = *self.version.lock().unwrap();
+
+        match version {
+            Some(Version::Pcp) => self.map_pcp(internal_port, external_port, lifetime),
+            Some(Version::NatPmp) => self.map_nat_pmp(internal_port, external_port, lifetime),
+            None => {
+                let (mapping, version) = match self.map_pcp(internal_port, external_port, lifetime)
+                {
+                    Err(NatError::UnsupportedVersion | NatError::NoResponse) => (
+                        self.map_nat_pmp(internal_port, external_port, lifetime)?,
+                        Version::NatPmp,
+                    ),
+                    mapping => (mapping?, Version::Pcp),
+                };
+
+                *self.version.lock().unwrap() = Some(version);
+                Ok(mapping)
+            }
+        }
+    }
+
+    fn map_pcp(
+        &self,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, u32), NatError> {
+        let mut request = vec![PCP_VERSION, MAP, 0, 0];
+        request.extend_from_slice(&lifetime.to_be_bytes());
+        request.extend_from_slice(&self.local_ip.to_ipv6_mapped().octets());
+        request.extend_from_slice(&self.nonce);
+        request.extend_from_slice(&[TCP, 0, 0, 0]);
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&external_port.to_be_bytes());
+        request.extend_from_slice(&Ipv4Addr::UNSPECIFIED.to_ipv6_mapped().octets());
+
+        let response = self.transaction(&request)?;
+
+        match response.first() {
+            Some(&NAT_PMP_VERSION) => return Err(NatError::UnsupportedVersion),
+            Some(&PCP_VERSION) if response.len() >= 4 => {}
+            _ => return Err(NatError::InvalidResponse),
+        }
+
+        match response[3] as u16 {
+            0 => {}
+            UNSUPPORTED_VERSION => return Err(NatError::UnsupportedVersion),
+            result => return Err(NatError::Refused(result)),
+        }
+
+        if response.len() < PCP_RESPONSE_LENGTH
+            || response[1] != RESPONSE | MAP
+            || response[24..36] != self.nonce
+        {
+            return Err(NatError::InvalidResponse);
+        }
+
+        Ok((read_u16(&response, 42), read_u32(&response, 4)))
+    }
+
+    fn map_nat_pmp(
+        &self,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, u32), NatError> {
+        let mut request = vec![NAT_PMP_VERSION, MAP_TCP, 0, 0];
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&external_port.to_be_bytes());
+        request.extend_from_slice(&lifetime.to_be_bytes());
+
+        let response = self.transaction(&request)?;
+
+        if response.len() < NAT_PMP_RESPONSE_LENGTH
+            || response[0] != NAT_PMP_VERSION
+            || response[1] != RESPONSE | MAP_TCP
+        {
+            return Err(NatError::InvalidResponse);
+        }
+
+        match read_u16(&response, 2) {
+            0 => Ok((read_u16(&response, 10), read_u32(&response, 12))),
+            UNSUPPORTED_VERSION => Err(NatError::UnsupportedVersion),
+            result => Err(NatError::Refused(result)),
+        }
+    }
+
+    // Doubles the timeout after each attempt.
+    fn transaction(&self, request: &[u8]) -> Result<Vec<u8>, NatError> {
+        let mut buffer = [0; 1100];
+
+        for attempt in 0..ATTEMPTS {
+            self.socket
+                .set_read_timeout(Some(INITIAL_TIMEOUT * 2_u32.pow(attempt)))
+                .or(Err(NatError::FailedToSend))?;
+            self.socket.send(request).or(Err(NatError::FailedToSend))?;
+
+            if let Ok(length) = self.socket.recv(&mut buffer) {
+                return Ok(buffer[..length].to_vec());
+            }
+        }
+
+        Err(NatError::NoResponse)
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test1_falls_back_to_nat_pmp_when_pcp_is_unsupported() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = gateway.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut buffer = [0; 1100];
+            let mut requests = vec![];
+
+            for _ in 0..3 {
+                let (length, from) = gateway.recv_from(&mut buffer).unwrap();
+                let request = buffer[..length].to_vec();
+
+                let response = match request[0] {
+                    // Older gateways answer PCP with their own version and an error.
+                    PCP_VERSION => vec![NAT_PMP_VERSION, RESPONSE | MAP, 0, 1, 0, 0, 0, 0],
+                    _ => {
+                        let mut response = vec![NAT_PMP_VERSION, RESPONSE | MAP_TCP, 0, 0];
+                        response.extend_from_slice(&7_u32.to_be_bytes());
+                        response.extend_from_slice(&request[4..6]);
+                        response.extend_from_slice(&40000_u16.to_be_bytes());
+                        response.extend_from_slice(&request[8..12]);
+                        response
+                    }
+                };
+
+                gateway.send_to(&response, from).unwrap();
+                requests.push(request);
+            }
+
+            requests
+        });
+
+        let nat_pmp = NatPmp::new(address).unwrap();
+
+        assert_eq!(nat_pmp.map(6881, 6881, 7200).unwrap(), (40000, 7200));
+        assert_eq!(nat_pmp.map(6881, 0, 0).unwrap(), (40000, 0));
+
+        let requests = handle.join().unwrap();
+
+        assert_eq!(requests[0].len(), PCP_RESPONSE_LENGTH);
+        assert_eq!(
+            &requests[0][8..24],
+            &Ipv4Addr::LOCALHOST.to_ipv6_mapped().octets()
+        );
+        assert_eq!(
+            requests[1],
+            [0, MAP_TCP, 0, 0, 0x1a, 0xe1, 0x1a, 0xe1, 0, 0, 0x1c, 0x20]
+        );
+        assert_eq!(requests[2][..2], [NAT_PMP_VERSION, MAP_TCP]);
+    }
+}
@@ -0,0 +1,320 @@
+use super::NatError;
+use crate::networking::{HttpClient, Url};
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const SSDP_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+// Most preferred first.
+const SERVICE_TYPES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_PACKET_SIZE: usize = 2048;
+// Error code of gateways that only accept mappings without an expiry.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+const DESCRIPTION: &str = "sitos";
+
+#[derive(Debug)]
+pub struct Upnp {
+    control_url: Url,
+    service_type: String,
+    local_ip: IpAddr,
+}
+
+impl Upnp {
+    pub fn discover() -> Result<Self, NatError> {
+        Self::discover_at(SocketAddr::from((SSDP_ADDRESS, SSDP_PORT)))
+    }
+
+    // Takes the first gateway that answers with a usable description.
+    pub fn discover_at(target: SocketAddr) -> Result<Self, NatError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").or(Err(NatError::FailedToSend))?;
+        socket
+            .set_read_timeout(Some(RECEIVE_TIMEOUT))
+            .or(Err(NatError::FailedToSend))?;
+
+        let search = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}:{}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+            SSDP_ADDRESS, SSDP_PORT, SEARCH_TARGET
+        );
+
+        socket
+            .send_to(search.as_bytes(), target)
+            .or(Err(NatError::FailedToSend))?;
+
+        let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        while Instant::now() < deadline {
+            let length = match socket.recv_from(&mut buffer) {
+                Ok((length, _)) => length,
+                Err(_) => continue,
+            };
+
+            if let Some(location) = header(&buffer[..length], "location") {
+                match Self::from_location(&location) {
+                    Ok(upnp) => return Ok(upnp),
+                    Err(error) => log::debug!("Upnp::discover_at() - {}: {:?}", location, error),
+                }
+            }
+        }
+
+        Err(NatError::NoGateway)
+    }
+
+    pub fn from_location(location: &str) -> Result<Self, NatError> {
+        let url = Url::parse(location).or(Err(NatError::FailedRequest))?;
+        let response = HttpClient::get(&url).or(Err(NatError::FailedRequest))?;
+        let description = String::from_utf8_lossy(&response.body);
+
+        let (service_type, control_url) = find_service(&description).ok_or(NatError::NoGateway)?;
+        let control_url = url.join(&control_url).or(Err(NatError::FailedRequest))?;
+
+        Ok(Self {
+            local_ip: local_ip_towards(&control_url.authority())?,
+            control_url,
+            service_type,
+        })
+    }
+
+    // Returns the lease granted, zero if the gateway only takes permanent mappings.
+    pub fn add_port_mapping(
+        &self,
+        external_port: u16,
+        internal_port: u16,
+        lease: u32,
+    ) -> Result<u32, NatError> {
+        let arguments = |lease: u32| {
+            [
+                ("NewRemoteHost", String::new()),
+                ("NewExternalPort", external_port.to_string()),
+                ("NewProtocol", "TCP".to_string()),
+                ("NewInternalPort", internal_port.to_string()),
+                ("NewInternalClient", self.local_ip.to_string()),
+                ("NewEnabled", "1".to_string()),
+                ("NewPortMappingDescription", DESCRIPTION.to_string()),
+                ("NewLeaseDuration", lease.to_string()),
+            ]
+        };
+
+        match self.soap("AddPortMapping", &arguments(lease)) {
+            Ok(_) => Ok(lease),
+            Err(NatError::Refused(ONLY_PERMANENT_LEASES_SUPPORTED)) if lease > 0 => {
+                self.soap("AddPortMapping", &arguments(0))?;
+                Ok(0)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn delete_port_mapping(&self, external_port: u16) -> Result<(), NatError> {
+        self.soap(
+            "DeletePortMapping",
+            &[
+                ("NewRemoteHost", String::new()),
+                ("NewExternalPort", external_port.to_string()),
+                ("NewProtocol", "TCP".to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn external_ip(&self) -> Result<IpAddr, NatError> {
+        let response = self.soap("GetExternalIPAddress", &[])?;
+
+        tag(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or(NatError::InvalidResponse)
+    }
+
+    fn soap(&self, action: &str, arguments: &[(&str, String)]) -> Result<String, NatError> {
+        let mut body = format!(
+            "<?xml version=\"1.0\"?>\r\n<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{} xmlns:u=\"{}\">",
+            action, self.service_type
+        );
+
+        for (name, value) in arguments {
+            write!(&mut body, "<{0}>{1}</{0}>", name, value).expect("Unable to write argument");
+        }
+
+        write!(&mut body, "</u:{}></s:Body></s:Envelope>", action).expect("Unable to write body");
+
+        let soap_action = format!("\"{}#{}\"", self.service_type, action);
+
+        let response = HttpClient::post(
+            &self.control_url,
+            &[
+                ("Content-Type", "text/xml; charset=\"utf-8\""),
+                ("SOAPAction", &soap_action),
+            ],
+            body.as_bytes(),
+        )
+        .or(Err(NatError::FailedRequest))?;
+
+        let body = String::from_utf8_lossy(&response.body).to_string();
+
+        if response.status != 200 {
+            return Err(
+                match tag(&body, "errorCode").and_then(|code| code.parse().ok()) {
+                    Some(code) => NatError::Refused(code),
+                    None => NatError::InvalidResponse,
+                },
+            );
+        }
+
+        Ok(body)
+    }
+}
+
+// The gateway has to forward to our address on the route to it.
+fn local_ip_towards(authority: &str) -> Result<IpAddr, NatError> {
+    let socket = UdpSocket::bind("0.0.0.0:0").or(Err(NatError::NoGateway))?;
+    socket.connect(authority).or(Err(NatError::NoGateway))?;
+
+    socket
+        .local_addr()
+        .map(|address| address.ip())
+        .or(Err(NatError::NoGateway))
+}
+
+fn header(response: &[u8], name: &str) -> Option<String> {
+    String::from_utf8_lossy(response)
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().to_string())
+}
+
+fn tag(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+
+    Some(xml[start..end].trim().to_string())
+}
+
+fn find_service(description: &str) -> Option<(String, String)> {
+    let services: Vec<(String, String)> = description
+        .split("<service>")
+        .skip(1)
+        .filter_map(|service| {
+            let service = service.split("</service>").next()?;
+
+            Some((tag(service, "serviceType")?, tag(service, "controlURL")?))
+        })
+        .collect();
+
+    SERVICE_TYPES.iter().find_map(|service_type| {
+        services
+            .iter()
+            .find(|(found, _)| found == service_type)
+            .cloned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const DESCRIPTION_XML: &str = "<root><device><serviceList>\
+        <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/l3f</controlURL></service>\
+        <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl/IPConn</controlURL></service>\
+        </serviceList></device></root>";
+
+    fn respond(stream: &mut std::net::TcpStream, status: &str, body: &str) -> String {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request = String::new();
+        let mut content_length = 0;
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+
+            request.push_str(&line);
+
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        let mut body_bytes = vec![0; content_length];
+        reader.read_exact(&mut body_bytes).unwrap();
+        request.push_str(&String::from_utf8(body_bytes).unwrap());
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        request
+    }
+
+    #[test]
+    fn test1_maps_ports_on_a_stand_in_gateway() {
+        let http = TcpListener::bind("127.0.0.1:0").unwrap();
+        let http_address = http.local_addr().unwrap();
+        let ssdp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ssdp_address = ssdp.local_addr().unwrap();
+
+        let gateway = thread::spawn(move || {
+            let mut buffer = [0; MAX_PACKET_SIZE];
+            let (length, from) = ssdp.recv_from(&mut buffer).unwrap();
+            assert!(String::from_utf8_lossy(&buffer[..length]).contains(SEARCH_TARGET));
+
+            let answer = format!(
+                "HTTP/1.1 200 OK\r\nST: {}\r\nLOCATION: http://{}/rootDesc.xml\r\n\r\n",
+                SEARCH_TARGET, http_address
+            );
+            ssdp.send_to(answer.as_bytes(), from).unwrap();
+
+            let fault = "<s:Envelope><s:Body><s:Fault><detail><UPnPError><errorCode>725</errorCode></UPnPError></detail></s:Fault></s:Body></s:Envelope>";
+            let responses = [
+                ("200 OK", DESCRIPTION_XML),
+                ("500 Internal Server Error", fault),
+                ("200 OK", ""),
+                (
+                    "200 OK",
+                    "<NewExternalIPAddress>203.0.113.9</NewExternalIPAddress>",
+                ),
+            ];
+
+            responses
+                .iter()
+                .zip(http.incoming())
+                .map(|((status, body), stream)| respond(&mut stream.unwrap(), status, body))
+                .collect::<Vec<String>>()
+        });
+
+        let upnp = Upnp::discover_at(ssdp_address).unwrap();
+
+        assert_eq!(upnp.add_port_mapping(6881, 6881, 3600).unwrap(), 0);
+        assert_eq!(upnp.external_ip().unwrap(), IpAddr::from([203, 0, 113, 9]));
+
+        let requests = gateway.join().unwrap();
+
+        assert!(requests[1].starts_with("POST /ctl/IPConn HTTP/1.1"));
+        assert!(requests[1].contains(
+            "SOAPAction: \"urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping\""
+        ));
+        assert!(requests[1].contains("<NewLeaseDuration>3600</NewLeaseDuration>"));
+        assert!(requests[2].contains("<NewInternalClient>127.0.0.1</NewInternalClient>"));
+        assert!(requests[2].contains("<NewLeaseDuration>0</NewLeaseDuration>"));
+    }
+}
@@ -1,73 +1,308 @@
-use super::{HTTPSTracker, HTTPTracker, Handshake, InterfaceProtocol, NetworkingError, Protocol};
+use super::{
+    Decoder, HTTPSTracker, HTTPTracker, Handshake, HttpClient, HttpError, HttpResponse,
+    NetworkingError, PeerRecord, Types, UDPAnnounce, UDPTracker, Url, UrlEncoder,
+    COMPACT_PEER6_LENGTH, COMPACT_PEER_LENGTH, MAX_ANNOUNCE_INTERVAL, MIN_ANNOUNCE_INTERVAL,
+    UDP_TRACKER_RETRANSMISSIONS, UDP_TRACKER_TIMEOUT,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
 
-pub enum InterfaceProtocolHandler {
-    Http(
-        InterfaceProtocol<HTTPTracker>,
-        <HTTPTracker as Protocol>::Stream,
-    ),
-    Https(
-        InterfaceProtocol<HTTPSTracker>,
-        <HTTPSTracker as Protocol>::Stream,
-    ),
+pub struct Announce<'a> {
+    pub info_hash: &'a [u8],
+    pub peer_id: &'a [u8],
+    pub port: u16,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    pub left: u64,
+    pub event: &'a str,
+    pub key: u32,
+    pub tracker_id: Option<&'a [u8]>,
+    pub ip: Option<IpAddr>,
+    // BEP 7, so that peers of both families learn about us.
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    pub total_pieces: usize,
 }
 
-impl InterfaceProtocolHandler {
-    pub fn new(tracker_address: String) -> Result<(Self, String), NetworkingError> {
-        let address_split = tracker_address.split("://").collect::<Vec<&str>>();
+pub struct AnnounceResponse {
+    pub interval: u64,
+    pub min_interval: Option<u64>,
+    pub tracker_id: Option<Vec<u8>>,
+    pub peers: Vec<PeerRecord>,
+}
 
-        let protocol = address_split[0];
-        let address = address_split[1].to_string();
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScrapeResponse {
+    pub seeders: u32,
+    pub leechers: u32,
+    pub completed: u32,
+}
 
-        let address_split: Vec<&str> = address.split('/').collect();
+pub enum InterfaceProtocolHandler {
+    Http(HTTPTracker),
+    Https(HTTPSTracker),
+    Udp(UDPTracker),
+}
 
-        let base = address_split[0].to_string();
-        let endpoint = address_split[1].to_string();
+impl InterfaceProtocolHandler {
+    pub fn new(tracker_address: String) -> Result<Self, NetworkingError> {
+        let (protocol, address) = tracker_address
+            .split_once("://")
+            .ok_or(NetworkingError::InvalidTrackerAddress)?;
 
         match protocol {
-            "http" => {
-                let mut client = InterfaceProtocol::new(HTTPTracker::new(Some(endpoint)));
+            "http" | "https" => {
+                Url::parse(&tracker_address).or(Err(NetworkingError::InvalidTrackerAddress))?;
 
-                if let Ok(stream) = client.connect(&base) {
-                    return Ok((Self::Http(client, stream), base));
+                match protocol {
+                    "http" => Ok(Self::Http(HTTPTracker::new(Some(tracker_address)))),
+                    _ => Ok(Self::Https(HTTPSTracker::new(Some(tracker_address)))),
                 }
-                Err(NetworkingError::FailedToConnect)
             }
-            "https" => {
-                let base = format!("{}:443", base);
+            "udp" => {
+                let base = address.split('/').next().unwrap_or_default();
 
-                let mut client = InterfaceProtocol::new(HTTPSTracker::new(Some(endpoint)));
+                let mut tracker =
+                    UDPTracker::new(base).or(Err(NetworkingError::FailedToConnect))?;
 
-                if let Ok(stream) = client.connect(&base) {
-                    return Ok((Self::Https(client, stream), base));
-                }
-                Err(NetworkingError::FailedToConnect)
+                // The BEP 15 schedule would keep the tracker thread for hours on a dead tracker.
+                tracker.set_retransmission(
+                    Duration::from_secs(UDP_TRACKER_TIMEOUT),
+                    UDP_TRACKER_RETRANSMISSIONS,
+                );
+
+                Ok(Self::Udp(tracker))
             }
-            _ => Err(NetworkingError::FailedToConnect),
+            _ => Err(NetworkingError::InvalidTrackerAddress),
         }
     }
 
-    pub fn send(&mut self, request: String) -> Result<(), NetworkingError> {
-        match self {
-            Self::Http(ref mut client, ref mut stream) => client.send(stream, request),
-            Self::Https(ref mut client, ref mut stream) => client.send(stream, request),
+    pub fn announce(&mut self, announce: Announce) -> Result<AnnounceResponse, NetworkingError> {
+        let handshake = Handshake {
+            id: UrlEncoder::encode_binary_data(announce.peer_id),
+            info_hash: UrlEncoder::encode_binary_data(announce.info_hash),
+            port: announce.port,
+            left: announce.left,
+            downloaded: announce.downloaded,
+            uploaded: announce.uploaded,
+            event: announce.event.to_string(),
+            key: announce.key,
+            tracker_id: announce.tracker_id.map(UrlEncoder::encode_binary_data),
+            ip: announce.ip.map(|ip| ip.to_string()),
+            ipv4: announce.ipv4.map(|ip| ip.to_string()),
+            ipv6: announce.ipv6.map(|ip| ip.to_string()),
+        };
+
+        let url = match self {
+            Self::Http(ref tracker) => tracker.announce_url(handshake),
+            Self::Https(ref tracker) => tracker.announce_url(handshake),
+            Self::Udp(ref mut tracker) => return Self::announce_over_udp(tracker, announce),
         }
+        .or(Err(NetworkingError::InvalidTrackerAddress))?;
+
+        let response = HttpClient::get(&url).map_err(|error| {
+            log::error!("Tracker announce failed: {:?}", error);
+            NetworkingError::FailedTrackerRequest
+        })?;
+
+        Self::parse_http_response(response, announce.total_pieces)
+    }
+
+    pub fn scrape(&mut self, info_hash: &[u8]) -> Result<ScrapeResponse, NetworkingError> {
+        let encoded_info_hash = UrlEncoder::encode_binary_data(info_hash);
+
+        let url = match self {
+            Self::Http(ref tracker) => tracker.scrape_url(&encoded_info_hash),
+            Self::Https(ref tracker) => tracker.scrape_url(&encoded_info_hash),
+            Self::Udp(ref mut tracker) => return Self::scrape_over_udp(tracker, info_hash),
+        }
+        .map_err(|error| match error {
+            HttpError::ScrapeUnsupported => NetworkingError::ScrapeUnsupported,
+            _ => NetworkingError::InvalidTrackerAddress,
+        })?;
+
+        let response = HttpClient::get(&url).map_err(|error| {
+            log::error!("Tracker scrape failed: {:?}", error);
+            NetworkingError::FailedTrackerRequest
+        })?;
+
+        Self::parse_scrape_response(response, info_hash)
+    }
+
+    fn scrape_over_udp(
+        tracker: &mut UDPTracker,
+        info_hash: &[u8],
+    ) -> Result<ScrapeResponse, NetworkingError> {
+        let scrape = tracker
+            .scrape(&[info_hash])
+            .map_err(|error| {
+                log::error!("UDP tracker scrape failed: {:?}", error);
+                NetworkingError::FailedTrackerRequest
+            })?
+            .pop()
+            .ok_or(NetworkingError::FailedTrackerRequest)?;
+
+        Ok(ScrapeResponse {
+            seeders: scrape.seeders,
+            leechers: scrape.leechers,
+            completed: scrape.completed,
+        })
+    }
+
+    fn announce_over_udp(
+        tracker: &mut UDPTracker,
+        announce: Announce,
+    ) -> Result<AnnounceResponse, NetworkingError> {
+        let response = tracker
+            .announce(&UDPAnnounce {
+                info_hash: announce.info_hash,
+                peer_id: announce.peer_id,
+                downloaded: announce.downloaded,
+                left: announce.left,
+                uploaded: announce.uploaded,
+                event: announce.event,
+                key: announce.key,
+                // The UDP announce only carries IPv4 addresses.
+                ip: match announce.ip {
+                    Some(IpAddr::V4(ip)) => ip,
+                    _ => Ipv4Addr::UNSPECIFIED,
+                },
+                port: announce.port,
+            })
+            .map_err(|error| {
+                log::error!("UDP tracker announce failed: {:?}", error);
+                NetworkingError::FailedTrackerRequest
+            })?;
+
+        Ok(AnnounceResponse {
+            interval: announce_interval(response.interval as i64),
+            min_interval: None,
+            tracker_id: None,
+            peers: PeerRecord::new_from_list(
+                &Types::String(response.peers),
+                tracker.compact_peer_length(),
+                announce.total_pieces,
+            ),
+        })
     }
 
-    pub fn read_to_end(&mut self) -> Result<Vec<u8>, NetworkingError> {
-        match self {
-            Self::Http(ref mut client, ref mut stream) => client.read_to_end(stream),
-            Self::Https(ref mut client, ref mut stream) => client.read_to_end(stream),
+    fn parse_scrape_response(
+        response: HttpResponse,
+        info_hash: &[u8],
+    ) -> Result<ScrapeResponse, NetworkingError> {
+        let decoded = Decoder::new_from_bytes(&response.body).decode();
+
+        let file = match &decoded {
+            Ok(Types::Dictionary(dict)) => dict
+                .get(b"files".as_slice())
+                .and_then(|files| files.get_dictionary())
+                .and_then(|files| files.get(info_hash))
+                .and_then(|file| file.get_dictionary()),
+            _ => None,
         }
+        .ok_or_else(|| {
+            log::error!(
+                "Tracker answered the scrape with status {} and no stats for the torrent",
+                response.status
+            );
+            NetworkingError::FailedTrackerRequest
+        })?;
+
+        let count = |key: &[u8]| {
+            file.get(key)
+                .and_then(|value| value.get_integrer())
+                .unwrap_or(0) as u32
+        };
+
+        Ok(ScrapeResponse {
+            seeders: count(b"complete"),
+            leechers: count(b"incomplete"),
+            completed: count(b"downloaded"),
+        })
     }
 
-    pub fn format_handshake_message(&self, handshake_params: Handshake) -> String {
-        match self {
-            Self::Http(ref client, _) => client
-                .get_protocol()
-                .format_handshake_message(handshake_params),
-            Self::Https(ref client, _) => client
-                .get_protocol()
-                .format_handshake_message(handshake_params),
+    fn parse_http_response(
+        response: HttpResponse,
+        total_pieces: usize,
+    ) -> Result<AnnounceResponse, NetworkingError> {
+        let dict = match Decoder::new_from_bytes(&response.body).decode() {
+            Ok(Types::Dictionary(dict)) => dict,
+            _ => {
+                log::error!(
+                    "Tracker answered with status {} and no valid body",
+                    response.status
+                );
+                return Err(NetworkingError::FailedTrackerRequest);
+            }
+        };
+
+        if let Some(Types::String(reason)) = dict.get(b"failure reason".as_slice()) {
+            log::error!(
+                "Tracker announce failed: {}",
+                String::from_utf8_lossy(reason)
+            );
+            return Err(NetworkingError::FailedTrackerRequest);
         }
+
+        if let Some(Types::String(reason)) = dict.get(b"warning reason".as_slice()) {
+            log::warn!("Tracker warning: {}", String::from_utf8_lossy(reason));
+        }
+
+        let interval = dict
+            .get(b"interval".as_slice())
+            .and_then(|interval| interval.get_integrer())
+            .ok_or(NetworkingError::FailedTrackerRequest)?;
+
+        let min_interval = dict
+            .get(b"min interval".as_slice())
+            .and_then(|min_interval| min_interval.get_integrer())
+            .map(announce_interval);
+
+        let tracker_id = dict
+            .get(b"tracker id".as_slice())
+            .and_then(|tracker_id| tracker_id.get_string());
+
+        let mut peers = vec![];
+
+        if let Some(list) = dict.get(b"peers".as_slice()) {
+            peers.extend(PeerRecord::new_from_list(
+                list,
+                COMPACT_PEER_LENGTH,
+                total_pieces,
+            ));
+        }
+
+        if let Some(list) = dict.get(b"peers6".as_slice()) {
+            peers.extend(PeerRecord::new_from_list(
+                list,
+                COMPACT_PEER6_LENGTH,
+                total_pieces,
+            ));
+        }
+
+        Ok(AnnounceResponse {
+            interval: announce_interval(interval),
+            min_interval,
+            tracker_id,
+            peers,
+        })
+    }
+}
+
+// A tracker answering garbage must neither be flooded nor leave the torrent without peers for days.
+fn announce_interval(seconds: i64) -> u64 {
+    seconds.clamp(MIN_ANNOUNCE_INTERVAL as i64, MAX_ANNOUNCE_INTERVAL as i64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_announce_intervals_are_clamped() {
+        assert_eq!(announce_interval(-1), MIN_ANNOUNCE_INTERVAL);
+        assert_eq!(announce_interval(0), MIN_ANNOUNCE_INTERVAL);
+        assert_eq!(announce_interval(1800), 1800);
+        assert_eq!(announce_interval(i64::MAX), MAX_ANNOUNCE_INTERVAL);
     }
 }
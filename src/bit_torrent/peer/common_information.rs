@@ -1,8 +1,14 @@
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use sha1::{Digest, Sha1};
 
+use super::{
+    Bitfield, ConnectedPeers, ResumeData, ScrapeResponse, Session, TorrentBandwidth, TorrentDisk,
+    TransferStats,
+};
 use crate::{
+    file_system::{Storage, StorageKind},
     frontend::{peers::PeersData, torrents::TorrentData},
     torrent_file::Torrent,
     utils::random_u64_as_bytes,
@@ -23,6 +29,18 @@ pub struct CommonInformation {
     pub tx_peers: Arc<Mutex<gtk::glib::Sender<PeersData>>>,
     pub temp_directory: String,
     pub download_directory: String,
+    pub bandwidth: TorrentBandwidth,
+    pub session: Session,
+    pub connected_peers: ConnectedPeers,
+    pub stats: Arc<TransferStats>,
+    pub error: Arc<Mutex<Option<String>>>,
+    pub storage: Arc<dyn Storage>,
+    pub disk: TorrentDisk,
+    // Share of the pieces hashed so far while a recheck is running.
+    pub checking: Arc<Mutex<Option<f64>>>,
+    pub swarm: Arc<Mutex<Option<ScrapeResponse>>>,
+    // Several connections may save resume data to the same file at once.
+    saving_resume: Arc<Mutex<()>>,
 }
 
 impl CommonInformation {
@@ -33,6 +51,7 @@ impl CommonInformation {
         download_directory: &str,
         tx_torrent: Arc<Mutex<gtk::glib::Sender<TorrentData>>>,
         tx_peers: Arc<Mutex<gtk::glib::Sender<PeersData>>>,
+        session: &Session,
     ) -> Self {
         let pieces = torrent
             .get_pieces()
@@ -56,7 +75,16 @@ impl CommonInformation {
 
         let peer_id: [u8; 20] = hasher.finalize().into();
 
-        let file_length = torrent.get_length().expect("Corrupted torrent, no length") as u64;
+        let files = storage_files(torrent, &file_name);
+        let file_length = files.iter().map(|(_, length)| length).sum();
+        let storage = StorageKind::for_torrent(&file_name).open(
+            Path::new(download_directory),
+            files,
+            piece_length,
+        );
+        let disk = session
+            .disk_io
+            .for_torrent(&info_hash, Arc::clone(&storage));
 
         Self {
             piece_length,
@@ -64,13 +92,234 @@ impl CommonInformation {
             total_pieces: pieces.len(),
             pieces,
             info_hash,
-            file_name,
+            file_name: file_name.clone(),
             file_length,
             torrent_pathname: torrent_pathname.to_string(),
             tx_torrent,
             tx_peers,
             temp_directory: temp_directory.to_string(),
             download_directory: download_directory.to_string(),
+            bandwidth: session.bandwidth.for_torrent(torrent_pathname),
+            session: session.clone(),
+            connected_peers: ConnectedPeers::new(peer_id),
+            stats: Arc::new(TransferStats::load(&format!(
+                "{}/{}.stats",
+                temp_directory, file_name
+            ))),
+            swarm: Arc::new(Mutex::new(None)),
+            error: Arc::new(Mutex::new(None)),
+            checking: Arc::new(Mutex::new(None)),
+            saving_resume: Arc::new(Mutex::new(())),
+            storage,
+            disk,
+        }
+    }
+
+    // Only the last piece may be shorter than piece_length.
+    pub fn piece_size(&self, piece_index: usize) -> usize {
+        if piece_index == self.total_pieces - 1 {
+            (self.file_length - (self.piece_length * (self.total_pieces - 1)) as u64) as usize
+        } else {
+            self.piece_length
+        }
+    }
+
+    pub fn bytes_left(&self, have: &Bitfield) -> u64 {
+        (0..self.total_pieces)
+            .filter(|piece_index| !have.has(*piece_index))
+            .map(|piece_index| self.piece_size(piece_index) as u64)
+            .sum()
+    }
+
+    pub fn save_stats(&self) {
+        if let Err(error) = self.stats.save() {
+            log::error!("Failed to save transfer stats: {}", error);
+        }
+    }
+
+    // The files are synced first, so that a crash never leaves resume data trusting pieces the
+    // disk lost. Must be called without `have` locked.
+    pub fn save_resume(&self, have: &Mutex<Bitfield>) {
+        let _saving = self.saving_resume.lock().unwrap();
+        let have = have.lock().unwrap().get();
+
+        if let Err(error) = self.storage.flush() {
+            log::error!("Failed to sync {}: {}", self.file_name, error);
+            return;
+        }
+
+        let data = ResumeData {
+            info_hash: self.info_hash.clone(),
+            have,
+            files: self.storage.file_states(),
+        };
+
+        if let Err(error) = data.save(&self.resume_pathname()) {
+            log::error!("Failed to save resume data: {}", error);
+        }
+    }
+
+    // Pieces already stored by a previous run, None if there is no record of one. Those in files
+    // changed since are verified again, the rest are trusted.
+    pub fn resume(&self) -> Option<Bitfield> {
+        let mut have = Bitfield::new(self.total_pieces);
+
+        let data = match ResumeData::load(&self.resume_pathname()) {
+            Some(data)
+                if data.info_hash == self.info_hash && data.have.len() == have.get().len() =>
+            {
+                data
+            }
+            _ => return None,
+        };
+
+        let resumed = Bitfield::new_from_vec(data.have.clone(), self.total_pieces);
+        let layout = self.storage.layout();
+        let changed: Vec<bool> = self
+            .storage
+            .file_states()
+            .iter()
+            .enumerate()
+            .map(|(index, state)| data.changed(index, state))
+            .collect();
+
+        let mut verified = 0;
+
+        for piece_index in (0..self.total_pieces).filter(|index| resumed.has(*index)) {
+            let spans = layout
+                .spans(
+                    layout.offset_of(piece_index, 0),
+                    self.piece_size(piece_index) as u64,
+                )
+                .unwrap_or_default();
+
+            let trusted = match spans.iter().any(|(index, _, _)| changed[*index]) {
+                true => {
+                    verified += 1;
+                    matches!(
+                        self.storage.hash_piece(piece_index),
+                        Ok(hash) if hash[..] == self.pieces[piece_index][..]
+                    )
+                }
+                false => true,
+            };
+
+            if trusted {
+                have.set(piece_index);
+            }
         }
+
+        log::info!(
+            "Resumed {} with {} of {} pieces, {} of them verified again",
+            self.file_name,
+            (0..self.total_pieces)
+                .filter(|index| have.has(*index))
+                .count(),
+            self.total_pieces,
+            verified
+        );
+
+        Some(have)
+    }
+
+    pub fn resume_pathname(&self) -> String {
+        format!("{}/{}.resume", self.temp_directory, self.file_name)
+    }
+}
+
+// Relative to the download directory: `name` itself for single-file torrents, else the listed
+// paths under a `name` directory. Components that could step out of it are replaced.
+fn storage_files(torrent: &Torrent, name: &str) -> Vec<(PathBuf, u64)> {
+    let root = PathBuf::from(sanitize(name));
+
+    match (torrent.get_length(), torrent.get_files()) {
+        (Some(length), _) => vec![(root, length as u64)],
+        (None, Some(files)) => files
+            .into_iter()
+            .map(|(path, length)| {
+                let path = path
+                    .iter()
+                    .map(|component| sanitize(component))
+                    .fold(root.clone(), |path, component| path.join(component));
+
+                (path, length)
+            })
+            .collect(),
+        (None, None) => panic!("Corrupted torrent, no length"),
+    }
+}
+
+fn sanitize(component: &str) -> String {
+    match component.replace(['/', '\\'], "_").as_str() {
+        "" | "." | ".." => "_".to_string(),
+        component => component.to_string(),
+    }
+}
+
+#[cfg(test)]
+impl CommonInformation {
+    // A single-file torrent named `name` over `data`, kept in memory and with its other files
+    // under a directory of its own. The receivers must be kept alive for as long as the torrent
+    // reports to the views.
+    pub fn from_data(
+        name: &str,
+        data: &[u8],
+        piece_length: usize,
+        session: &Session,
+    ) -> (
+        Self,
+        gtk::glib::Receiver<TorrentData>,
+        gtk::glib::Receiver<PeersData>,
+    ) {
+        use gtk::glib;
+        use std::{env, fs};
+
+        let directory = env::temp_dir().join(format!("sitos_{}", name));
+        let directory_name = directory.to_str().unwrap();
+        fs::create_dir_all(&directory).unwrap();
+
+        let pieces: Vec<u8> = data
+            .chunks(piece_length)
+            .flat_map(|piece| <[u8; 20]>::from(Sha1::digest(piece)))
+            .collect();
+        let mut contents = format!(
+            "d8:announce25:http://localhost/announce4:infod6:lengthi{}e4:name{}:{}12:piece lengthi{}e6:pieces{}:",
+            data.len(),
+            name.len(),
+            name,
+            piece_length,
+            pieces.len()
+        )
+        .into_bytes();
+        contents.extend(pieces);
+        contents.extend(b"ee");
+
+        let torrent_pathname = directory.join(format!("{}.torrent", name));
+        fs::write(&torrent_pathname, contents).unwrap();
+
+        let (tx_torrent, rx_torrent) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let (tx_peers, rx_peers) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        let mut common_information = Self::new(
+            &Torrent::new_from_pathname(torrent_pathname.to_str().unwrap()),
+            torrent_pathname.to_str().unwrap(),
+            directory_name,
+            directory_name,
+            Arc::new(Mutex::new(tx_torrent)),
+            Arc::new(Mutex::new(tx_peers)),
+            session,
+        );
+
+        common_information.storage = StorageKind::Memory.open(
+            &directory,
+            vec![(PathBuf::from(name), data.len() as u64)],
+            piece_length,
+        );
+        common_information.disk = session.disk_io.for_torrent(
+            &common_information.info_hash,
+            Arc::clone(&common_information.storage),
+        );
+
+        (common_information, rx_torrent, rx_peers)
     }
 }
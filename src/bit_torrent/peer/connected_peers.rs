@@ -0,0 +1,141 @@
+use super::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    connections: HashMap<Vec<u8>, (u64, Origin)>,
+    next_id: u64,
+}
+
+// When a peer is connected from both sides, both ends keep the connection opened by whichever
+// of them has the lower peer id, so they agree on which one to close.
+#[derive(Clone, Debug)]
+pub struct ConnectedPeers {
+    own_peer_id: [u8; 20],
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl ConnectedPeers {
+    pub fn new(own_peer_id: [u8; 20]) -> Self {
+        Self {
+            own_peer_id,
+            registry: Arc::new(Mutex::new(Registry::default())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registry.lock().unwrap().connections.is_empty()
+    }
+
+    pub fn register(&self, peer_id: &[u8], origin: Origin) -> Result<Registration, Error> {
+        if peer_id == self.own_peer_id {
+            return Err(Error::SelfConnection);
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+
+        if let Some((_, existing)) = registry.connections.get(peer_id) {
+            let kept = if self.own_peer_id.as_slice() < peer_id {
+                Origin::Outgoing
+            } else {
+                Origin::Incoming
+            };
+
+            if *existing == origin || *existing == kept {
+                return Err(Error::DuplicateConnection);
+            }
+        }
+
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.connections.insert(peer_id.to_vec(), (id, origin));
+
+        Ok(Registration {
+            peer_id: peer_id.to_vec(),
+            id,
+            registry: Arc::clone(&self.registry),
+        })
+    }
+}
+
+// Unregistered when dropped.
+#[derive(Debug)]
+pub struct Registration {
+    peer_id: Vec<u8>,
+    id: u64,
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Registration {
+    // False once a duplicate connection has replaced this one.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.registry.lock().unwrap().connections.get(&self.peer_id),
+            Some((id, _)) if *id == self.id
+        )
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+
+        if matches!(registry.connections.get(&self.peer_id), Some((id, _)) if *id == self.id) {
+            registry.connections.remove(&self.peer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_connection_to_ourselves_is_rejected() {
+        let peers = ConnectedPeers::new([1; 20]);
+
+        assert!(matches!(
+            peers.register(&[1; 20], Origin::Outgoing),
+            Err(Error::SelfConnection)
+        ));
+    }
+
+    #[test]
+    fn test2_lower_peer_id_keeps_its_outgoing_connection() {
+        let peers = ConnectedPeers::new([1; 20]);
+
+        let incoming = peers.register(&[2; 20], Origin::Incoming).unwrap();
+        let outgoing = peers.register(&[2; 20], Origin::Outgoing).unwrap();
+
+        assert!(!incoming.is_active());
+        assert!(outgoing.is_active());
+        assert!(matches!(
+            peers.register(&[2; 20], Origin::Incoming),
+            Err(Error::DuplicateConnection)
+        ));
+    }
+
+    #[test]
+    fn test3_higher_peer_id_keeps_the_incoming_connection() {
+        let peers = ConnectedPeers::new([3; 20]);
+
+        let incoming = peers.register(&[2; 20], Origin::Incoming).unwrap();
+
+        assert!(matches!(
+            peers.register(&[2; 20], Origin::Outgoing),
+            Err(Error::DuplicateConnection)
+        ));
+
+        drop(incoming);
+
+        assert!(peers.is_empty());
+        assert!(peers.register(&[2; 20], Origin::Outgoing).is_ok());
+    }
+}
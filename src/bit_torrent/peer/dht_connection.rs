@@ -0,0 +1,80 @@
+use crate::frontend::torrents::TorrentData;
+
+use super::{
+    Bitfield, CommonInformation, Dht, PeerList, PeerRecord, PeerState, DHT_ANNOUNCE_INTERVAL,
+};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct DhtConnection {
+    dht: Arc<Dht>,
+    bitfield: Arc<Mutex<Bitfield>>,
+    peers: Arc<Mutex<PeerList>>,
+    common_information: CommonInformation,
+    state: Arc<Mutex<PeerState>>,
+}
+
+impl DhtConnection {
+    pub fn new(
+        dht: Arc<Dht>,
+        bitfield: Arc<Mutex<Bitfield>>,
+        peers: Arc<Mutex<PeerList>>,
+        common_information: CommonInformation,
+        state: Arc<Mutex<PeerState>>,
+    ) -> Self {
+        Self {
+            dht,
+            bitfield,
+            peers,
+            common_information,
+            state,
+        }
+    }
+
+    pub fn activate(self, listening_port: u16) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if self.is_broken() {
+                break;
+            }
+
+            let port = self
+                .common_information
+                .session
+                .addresses
+                .advertised_port(listening_port);
+
+            let found = self.dht.announce(&self.common_information.info_hash, port);
+
+            log::info!("DHT returned {} peers", found.len());
+
+            let records = found
+                .into_iter()
+                .map(|address| {
+                    PeerRecord::new_from_address(address, self.common_information.total_pieces)
+                })
+                .collect();
+
+            let mut peers_guard = self.peers.lock().unwrap();
+            peers_guard.update(records);
+
+            let bitfield_guard = self.bitfield.lock().unwrap();
+            TorrentData::refresh(&self.common_information, &peers_guard, &bitfield_guard);
+
+            drop(bitfield_guard);
+            drop(peers_guard);
+
+            let last_call = Instant::now();
+
+            while !self.is_broken() && last_call.elapsed().as_secs() < DHT_ANNOUNCE_INTERVAL {
+                thread::sleep(POLL_INTERVAL);
+            }
+        })
+    }
+
+    fn is_broken(&self) -> bool {
+        matches!(&*self.state.lock().unwrap(), PeerState::Broken)
+    }
+}
@@ -5,5 +5,12 @@ pub enum Error {
     NoNewPiecesFromPeer,
     FailedToUnchoke,
     FailedMessageRead,
+    FailedHandshake,
+    SelfConnection,
+    DuplicateConnection,
+    InvalidRequest,
+    TooManyRequests,
+    FailedToReadBlock,
+    FailedToSendBlock,
     //FailedToGetLock,
 }
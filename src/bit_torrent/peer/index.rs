@@ -2,14 +2,16 @@ use crate::bit_torrent::peer::RemoveTorrent;
 use crate::frontend::peers::PeersData;
 
 use super::{
-    Bitfield, CommonInformation, PeerHandler, PeerList, PeerState, ServerHandler, Torrent,
-    TorrentData, TrackerConnection,
+    Bitfield, CommonInformation, DhtConnection, NetworkingError, PeerHandler, PeerList, PeerState,
+    Recheck, ServerConnection, Session, Torrent, TorrentData, TrackerConnection,
 };
 use gtk::glib::Sender;
+use std::net::SocketAddr;
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+// Locks are always taken in the order `peers`, `have`, `state`, skipping any not needed.
 pub struct Peer {
     common_information: CommonInformation,
     have: Arc<Mutex<Bitfield>>,
@@ -26,6 +28,7 @@ impl Peer {
         download_directory: &str,
         sender_torrent: Arc<Mutex<Sender<TorrentData>>>,
         sender_peers: Arc<Mutex<Sender<PeersData>>>,
+        session: &Session,
     ) -> Self {
         let torrent = Torrent::new_from_pathname(torrent_pathname);
 
@@ -36,10 +39,21 @@ impl Peer {
             download_directory,
             sender_torrent,
             sender_peers,
+            session,
         );
 
         let have = Arc::new(Mutex::new(Bitfield::new(common_information.total_pieces)));
-        let peers = Arc::new(Mutex::new(PeerList::new()));
+        let own_address = match (session.addresses.external_ip(), &session.listener) {
+            (Some(ip), Some(listener)) => Some(SocketAddr::new(
+                ip,
+                session.addresses.advertised_port(listener.port()),
+            )),
+            _ => None,
+        };
+        let peers = Arc::new(Mutex::new(PeerList::new_with(
+            own_address,
+            Arc::clone(&session.ip_filter),
+        )));
 
         Self {
             state: Arc::new(Mutex::new(PeerState::NoPieces(format!(
@@ -56,37 +70,157 @@ impl Peer {
 
     pub fn activate(mut self, remove_rx: Receiver<String>) -> thread::JoinHandle<()> {
         thread::spawn(move || {
+            let recheck = {
+                let common_information = self.common_information.clone();
+                let have = Arc::clone(&self.have);
+                let peers = Arc::clone(&self.peers);
+                let state = Arc::clone(&self.state);
+
+                move || {
+                    let (common_information, have, peers, state) = (
+                        common_information.clone(),
+                        Arc::clone(&have),
+                        Arc::clone(&peers),
+                        Arc::clone(&state),
+                    );
+
+                    thread::spawn(move || Recheck::run(&common_information, &have, &peers, &state));
+                }
+            };
+
             self.handlers.push(RemoveTorrent::wait_signal(
-                self.common_information.torrent_pathname.clone(),
+                self.common_information.clone(),
                 remove_rx,
                 Arc::clone(&self.state),
+                recheck.clone(),
             ));
 
-            let announce =
-                String::from_utf8(self.torrent.get_announce().expect("Failed to get announce"))
-                    .expect("Failed to parse announce");
+            match self.common_information.resume() {
+                Some(have) => *self.have.lock().unwrap() = have,
+                // Files already there without a record of how they got there, e.g. added to be
+                // seeded, are checked in full. The check runs in the background while the torrent
+                // starts, PeerHandler picks up the pieces found once it is done.
+                None if self
+                    .common_information
+                    .storage
+                    .file_states()
+                    .iter()
+                    .any(|state| matches!(state, Some((length, _)) if *length > 0)) =>
+                {
+                    recheck()
+                }
+                None => {}
+            }
 
-            let server_handler = ServerHandler::new(
-                Arc::clone(&self.have),
-                self.common_information.clone(),
-                Arc::clone(&self.state),
-            )
-            .expect("Failed to create server handler");
+            if let Err(error) = self.common_information.storage.allocate() {
+                log::error!(
+                    "Failed to allocate {}: {}",
+                    self.common_information.file_name,
+                    error
+                );
+            }
 
-            let tracker_connection = TrackerConnection::new(
-                announce,
-                Arc::clone(&self.have),
-                Arc::clone(&self.peers),
-                self.common_information.clone(),
-                Arc::clone(&self.state),
-            )
-            .expect("Failed to create tracker connection");
+            let listener = self.common_information.session.listener.clone();
 
-            self.handlers.push(
-                tracker_connection.activate(server_handler.get_port(), server_handler.get_ip()),
-            );
+            if listener.is_none() {
+                log::warn!(
+                    "No port to accept peers on, {} only downloads from peers it dials",
+                    self.common_information.file_name
+                );
+            }
+
+            let _listener_registration = listener.as_ref().map(|listener| {
+                let bitfield = Arc::clone(&self.have);
+                let state = Arc::clone(&self.state);
+                let common_information = self.common_information.clone();
+
+                listener.register(
+                    &self.common_information.info_hash,
+                    Arc::new(move |stream, slot, peer_id| {
+                        ServerConnection::activate(
+                            Arc::clone(&bitfield),
+                            Arc::clone(&state),
+                            common_information.clone(),
+                            stream,
+                            slot,
+                            peer_id,
+                        );
+                    }),
+                )
+            });
+
+            // Without a listener port 0 is announced, unless one is configured to be advertised.
+            let listening_port = listener.as_ref().map_or(0, |listener| listener.port());
+
+            let dht = match self.torrent.is_private() {
+                true => None,
+                false => self.common_information.session.dht.clone(),
+            };
+
+            let tracker_connection = self
+                .torrent
+                .get_announce()
+                .and_then(|announce| String::from_utf8(announce).ok())
+                .ok_or(NetworkingError::InvalidTrackerAddress)
+                .and_then(|announce| {
+                    TrackerConnection::new(
+                        announce,
+                        Arc::clone(&self.have),
+                        Arc::clone(&self.peers),
+                        self.common_information.clone(),
+                        Arc::clone(&self.state),
+                    )
+                });
+
+            match tracker_connection {
+                Ok(tracker_connection) => self
+                    .handlers
+                    .push(tracker_connection.activate(listening_port)),
+                Err(error) if dht.is_some() => {
+                    log::warn!("No usable tracker ({:?}), relying on the DHT", error)
+                }
+                // Without a tracker or the DHT the torrent stops, with the reason shown.
+                Err(error) => {
+                    log::error!(
+                        "No usable tracker for {}: {:?}",
+                        self.common_information.file_name,
+                        error
+                    );
+                    *self.common_information.error.lock().unwrap() =
+                        Some(format!("No usable tracker: {:?}", error));
+                    *self.state.lock().unwrap() = PeerState::Broken;
+
+                    let peers_guard = self.peers.lock().unwrap();
+                    let have_guard = self.have.lock().unwrap();
+                    TorrentData::refresh(&self.common_information, &peers_guard, &have_guard);
+                }
+            }
 
-            self.handlers.push(server_handler.activate());
+            let _lsd_registration = match (
+                self.torrent.is_private(),
+                &self.common_information.session.lsd,
+            ) {
+                (false, Some(lsd)) => Some(lsd.register(
+                    &self.common_information.info_hash,
+                    listening_port,
+                    Arc::clone(&self.peers),
+                    self.common_information.total_pieces,
+                )),
+                _ => None,
+            };
+
+            if let Some(dht) = dht {
+                self.handlers.push(
+                    DhtConnection::new(
+                        dht,
+                        Arc::clone(&self.have),
+                        Arc::clone(&self.peers),
+                        self.common_information.clone(),
+                        Arc::clone(&self.state),
+                    )
+                    .activate(listening_port),
+                );
+            }
 
             log::info!("Established connection with tracker");
 
@@ -1,30 +1,40 @@
 pub use super::*;
-pub use client::InterfaceProtocolHandler;
+pub use client::{Announce, AnnounceResponse, InterfaceProtocolHandler, ScrapeResponse};
 pub use common_information::CommonInformation;
+pub use connected_peers::{ConnectedPeers, Origin, Registration};
+pub use dht_connection::DhtConnection;
 pub use errors::Error;
 pub use index::Peer;
 pub use peer_connection::PeerConnection;
 pub use peer_handler::PeerHandler;
 pub use peer_list::PeerList;
+pub use peer_priority::canonical_priority;
 pub use peer_state::PeerState;
+pub use recheck::Recheck;
 pub use remove_torrent::RemoveTorrent;
+pub use resume_data::ResumeData;
 pub use server_connection::ServerConnection;
-pub use server_handler::ServerHandler;
 pub use state::State;
 pub use tracker_connection::TrackerConnection;
+pub use transfer_stats::TransferStats;
 pub use upload_state::State as UploadState;
 
 mod client;
 mod common_information;
+mod connected_peers;
+mod dht_connection;
 mod errors;
 mod index;
 mod peer_connection;
 mod peer_handler;
 mod peer_list;
+mod peer_priority;
 mod peer_state;
+mod recheck;
 mod remove_torrent;
+mod resume_data;
 mod server_connection;
-mod server_handler;
 mod state;
 mod tracker_connection;
+mod transfer_stats;
 mod upload_state;
@@ -2,13 +2,17 @@ use crate::frontend::peers::PeersData;
 use crate::frontend::torrents::TorrentData;
 
 use super::{
-    BTProtocol, Bitfield, CommonInformation, Error, InterfaceProtocol, Message, NetworkingError,
-    PeerList, PeerRecord, PeerState, Piece, Protocol, ServerHandler, State, BLOCK_LENGTH,
+    BTProtocol, Bitfield, CommonInformation, ConnectionSlot, Direction, Error, InterfaceProtocol,
+    Message, NetworkingError, Origin, PeerList, PeerRecord, PeerState, Piece, Protocol,
+    Registration, State, BLOCK_LENGTH,
 };
 use std::thread;
 
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+// How long a peer has to tell which pieces it has once connected.
+const PIECES_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct PeerConnection {
     pub peer: PeerRecord,
@@ -32,10 +36,16 @@ impl PeerConnection {
     ) -> Result<Self, NetworkingError> {
         let mut client = InterfaceProtocol::new(BTProtocol);
 
-        let address = if peer.ip == ServerHandler::get_clients_ip().unwrap() {
-            format!("127.0.0.1:{}", peer.port)
-        } else {
-            peer.get_address()
+        let address = match peer.ip.parse() {
+            Ok(ip) if common_information.session.ip_filter.block(ip) => {
+                return Err(NetworkingError::BlockedAddress)
+            }
+            Ok(ip) => common_information
+                .session
+                .addresses
+                .connect_address(ip, peer.port as u16)
+                .to_string(),
+            Err(_) => peer.get_address(),
         };
 
         let stream = client.connect(&address)?;
@@ -59,8 +69,11 @@ impl PeerConnection {
         common_information: CommonInformation,
         peer: PeerRecord,
         peer_state: Arc<Mutex<PeerState>>,
+        mut slot: ConnectionSlot,
     ) -> thread::JoinHandle<()> {
         thread::spawn(move || {
+            let mut registration: Option<Registration> = None;
+
             match PeerConnection::new(
                 bitfield,
                 Arc::clone(&peers),
@@ -74,14 +87,36 @@ impl PeerConnection {
                         break;
                     }
 
+                    if registration
+                        .as_ref()
+                        .is_some_and(|registration| !registration.is_active())
+                    {
+                        log::debug!("PeerConnection::activate() - closing duplicate connection");
+                        peers.lock().unwrap().release(&peer.ip, peer.port);
+                        PeersData::refresh(&peer_connection, true);
+                        break;
+                    }
+
                     let current_state = peer_connection.state.clone();
 
                     match current_state {
                         State::UnknownToPeer => match peer_connection.greet() {
-                            Ok(new_state) => {
-                                peer_connection.state = new_state;
+                            Ok(new_registration) => {
+                                slot.established();
+                                peers.lock().unwrap().succeed(&peer.ip, peer.port);
+                                registration = Some(new_registration);
+                                peer_connection.state = State::ProcessingHandshakeResponse;
+                            }
+                            Err(Error::SelfConnection) => {
+                                log::debug!("PeerConnection::activate() - forgetting own address");
+                                peers.lock().unwrap().remove(&peer.ip, peer.port);
+                                break;
+                            }
+                            Err(Error::DuplicateConnection) => {
+                                peers.lock().unwrap().release(&peer.ip, peer.port);
+                                break;
                             }
-                            _ => peer_connection.state = State::Useless(false),
+                            Err(_) => peer_connection.state = State::Useless(false),
                         },
                         State::ProcessingHandshakeResponse => {
                             peer_connection.state = peer_connection.process_handshake_response();
@@ -95,55 +130,65 @@ impl PeerConnection {
                             Err(_) => peer_connection.state = State::Useless(false),
                         },
                         State::Useless(_) => {
-                            peers.lock().unwrap().remove(&peer.ip, peer.port);
+                            peers.lock().unwrap().fail(&peer.ip, peer.port);
                             PeersData::refresh(&peer_connection, true);
                             break;
                         }
                         State::FileDownloaded => {
+                            peers.lock().unwrap().release(&peer.ip, peer.port);
                             break;
                         }
                     }
                 },
-                Err(_) => {
+                // Blocked once the lists were reloaded, it would only be refused again.
+                Err(NetworkingError::BlockedAddress) => {
                     peers.lock().unwrap().remove(&peer.ip, peer.port);
                 }
+                Err(_) => {
+                    peers.lock().unwrap().fail(&peer.ip, peer.port);
+                }
             };
         })
     }
 
-    fn greet(&mut self) -> Result<State, NetworkingError> {
+    fn greet(&mut self) -> Result<Registration, Error> {
         let handshake = BTProtocol::format_handshake_message(
             &self.common_information.info_hash,
             &self.common_information.peer_id,
         );
 
-        self.client.send(&mut self.stream, &handshake)?;
+        self.client
+            .send(&mut self.stream, &handshake)
+            .or(Err(Error::FailedHandshake))?;
 
-        match Message::validate_stream_handshake(
+        let peer_id = Message::validate_stream_handshake(
             &mut self.stream,
             handshake.len(),
             &self.common_information.info_hash,
-        ) {
-            Ok(true) => Ok(State::ProcessingHandshakeResponse),
-            _ => {
-                log::debug!("PeerConnection::greet() - Trying to obtain PeerList lock");
-                let mut peers_guard = self.peers.lock().unwrap();
-                log::debug!("PeerConnection::greet() - PeerList lock obtained");
-
-                peers_guard.remove(&self.peer.ip, self.peer.port);
-                PeersData::refresh(self, true);
-                drop(peers_guard);
-                log::debug!("PeerConnection::greet() - PeerList lock dropped");
-                Err(NetworkingError::FailedPeerConnection)
-            }
-        }
+        )
+        .or(Err(Error::FailedHandshake))?;
+
+        self.common_information
+            .connected_peers
+            .register(&peer_id, Origin::Outgoing)
     }
 
+    // Until the peer tells which pieces it has, its messages may not have arrived yet.
     fn process_handshake_response(&mut self) -> State {
         let mut state = State::Useless(false);
 
+        if self.stream.set_read_timeout(Some(PIECES_TIMEOUT)).is_err() {
+            return state;
+        }
+
         loop {
-            match Message::read_message_from_stream(&mut self.stream, true) {
+            let non_blocking = !matches!(state, State::Useless(_));
+
+            match Message::read_message_from_stream(
+                &mut self.stream,
+                non_blocking,
+                self.common_information.total_pieces,
+            ) {
                 Ok(Message::Have { payload }) => {
                     let maybe_index = Bitfield::index_from_bytes(payload);
 
@@ -214,6 +259,10 @@ impl PeerConnection {
             }
         }
 
+        if self.stream.set_read_timeout(None).is_err() {
+            return State::Useless(false);
+        }
+
         state
     }
 
@@ -227,7 +276,11 @@ impl PeerConnection {
             )
             .unwrap();
 
-        match Message::read_message_from_stream(&mut self.stream, false) {
+        match Message::read_message_from_stream(
+            &mut self.stream,
+            false,
+            self.common_information.total_pieces,
+        ) {
             Ok(Message::Unchoke) => Ok(State::Downloading),
             _ => Err(Error::FailedToUnchoke),
         }
@@ -240,7 +293,7 @@ impl PeerConnection {
 
         let maybe_piece_index = self.peer.has.first_needed_available_piece(&have_guard);
 
-        if maybe_piece_index == None && have_guard.is_complete() {
+        if maybe_piece_index.is_none() && have_guard.is_complete() {
             return Ok(State::FileDownloaded);
         }
 
@@ -251,17 +304,7 @@ impl PeerConnection {
         drop(have_guard);
         log::debug!("PeerConnection::download_piece() - bitfield lock dropped");
 
-        let piece_length = if piece_index == self.common_information.total_pieces - 1 {
-            let total_pieces = (self.common_information.file_length as f64
-                / self.common_information.piece_length as f64)
-                .ceil() as usize;
-
-            (self.common_information.file_length
-                - (self.common_information.piece_length * (total_pieces - 1)) as u64)
-                as usize
-        } else {
-            self.common_information.piece_length
-        };
+        let piece_length = self.common_information.piece_size(piece_index);
 
         let mut piece = Piece::new(piece_index, piece_length, BLOCK_LENGTH as usize);
 
@@ -283,17 +326,26 @@ impl PeerConnection {
                 )
                 .is_err()
             {
+                self.common_information.stats.add_wasted(piece.received());
                 self.bitfield.lock().unwrap().unset_downloading(piece_index);
                 return Err(Error::FailedToSavePiece);
             }
 
             loop {
-                match Message::read_message_from_stream(&mut self.stream, false) {
+                match Message::read_message_from_stream(
+                    &mut self.stream,
+                    false,
+                    self.common_information.total_pieces,
+                ) {
                     Ok(Message::Piece { payload }) => {
+                        self.common_information
+                            .bandwidth
+                            .throttle(Direction::Download, payload.len());
                         piece.add_block(payload[8..].to_vec());
                         break;
                     }
                     Err(_) => {
+                        self.common_information.stats.add_wasted(piece.received());
                         self.bitfield.lock().unwrap().unset_downloading(piece_index);
                         return Err(Error::FailedMessageRead);
                     }
@@ -304,28 +356,131 @@ impl PeerConnection {
 
         if piece.verify(self.common_information.pieces[piece_index].clone()) {
             log::info!("Piece {} verified", piece_index);
-            if piece.save(&self.common_information.file_name).is_ok() {
-                log::info!("Piece {} saved", piece_index);
-                log::debug!("PeerConnection::download_piece() - trying to obtain bitfield lock");
-                let mut have_guard = self.bitfield.lock().unwrap();
-                log::debug!("PeerConnection::download_piece() - bitfield lock obtained");
-                have_guard.set(piece_index);
-                let peers_guard = self.peers.lock().unwrap();
-
-                TorrentData::refresh(&self.common_information, &peers_guard, &have_guard);
-                PeersData::refresh(self, false);
-                self.instant = Instant::now();
-
-                drop(have_guard);
-                log::debug!("PeerConnection::download_piece() - bitfield lock dropped");
-
-                return Ok(State::Downloading);
-            }
 
-            self.bitfield.lock().unwrap().unset_downloading(piece_index);
-            return Err(Error::FailedToSavePiece);
+            let elapsed = self.instant.elapsed().as_millis().max(1) as u64;
+            self.peers.lock().unwrap().record_rate(
+                &self.peer.ip,
+                self.peer.port,
+                piece_length as u64 * 1000 / elapsed,
+            );
+            PeersData::refresh(self, false);
+            self.instant = Instant::now();
+
+            let common_information = self.common_information.clone();
+            let bitfield = Arc::clone(&self.bitfield);
+            let peers = Arc::clone(&self.peers);
+
+            // The piece is only marked as had once it is on disk, so the bitfield and resume
+            // data never claim more than storage holds.
+            self.common_information.disk.write_piece(
+                piece_index,
+                piece.into_data(),
+                move |result| {
+                    let peers_guard = peers.lock().unwrap();
+                    let mut have_guard = bitfield.lock().unwrap();
+
+                    if let Err(error) = result {
+                        log::error!("Failed to save piece {}: {}", piece_index, error);
+                        have_guard.unset_downloading(piece_index);
+                        return;
+                    }
+
+                    log::info!("Piece {} saved", piece_index);
+                    common_information.stats.add_downloaded(piece_length);
+                    have_guard.set(piece_index);
+
+                    TorrentData::refresh(&common_information, &peers_guard, &have_guard);
+
+                    drop(have_guard);
+                    drop(peers_guard);
+
+                    common_information.save_stats();
+                    common_information.save_resume(&bitfield);
+                },
+            );
+
+            return Ok(State::Downloading);
         }
 
+        self.common_information.stats.add_wasted(piece_length);
+        self.bitfield.lock().unwrap().unset_downloading(piece_index);
         Err(Error::InvalidPiece)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_torrent::peer::{ResumeData, ServerConnection};
+    use crate::bit_torrent::Session;
+    use std::fs;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test1_downloads_a_torrent_from_a_seed() {
+        // Pieces of two blocks, the last one shorter than a block.
+        let data: Vec<u8> = (0..5 * BLOCK_LENGTH as usize + 100)
+            .map(|byte| (byte % 251) as u8)
+            .collect();
+        let piece_length = 2 * BLOCK_LENGTH as usize;
+
+        let (seed_session, leech_session) = (Session::new_for_tests(), Session::new_for_tests());
+        let (seed, _seed_torrent, _seed_peers) =
+            CommonInformation::from_data("test1_download", &data, piece_length, &seed_session);
+        let (leech, _leech_torrent, _leech_peers) =
+            CommonInformation::from_data("test1_download", &data, piece_length, &leech_session);
+
+        seed.storage.write(0, &data).unwrap();
+        let mut seed_have = Bitfield::new(seed.total_pieces);
+        (0..seed.total_pieces).for_each(|index| seed_have.set(index));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let peer_id = match Message::read_handshake_from_stream(&mut stream) {
+                Ok(Message::Handshake(_, peer_id)) => peer_id,
+                _ => panic!("Expected a handshake"),
+            };
+            let slot = seed.session.connection_limits.try_accept().unwrap();
+
+            ServerConnection::activate(
+                Arc::new(Mutex::new(seed_have)),
+                Arc::new(Mutex::new(PeerState::AllPieces(String::new()))),
+                seed,
+                stream,
+                slot,
+                peer_id,
+            )
+        });
+
+        let have = Arc::new(Mutex::new(Bitfield::new(leech.total_pieces)));
+        PeerConnection::activate(
+            Arc::clone(&have),
+            Arc::new(Mutex::new(PeerList::new())),
+            leech.clone(),
+            PeerRecord::new_from_address(address, leech.total_pieces),
+            Arc::new(Mutex::new(PeerState::NoPieces(String::new()))),
+            leech.session.connection_limits.try_connect().unwrap(),
+        )
+        .join()
+        .unwrap();
+
+        // Pieces are saved in the background, by callbacks holding on to `have` until done.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Arc::strong_count(&have) > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(have.lock().unwrap().is_complete());
+        assert_eq!(leech.storage.read(0, data.len()).unwrap(), data);
+        assert_eq!(leech.stats.downloaded(), data.len() as u64);
+        assert_eq!(
+            ResumeData::load(&leech.resume_pathname()).unwrap().have,
+            have.lock().unwrap().get()
+        );
+
+        server.join().unwrap().join().unwrap();
+        fs::remove_dir_all(&leech.temp_directory).unwrap();
+    }
+}
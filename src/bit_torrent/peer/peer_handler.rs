@@ -1,11 +1,11 @@
-use super::{
-    file_system::File, Bitfield, CommonInformation, NetworkingError, PeerConnection, PeerList,
-    PeerState,
-};
+use super::{Bitfield, CommonInformation, NetworkingError, PeerConnection, PeerList, PeerState};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use std::sync::{Arc, Mutex};
 
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
 pub struct PeerHandler {
     bitfield: Arc<Mutex<Bitfield>>,
     peers: Arc<Mutex<PeerList>>,
@@ -38,64 +38,65 @@ impl PeerHandler {
                 }
 
                 let bitfield_guard = self.bitfield.lock().unwrap();
+                let (started, complete) = (!bitfield_guard.is_null(), bitfield_guard.is_complete());
+
+                // Completing syncs and renames every file, which must not hold up the threads
+                // waiting on the bitfield.
+                drop(bitfield_guard);
 
-                if !bitfield_guard.is_null() {
+                if started {
                     let mut state_guard = self.state.lock().unwrap();
                     if let PeerState::NoPieces(_) = &*state_guard {
                         *state_guard = state_guard.upgrade(None);
                     }
                 }
 
-                if bitfield_guard.is_complete() {
-                    if bitfield_guard.is_complete() {
-                        File::join_pieces(
-                            format!(
-                                "{}/{}",
-                                self.common_information.temp_directory,
-                                self.common_information.file_name
-                            ),
-                            format!(
-                                "{}/{}",
-                                self.common_information.download_directory,
-                                self.common_information.file_name
-                            ),
-                        )
-                        .unwrap();
-                    }
+                // Kept running once seeding, a recheck finding pieces missing resumes the download.
+                if complete {
+                    let downloading =
+                        matches!(&*self.state.lock().unwrap(), PeerState::SomePieces(_));
 
-                    let mut state_guard = self.state.lock().unwrap();
-                    if let PeerState::SomePieces(_) = &*state_guard {
-                        *state_guard = state_guard.upgrade(Some(format!(
-                            "{}/{}",
-                            self.common_information.download_directory,
-                            self.common_information.file_name
-                        )));
+                    if downloading {
+                        self.seed();
                     }
 
-                    break;
+                    thread::sleep(IDLE_WAIT);
+                    continue;
+                }
+
+                // Pieces are only asked for once a recheck has found those already stored.
+                if self.common_information.checking.lock().unwrap().is_some() {
+                    thread::sleep(IDLE_WAIT);
+                    continue;
                 }
-                drop(bitfield_guard);
 
                 let peers_guard = self.peers.lock();
                 match peers_guard {
                     Ok(mut peers_guard) => {
-                        if peers_guard.active() == 30 {
+                        let connection_limits = &self.common_information.session.connection_limits;
+
+                        if peers_guard.active() >= connection_limits.max_connections_per_torrent() {
                             drop(peers_guard);
-                            thread::yield_now();
+                            thread::sleep(IDLE_WAIT);
                             continue;
                         }
 
-                        let maybe_peer = peers_guard.pop();
+                        let maybe_slot = connection_limits.try_connect();
+                        let maybe_peer = maybe_slot.as_ref().and_then(|_| peers_guard.pop());
 
                         drop(peers_guard);
-                        if let Some(peer_in_use) = maybe_peer {
-                            connections.push(PeerConnection::activate(
-                                Arc::clone(&self.bitfield),
-                                Arc::clone(&self.peers),
-                                self.common_information.clone(),
-                                peer_in_use.clone(),
-                                Arc::clone(&self.state),
-                            ));
+                        match (maybe_slot, maybe_peer) {
+                            (Some(slot), Some(peer_in_use)) => {
+                                connections.push(PeerConnection::activate(
+                                    Arc::clone(&self.bitfield),
+                                    Arc::clone(&self.peers),
+                                    self.common_information.clone(),
+                                    peer_in_use.clone(),
+                                    Arc::clone(&self.state),
+                                    slot,
+                                ));
+                            }
+                            _ => thread::sleep(IDLE_WAIT),
                         }
                     }
                     Err(_) => {
@@ -111,4 +112,22 @@ impl PeerHandler {
             }
         })
     }
+
+    fn seed(&self) {
+        if let Err(error) = self.common_information.storage.complete() {
+            log::error!(
+                "Failed to complete {}: {}",
+                self.common_information.file_name,
+                error
+            );
+        }
+
+        let mut state_guard = self.state.lock().unwrap();
+        if let PeerState::SomePieces(_) = &*state_guard {
+            *state_guard = state_guard.upgrade(Some(format!(
+                "{}/{}",
+                self.common_information.download_directory, self.common_information.file_name
+            )));
+        }
+    }
 }
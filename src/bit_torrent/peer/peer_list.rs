@@ -1,9 +1,20 @@
-use super::PeerRecord;
+use super::{
+    canonical_priority, IpFilter, PeerRecord, MAX_PEER_FAILURES, RECONNECT_BASE_DELAY,
+    RECONNECT_MAX_DELAY,
+};
+use std::cmp::Reverse;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+// The most promising available peer is handed out first: LAN peers, then those that failed
+// the least, then the fastest so far, then by BEP 40 canonical priority.
 #[derive(Debug, Default)]
 pub struct PeerList {
     peers: Vec<PeerRecord>,
     in_use: usize,
+    own_address: Option<SocketAddr>,
+    ip_filter: Arc<IpFilter>,
 }
 
 impl PeerList {
@@ -20,49 +31,265 @@ impl PeerList {
     }
 
     pub fn new() -> Self {
+        Self::new_with(None, Arc::default())
+    }
+
+    pub fn new_with(own_address: Option<SocketAddr>, ip_filter: Arc<IpFilter>) -> Self {
         Self {
             peers: Vec::new(),
             in_use: 0,
+            own_address,
+            ip_filter,
         }
     }
 
+    // Blocked addresses are left out.
     pub fn update(&mut self, incoming_peers: Vec<PeerRecord>) {
-        for peer in incoming_peers {
-            if self
-                .peers
-                .iter()
-                .any(|some_peer| some_peer.ip == peer.ip && some_peer.port == peer.port)
-            {
+        for mut peer in incoming_peers {
+            if peer.ip.parse().is_ok_and(|ip| self.ip_filter.contains(ip)) {
                 continue;
-            } else {
-                self.peers.push(peer);
+            }
+
+            match self.find(&peer.ip, peer.port) {
+                Some(known_peer) => known_peer.local |= peer.local,
+                None => {
+                    peer.priority = self.priority_of(&peer);
+                    self.peers.push(peer);
+                }
             }
         }
     }
 
     pub fn pop(&mut self) -> Option<PeerRecord> {
-        for peer in &mut self.peers {
-            if !peer.in_use {
-                peer.in_use = true;
-                self.in_use += 1;
-                return Some(peer.clone());
-            }
+        let peer = self
+            .peers
+            .iter_mut()
+            .filter(|peer| peer.is_available())
+            .max_by_key(|peer| {
+                (
+                    peer.local || is_lan(&peer.ip),
+                    Reverse(peer.failures),
+                    peer.download_rate,
+                    peer.priority,
+                )
+            })?;
+
+        peer.in_use = true;
+        self.in_use += 1;
+
+        Some(peer.clone())
+    }
+
+    pub fn record_rate(&mut self, ip: &str, port: i64, bytes_per_second: u64) {
+        if let Some(peer) = self.find(ip, port) {
+            peer.download_rate = bytes_per_second;
         }
+    }
 
-        None
+    pub fn succeed(&mut self, ip: &str, port: i64) {
+        if let Some(peer) = self.find(ip, port) {
+            peer.failures = 0;
+            peer.retry_at = None;
+        }
+    }
+
+    pub fn release(&mut self, ip: &str, port: i64) {
+        if let Some(index) = self.position(ip, port) {
+            self.unset_in_use(index);
+        }
+    }
+
+    // Failed peers are retried with exponential backoff and forgotten after repeated failures.
+    pub fn fail(&mut self, ip: &str, port: i64) {
+        if let Some(index) = self.position(ip, port) {
+            self.unset_in_use(index);
+
+            let peer = &mut self.peers[index];
+            peer.failures += 1;
+
+            if peer.failures >= MAX_PEER_FAILURES {
+                log::debug!("PeerList::fail() - forgetting peer {}:{}", ip, port);
+                self.peers.swap_remove(index);
+                return;
+            }
+
+            let delay = RECONNECT_BASE_DELAY
+                .saturating_mul(2_u64.saturating_pow(peer.failures - 1))
+                .min(RECONNECT_MAX_DELAY);
+
+            peer.retry_at = Some(Instant::now() + Duration::from_secs(delay));
+        }
     }
 
     pub fn remove(&mut self, ip: &str, port: i64) {
-        if let Some(index) = self
-            .peers
+        if let Some(index) = self.position(ip, port) {
+            self.unset_in_use(index);
+            self.peers.swap_remove(index);
+        }
+    }
+
+    fn priority_of(&self, peer: &PeerRecord) -> u32 {
+        let own_address = match self.own_address {
+            Some(own_address) => own_address,
+            None => return 0,
+        };
+
+        match peer.ip.parse::<IpAddr>() {
+            Ok(ip) => {
+                canonical_priority(own_address, SocketAddr::new(ip, peer.port as u16)).unwrap_or(0)
+            }
+            Err(_) => 0,
+        }
+    }
+
+    fn position(&self, ip: &str, port: i64) -> Option<usize> {
+        self.peers
             .iter()
             .position(|peer| peer.ip == *ip && peer.port == port)
-        {
-            if self.peers[index].in_use {
-                self.in_use -= 1;
-            }
+    }
 
-            self.peers.swap_remove(index);
+    fn find(&mut self, ip: &str, port: i64) -> Option<&mut PeerRecord> {
+        self.peers
+            .iter_mut()
+            .find(|peer| peer.ip == *ip && peer.port == port)
+    }
+
+    fn unset_in_use(&mut self, index: usize) {
+        if self.peers[index].in_use {
+            self.peers[index].in_use = false;
+            self.in_use -= 1;
+        }
+    }
+}
+
+fn is_lan(ip: &str) -> bool {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        Ok(IpAddr::V6(ip)) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Bitfield;
+    use super::*;
+
+    fn peer(port: i64) -> PeerRecord {
+        PeerRecord {
+            ip: String::from("127.0.0.1"),
+            port,
+            has: Bitfield::new(1),
+            ipv6: false,
+            in_use: false,
+            failures: 0,
+            retry_at: None,
+            local: false,
+            priority: 0,
+            download_rate: 0,
         }
     }
+
+    #[test]
+    fn test1_failed_peer_is_retried_after_backoff() {
+        let mut peers = PeerList::new();
+        peers.update(vec![peer(1)]);
+
+        let popped = peers.pop().unwrap();
+        peers.fail(&popped.ip, popped.port);
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers.active(), 0);
+        assert!(peers.pop().is_none());
+
+        peers.peers[0].retry_at = Some(Instant::now());
+
+        assert!(peers.pop().is_some());
+    }
+
+    #[test]
+    fn test2_backoff_doubles_with_each_failure() {
+        let mut peers = PeerList::new();
+        peers.update(vec![peer(1)]);
+
+        peers.fail("127.0.0.1", 1);
+        let first = peers.peers[0].retry_at.unwrap();
+        peers.fail("127.0.0.1", 1);
+        let second = peers.peers[0].retry_at.unwrap();
+
+        assert!(second - first >= Duration::from_secs(RECONNECT_BASE_DELAY));
+    }
+
+    #[test]
+    fn test3_peer_is_forgotten_after_too_many_failures() {
+        let mut peers = PeerList::new();
+        peers.update(vec![peer(1), peer(2)]);
+
+        for _ in 0..MAX_PEER_FAILURES {
+            peers.fail("127.0.0.1", 1);
+        }
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers.peers[0].port, 2);
+    }
+
+    #[test]
+    fn test4_success_resets_failures() {
+        let mut peers = PeerList::new();
+        peers.update(vec![peer(1)]);
+
+        peers.fail("127.0.0.1", 1);
+        peers.succeed("127.0.0.1", 1);
+
+        assert_eq!(peers.peers[0].failures, 0);
+        assert!(peers.pop().is_some());
+    }
+
+    #[test]
+    fn test5_blocked_peers_are_left_out() {
+        let path = std::env::temp_dir().join(format!("peer_list_test_{}", std::process::id()));
+        std::fs::write(&path, "8.8.8.0/24").unwrap();
+        let ip_filter = IpFilter::new_with(vec![path.clone()]);
+        let mut peers = PeerList::new_with(None, Arc::clone(&ip_filter));
+
+        let remote = |ip: &str, port: i64| PeerRecord {
+            ip: ip.to_string(),
+            ..peer(port)
+        };
+
+        peers.update(vec![remote("8.8.8.8", 1), remote("8.8.4.4", 2)]);
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers.pop().unwrap().port, 2);
+        assert_eq!(ip_filter.blocked(), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test6_most_promising_peers_are_popped_first() {
+        let mut peers =
+            PeerList::new_with(Some("123.213.32.10:6881".parse().unwrap()), Arc::default());
+
+        let remote = |ip: &str, port: i64| PeerRecord {
+            ip: ip.to_string(),
+            ..peer(port)
+        };
+
+        peers.update(vec![
+            remote("98.76.54.32", 1),
+            remote("123.213.32.234", 2),
+            remote("8.8.8.8", 3),
+            remote("192.168.1.5", 4),
+        ]);
+        peers.record_rate("8.8.8.8", 3, 100_000);
+        peers.fail("123.213.32.234", 2);
+        peers.peers[1].retry_at = None;
+
+        assert_eq!(peers.peers[0].priority, 0xec2d7224);
+
+        let order: Vec<i64> = (0..4).map(|_| peers.pop().unwrap().port).collect();
+
+        assert_eq!(order, vec![4, 3, 1, 2]);
+    }
 }
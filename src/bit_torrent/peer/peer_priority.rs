@@ -0,0 +1,95 @@
+use std::net::{IpAddr, SocketAddr};
+
+// BEP 40 hashes with CRC32-C.
+const CASTAGNOLI: u32 = 0x82f6_3b78;
+
+// Addresses are masked so that peers in the same subnet cannot pick priorities for
+// themselves. Only when both addresses are the same are the ports hashed instead.
+pub fn canonical_priority(own: SocketAddr, peer: SocketAddr) -> Option<u32> {
+    let (own_ip, peer_ip) = match (own.ip(), peer.ip()) {
+        (IpAddr::V4(own), IpAddr::V4(peer)) => (own.octets().to_vec(), peer.octets().to_vec()),
+        (IpAddr::V6(own), IpAddr::V6(peer)) => (own.octets().to_vec(), peer.octets().to_vec()),
+        _ => return None,
+    };
+
+    if own_ip == peer_ip {
+        let mut ports = [own.port(), peer.port()];
+        ports.sort_unstable();
+
+        return Some(crc32c(
+            &[ports[0].to_be_bytes(), ports[1].to_be_bytes()].concat(),
+        ));
+    }
+
+    // Bytes kept whole: the first 2 of an IPv4 address (6 of an IPv6 one), plus one more for
+    // every further byte both have in common.
+    let network_length = match own_ip.len() {
+        4 => 2,
+        _ => 6,
+    };
+    let shared = own_ip
+        .iter()
+        .zip(&peer_ip)
+        .take_while(|(own, peer)| own == peer)
+        .count();
+    let kept = network_length.max(shared + 1);
+
+    let mask = |ip: Vec<u8>| -> Vec<u8> {
+        ip.into_iter()
+            .enumerate()
+            .map(|(index, byte)| match index < kept {
+                true => byte,
+                false => byte & 0x55,
+            })
+            .collect()
+    };
+
+    let mut ips = [mask(own_ip), mask(peer_ip)];
+    ips.sort_unstable();
+
+    Some(crc32c(&ips.concat()))
+}
+
+fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ CASTAGNOLI,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_priorities_match_the_bep_examples() {
+        let address = |address: &str| address.parse::<SocketAddr>().unwrap();
+
+        assert_eq!(
+            canonical_priority(address("123.213.32.10:0"), address("98.76.54.32:0")),
+            Some(0xec2d7224)
+        );
+        assert_eq!(
+            canonical_priority(address("123.213.32.10:0"), address("123.213.32.234:0")),
+            Some(0x99568189)
+        );
+        assert_eq!(
+            canonical_priority(address("98.76.54.32:0"), address("123.213.32.10:0")),
+            canonical_priority(address("123.213.32.10:0"), address("98.76.54.32:0"))
+        );
+        assert_eq!(
+            canonical_priority(address("123.213.32.10:0"), address("[::1]:0")),
+            None
+        );
+    }
+}
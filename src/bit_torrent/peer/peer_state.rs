@@ -19,4 +19,18 @@ impl PeerState {
             Self::Broken => Self::Broken,
         }
     }
+
+    // Once pieces turned out to be missing, `started` if any piece is left.
+    pub fn downgrade(&self, started: bool) -> Self {
+        match (self, started) {
+            (Self::SomePieces(pathname) | Self::AllPieces(pathname), true) => {
+                Self::SomePieces(pathname.to_string())
+            }
+            (
+                Self::NoPieces(pathname) | Self::SomePieces(pathname) | Self::AllPieces(pathname),
+                _,
+            ) => Self::NoPieces(pathname.to_string()),
+            (Self::Broken, _) => Self::Broken,
+        }
+    }
 }
@@ -0,0 +1,224 @@
+use super::{Bitfield, CommonInformation, PeerList, PeerState};
+use crate::frontend::torrents::TorrentData;
+use crate::utils::env_setting;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// Full hash check of the data a torrent has in storage, such as files it was added to seed.
+pub struct Recheck;
+
+impl Recheck {
+    // Moves straight to seeding if nothing is missing and back to downloading otherwise. Returns
+    // how many pieces were found, None if a recheck of the torrent is already running.
+    pub fn run(
+        common_information: &CommonInformation,
+        have: &Arc<Mutex<Bitfield>>,
+        peers: &Arc<Mutex<PeerList>>,
+        state: &Arc<Mutex<PeerState>>,
+    ) -> Option<usize> {
+        {
+            let mut checking = common_information.checking.lock().unwrap();
+
+            if checking.is_some() {
+                return None;
+            }
+
+            *checking = Some(0.0);
+        }
+
+        let total_pieces = common_information.total_pieces;
+        let threads = threads();
+        let next_piece = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::channel();
+
+        log::info!(
+            "Rechecking {} on {} threads",
+            common_information.file_name,
+            threads
+        );
+
+        let found = thread::scope(|scope| {
+            for _ in 0..threads {
+                let tx = tx.clone();
+                let next_piece = &next_piece;
+
+                scope.spawn(move || loop {
+                    let piece_index = next_piece.fetch_add(1, Ordering::Relaxed);
+
+                    if piece_index >= total_pieces
+                        || tx
+                            .send((piece_index, verify(common_information, piece_index)))
+                            .is_err()
+                    {
+                        break;
+                    }
+                });
+            }
+
+            drop(tx);
+
+            let mut checked = 0;
+            let mut found = 0;
+            let mut reported = Instant::now();
+
+            for (piece_index, valid) in rx {
+                checked += 1;
+
+                // A piece that failed may have been downloaded while it was being hashed, it is
+                // only dropped if it still fails. It is hashed again without holding the bitfield.
+                let had = have.lock().unwrap().has(piece_index);
+                let valid = valid || (had && verify(common_information, piece_index));
+
+                match valid {
+                    true => {
+                        have.lock().unwrap().set(piece_index);
+                        found += 1;
+                    }
+                    false => have.lock().unwrap().unset(piece_index),
+                }
+
+                if reported.elapsed() >= PROGRESS_INTERVAL {
+                    *common_information.checking.lock().unwrap() =
+                        Some(checked as f64 * 100.0 / total_pieces as f64);
+
+                    refresh(common_information, have, peers);
+                    reported = Instant::now();
+                }
+            }
+
+            found
+        });
+
+        *common_information.checking.lock().unwrap() = None;
+
+        common_information.save_resume(have);
+
+        let (started, complete) = {
+            let have_guard = have.lock().unwrap();
+
+            (!have_guard.is_null(), have_guard.is_complete())
+        };
+
+        match complete {
+            true => seed(common_information, state),
+            // A seed whose data was damaged or deleted goes back to downloading what is missing.
+            false => {
+                let mut state_guard = state.lock().unwrap();
+                *state_guard = state_guard.downgrade(started);
+            }
+        }
+
+        refresh(common_information, have, peers);
+
+        log::info!(
+            "Recheck of {} found {} of {} pieces",
+            common_information.file_name,
+            found,
+            total_pieces
+        );
+
+        Some(found)
+    }
+}
+
+fn verify(common_information: &CommonInformation, piece_index: usize) -> bool {
+    matches!(
+        common_information.storage.hash_piece(piece_index),
+        Ok(hash) if hash[..] == common_information.pieces[piece_index][..]
+    )
+}
+
+fn refresh(
+    common_information: &CommonInformation,
+    have: &Arc<Mutex<Bitfield>>,
+    peers: &Arc<Mutex<PeerList>>,
+) {
+    let peers_guard = peers.lock().unwrap();
+    let have_guard = have.lock().unwrap();
+
+    TorrentData::refresh(common_information, &peers_guard, &have_guard);
+}
+
+fn seed(common_information: &CommonInformation, state: &Arc<Mutex<PeerState>>) {
+    if let Err(error) = common_information.storage.complete() {
+        log::error!(
+            "Failed to complete {}: {}",
+            common_information.file_name,
+            error
+        );
+        return;
+    }
+
+    let mut state_guard = state.lock().unwrap();
+
+    if let PeerState::NoPieces(_) | PeerState::SomePieces(_) = &*state_guard {
+        *state_guard = PeerState::AllPieces(format!(
+            "{}/{}",
+            common_information.download_directory, common_information.file_name
+        ));
+    }
+}
+
+fn threads() -> usize {
+    match env_setting("RECHECK_THREADS", 0) {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_torrent::Session;
+    use std::fs;
+
+    #[test]
+    fn test1_recheck_finds_the_stored_pieces() {
+        let data: Vec<u8> = (0..10).collect();
+        let session = Session::new_for_tests();
+        let (common_information, _rx_torrent, _rx_peers) =
+            CommonInformation::from_data("test1_recheck", &data, 4, &session);
+
+        let have = Arc::new(Mutex::new(Bitfield::new(3)));
+        let peers = Arc::new(Mutex::new(PeerList::new()));
+        let state = Arc::new(Mutex::new(PeerState::AllPieces(String::new())));
+        let storage = &common_information.storage;
+
+        storage.write(0, &data).unwrap();
+        storage.write(5, &[0xff]).unwrap();
+
+        assert_eq!(
+            Recheck::run(&common_information, &have, &peers, &state),
+            Some(2)
+        );
+        {
+            let have_guard = have.lock().unwrap();
+            assert!(have_guard.has(0) && !have_guard.has(1) && have_guard.has(2));
+        }
+        assert!(matches!(&*state.lock().unwrap(), PeerState::SomePieces(_)));
+
+        storage.write(5, &[5]).unwrap();
+
+        assert_eq!(
+            Recheck::run(&common_information, &have, &peers, &state),
+            Some(3)
+        );
+        assert!(have.lock().unwrap().is_complete());
+        assert!(matches!(&*state.lock().unwrap(), PeerState::AllPieces(_)));
+
+        // As if another recheck of the torrent were still running.
+        *common_information.checking.lock().unwrap() = Some(50.0);
+
+        assert_eq!(
+            Recheck::run(&common_information, &have, &peers, &state),
+            None
+        );
+
+        fs::remove_dir_all(&common_information.temp_directory).unwrap();
+    }
+}
@@ -1,36 +1,71 @@
 use std::{
+    fs,
     sync::{mpsc::Receiver, Arc, Mutex},
     thread::{self, JoinHandle},
 };
 
-use super::PeerState;
+use super::{CommonInformation, PeerState};
+use crate::utils::env_setting;
 
+// `End` is sent when the application closes, `Remove` when the torrent is removed from the list.
 pub struct RemoveTorrent;
 
 impl RemoveTorrent {
     pub fn wait_signal(
-        pathname: String,
+        common_information: CommonInformation,
         remove_rx: Receiver<String>,
         state: Arc<Mutex<PeerState>>,
+        recheck: impl Fn() + Send + 'static,
     ) -> JoinHandle<()> {
         thread::spawn(move || loop {
             if let PeerState::Broken = &*state.lock().unwrap() {
                 break;
             }
 
-            let maybe_recv = remove_rx.try_recv();
-            if let Ok(recv) = maybe_recv {
-                if recv == "End" {
+            if let Ok(recv) = remove_rx.try_recv() {
+                if recv == "End" || recv == "Remove" {
                     log::info!(
                         "RemoveTorrent::wait_signal(): Removing torrent {}",
-                        pathname
+                        common_information.torrent_pathname
                     );
                     *state.lock().unwrap() = PeerState::Broken;
+
+                    if recv == "Remove" {
+                        Self::remove(&common_information);
+                    }
+
                     break;
                 }
+
+                if recv == "Recheck" {
+                    recheck();
+                }
             }
 
             thread::yield_now();
         })
     }
+
+    fn remove(common_information: &CommonInformation) {
+        common_information
+            .session
+            .bandwidth
+            .remove_torrent(&common_information.torrent_pathname);
+
+        if !env_setting("REMOVE_DELETES_DATA", false) {
+            return;
+        }
+
+        match common_information.storage.delete() {
+            Ok(()) => log::info!("Deleted the files of {}", common_information.file_name),
+            Err(error) => log::error!(
+                "Failed to delete the files of {}: {}",
+                common_information.file_name,
+                error
+            ),
+        }
+
+        // Resume data about files that are gone would only cost a recheck if added again.
+        let _ = fs::remove_file(common_information.resume_pathname());
+    }
 }
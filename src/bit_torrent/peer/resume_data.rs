@@ -0,0 +1,123 @@
+use crate::file_system::FileState;
+use crate::utils::{decode_hex, encode_hex};
+use std::fmt::Write;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Kept across restarts in a KEY,VALUE file next to the torrent's stats.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResumeData {
+    pub info_hash: Vec<u8>,
+    pub have: Vec<u8>,
+    pub files: Vec<FileState>,
+}
+
+impl ResumeData {
+    // None if there is none or it is corrupted.
+    pub fn load(pathname: &str) -> Option<Self> {
+        let contents = fs::read_to_string(pathname).ok()?;
+
+        let mut info_hash = None;
+        let mut have = None;
+        let mut files = vec![];
+
+        for line in contents.lines() {
+            let (key, value) = match line.split_once(',') {
+                Some((key, value)) => (key, value.trim()),
+                None => continue,
+            };
+
+            match key {
+                "INFO_HASH" => info_hash = Some(decode_hex(value)?),
+                "HAVE" => have = Some(decode_hex(value)?),
+                "FILE" => files.push(decode_file_state(value)?),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            info_hash: info_hash?,
+            have: have?,
+            files,
+        })
+    }
+
+    pub fn save(&self, pathname: &str) -> Result<(), Error> {
+        if let Some(directory) = Path::new(pathname).parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut contents = format!(
+            "INFO_HASH,{}\nHAVE,{}",
+            encode_hex(&self.info_hash),
+            encode_hex(&self.have)
+        );
+
+        for state in &self.files {
+            contents.push_str("\nFILE,");
+
+            if let Some((length, modified)) = state {
+                let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+                write!(&mut contents, "{};{}", length, modified.as_nanos())
+                    .expect("Unable to write file state");
+            }
+        }
+
+        let temp_pathname = format!("{}.tmp", pathname);
+
+        fs::write(&temp_pathname, contents)?;
+        fs::rename(temp_pathname, pathname)
+    }
+
+    // Missing files are never trusted.
+    pub fn changed(&self, index: usize, now: &FileState) -> bool {
+        match (self.files.get(index), now) {
+            (Some(Some(saved)), Some(now)) => saved != now,
+            _ => true,
+        }
+    }
+}
+
+// `length;nanoseconds since the epoch`, or nothing for a missing file.
+fn decode_file_state(value: &str) -> Option<FileState> {
+    if value.is_empty() {
+        return Some(None);
+    }
+
+    let (length, modified) = value.split_once(';')?;
+    let modified = UNIX_EPOCH + Duration::from_nanos(modified.parse().ok()?);
+
+    Some(Some((length.parse().ok()?, modified)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test1_progress_survives_a_restart() {
+        let pathname = env::temp_dir().join("sitos_test1_progress_survives_a_restart.resume");
+        let pathname = pathname.to_str().unwrap();
+        let modified = UNIX_EPOCH + Duration::from_nanos(1_650_000_000_123_456_789);
+
+        let data = ResumeData {
+            info_hash: vec![0, 1, 0xab, 0xff],
+            have: vec![0b1010_0000, 0x0f],
+            files: vec![Some((5, modified)), None],
+        };
+        data.save(pathname).unwrap();
+
+        let loaded = ResumeData::load(pathname).unwrap();
+
+        assert_eq!(loaded, data);
+        assert!(!loaded.changed(0, &Some((5, modified))));
+        assert!(loaded.changed(0, &Some((6, modified))));
+        assert!(loaded.changed(1, &None));
+        assert!(loaded.changed(2, &Some((5, modified))));
+
+        fs::remove_file(pathname).unwrap();
+    }
+}
@@ -1,5 +1,9 @@
-use super::{file_system::File, Bitfield, CommonInformation, Message, PeerState, UploadState};
-use std::io::Write;
+use super::{
+    Bitfield, CommonInformation, ConnectionSlot, Direction, Error, Message, Origin, PeerState,
+    Registration, UploadState, BLOCK_LENGTH, MAX_QUEUED_REQUESTS,
+};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Write};
 use std::net::TcpStream;
 use std::thread;
 
@@ -10,6 +14,7 @@ pub struct ServerConnection {
     peer_state: Arc<Mutex<PeerState>>,
     common_information: CommonInformation,
     state: UploadState,
+    requests: VecDeque<(u32, u32, u32)>,
 }
 
 impl ServerConnection {
@@ -23,6 +28,7 @@ impl ServerConnection {
             state: UploadState::UnknownPeer,
             bitfield,
             common_information,
+            requests: VecDeque::new(),
         }
     }
 
@@ -31,106 +37,185 @@ impl ServerConnection {
         peer_state: Arc<Mutex<PeerState>>,
         common_information: CommonInformation,
         mut stream: TcpStream,
+        slot: ConnectionSlot,
+        peer_id: Vec<u8>,
     ) -> thread::JoinHandle<()> {
         let mut connection = Self::new(bitfield, peer_state, common_information);
 
-        thread::spawn(move || loop {
-            let state_guard = connection.peer_state.lock().unwrap();
+        thread::spawn(move || {
+            // Held for as long as the connection is served.
+            let _slot = slot;
+            let mut registration: Option<Registration> = None;
 
-            if let PeerState::Broken = &*state_guard {
-                break;
-            }
+            loop {
+                let state_guard = connection.peer_state.lock().unwrap();
 
-            drop(state_guard);
+                if let PeerState::Broken = &*state_guard {
+                    break;
+                }
 
-            let current_state = connection.state.clone();
+                drop(state_guard);
 
-            match current_state {
-                UploadState::UnknownPeer => {
-                    if let Ok(new_state) =
-                        Self::validate_peer(&mut stream, connection.common_information.peer_id)
-                    {
-                        connection.state = new_state;
-                    } else {
-                        connection.state = UploadState::Useless;
-                    }
+                if registration
+                    .as_ref()
+                    .is_some_and(|registration| !registration.is_active())
+                {
+                    log::debug!("ServerConnection::activate() - closing duplicate connection");
+                    break;
                 }
-                UploadState::AwaitingResponse => {
-                    if let Ok(new_state) = connection.send_handshake_response(&mut stream) {
-                        connection.state = new_state;
-                    } else {
-                        connection.state = UploadState::Useless;
+
+                let current_state = connection.state.clone();
+
+                match current_state {
+                    UploadState::UnknownPeer => {
+                        if let Ok(new_registration) =
+                            connection.validate_peer(&mut stream, &peer_id)
+                        {
+                            registration = Some(new_registration);
+                            connection.state = UploadState::AwaitingResponse;
+                        } else {
+                            connection.state = UploadState::Useless;
+                        }
                     }
-                }
-                UploadState::Uploading => {
-                    if let Ok(new_state) = connection.serve_file(&mut stream) {
-                        connection.state = new_state;
-                    } else {
-                        connection.state = UploadState::Useless;
+                    UploadState::AwaitingResponse => {
+                        if let Ok(new_state) = connection.send_handshake_response(&mut stream) {
+                            connection.state = new_state;
+                        } else {
+                            connection.state = UploadState::Useless;
+                        }
+                    }
+                    UploadState::Uploading => match connection.serve_file(&mut stream) {
+                        Ok(new_state) => connection.state = new_state,
+                        Err(error) => {
+                            log::debug!(
+                                "ServerConnection::activate() - dropping peer: {:?}",
+                                error
+                            );
+                            connection.state = UploadState::Useless;
+                        }
+                    },
+                    UploadState::Useless => {
+                        break;
                     }
-                }
-                UploadState::Useless => {
-                    break;
                 }
             }
         })
     }
 
-    fn serve_file(&self, stream: &mut TcpStream) -> Result<UploadState, ()> {
-        if let Ok(Message::Request {
-            piece_index,
-            block_offset,
-            block_length,
-        }) = Message::read_message_from_stream(stream, false)
-        {
-            let bitfield_guard = self.bitfield.lock().unwrap();
-
-            if bitfield_guard.has(piece_index as usize) {
-                let state_guard = self.peer_state.lock().unwrap();
-
-                let maybe_block = match &*state_guard {
-                    PeerState::SomePieces(pathname) => {
-                        let mut file = File::new(format!(
-                            "{}/{}.piece{}",
-                            pathname, self.common_information.file_name, piece_index
-                        ));
-
-                        Some(file.get_block(
-                            0,
-                            self.common_information.piece_length,
-                            block_length as usize,
-                            block_offset as usize,
-                        ))
-                    }
-                    PeerState::AllPieces(pathname) => {
-                        let mut file = File::new(pathname);
-
-                        Some(file.get_block(
-                            piece_index as usize,
-                            self.common_information.piece_length,
-                            block_length as usize,
-                            block_offset as usize,
-                        ))
-                    }
-                    _ => None,
-                };
+    fn serve_file(&mut self, stream: &mut TcpStream) -> Result<UploadState, Error> {
+        self.queue_requests(stream)?;
+
+        if let Some((piece_index, block_offset, block_length)) = self.requests.pop_front() {
+            self.serve_block(stream, piece_index, block_offset, block_length)?;
+        }
+
+        Ok(UploadState::Uploading)
+    }
 
-                if let Some(block) = maybe_block {
-                    let mut message = vec![];
+    // Only blocks when there is nothing left to serve.
+    fn queue_requests(&mut self, stream: &mut TcpStream) -> Result<(), Error> {
+        loop {
+            if !self.requests.is_empty() && !message_waiting(stream)? {
+                return Ok(());
+            }
 
-                    message.extend_from_slice(&piece_index.to_be_bytes());
-                    message.extend_from_slice(&block_offset.to_be_bytes());
-                    message.extend_from_slice(&block);
+            match Message::read_message_from_stream(
+                stream,
+                false,
+                self.common_information.total_pieces,
+            ) {
+                Ok(Message::Request {
+                    piece_index,
+                    block_offset,
+                    block_length,
+                }) => {
+                    self.validate_request(piece_index, block_offset, block_length)?;
+
+                    if self.requests.len() >= MAX_QUEUED_REQUESTS {
+                        return Err(Error::TooManyRequests);
+                    }
 
-                    let response = Message::Piece { payload: message };
-                    stream
-                        .write_all(&response.parse().expect("Failed to parse piece message"))
-                        .or(Err(()))?;
+                    self.requests
+                        .push_back((piece_index, block_offset, block_length));
                 }
+                Ok(_) => {}
+                Err(_) => return Err(Error::FailedMessageRead),
             }
-        };
+        }
+    }
 
-        Ok(UploadState::Uploading)
+    fn validate_request(
+        &self,
+        piece_index: u32,
+        block_offset: u32,
+        block_length: u32,
+    ) -> Result<(), Error> {
+        let piece_index = piece_index as usize;
+
+        if piece_index >= self.common_information.total_pieces
+            || block_length == 0
+            || block_length > BLOCK_LENGTH
+        {
+            return Err(Error::InvalidRequest);
+        }
+
+        let block_end = block_offset as usize + block_length as usize;
+
+        if block_end > self.common_information.piece_size(piece_index) {
+            return Err(Error::InvalidRequest);
+        }
+
+        Ok(())
+    }
+
+    fn serve_block(
+        &self,
+        stream: &mut TcpStream,
+        piece_index: u32,
+        block_offset: u32,
+        block_length: u32,
+    ) -> Result<(), Error> {
+        if !self.bitfield.lock().unwrap().has(piece_index as usize) {
+            return Ok(());
+        }
+
+        let serving = matches!(
+            &*self.peer_state.lock().unwrap(),
+            PeerState::SomePieces(_) | PeerState::AllPieces(_)
+        );
+
+        // Read through the disk pool with no lock held, a miss waits on the disk.
+        let maybe_block = serving.then(|| {
+            self.common_information.disk.read_block(
+                piece_index as usize,
+                block_offset as usize,
+                block_length as usize,
+            )
+        });
+
+        if let Some(block) = maybe_block {
+            let block = block.or(Err(Error::FailedToReadBlock))?;
+            let mut message = vec![];
+
+            message.extend_from_slice(&piece_index.to_be_bytes());
+            message.extend_from_slice(&block_offset.to_be_bytes());
+            message.extend_from_slice(&block);
+
+            self.common_information
+                .bandwidth
+                .throttle(Direction::Upload, message.len());
+
+            let response = Message::Piece { payload: message };
+            stream
+                .write_all(&response.parse().expect("Failed to parse piece message"))
+                .or(Err(Error::FailedToSendBlock))?;
+
+            self.common_information
+                .stats
+                .add_uploaded(block_length as usize);
+        }
+
+        Ok(())
     }
 
     fn send_handshake_response(&self, stream: &mut TcpStream) -> Result<UploadState, ()> {
@@ -157,19 +242,128 @@ impl ServerConnection {
         Ok(UploadState::Uploading)
     }
 
-    fn validate_peer(stream: &mut TcpStream, own_peer_id: [u8; 20]) -> Result<UploadState, ()> {
-        match Message::read_handshake_from_stream(stream) {
-            Ok(Message::Handshake(info_hash, _)) => {
-                stream
-                    .write_all(
-                        &Message::HandshakeResponse(info_hash, own_peer_id)
-                            .parse()
-                            .expect("Failed to parse handshake response"),
-                    )
-                    .or(Err(()))?;
-                Ok(UploadState::AwaitingResponse)
-            }
-            _ => Err(()),
+    // The handshake was already read by the listener. Answering it lets the outgoing side of a
+    // connection to ourselves detect it too.
+    fn validate_peer(&self, stream: &mut TcpStream, peer_id: &[u8]) -> Result<Registration, ()> {
+        stream
+            .write_all(
+                &Message::HandshakeResponse(
+                    self.common_information.info_hash.clone(),
+                    self.common_information.peer_id,
+                )
+                .parse()
+                .expect("Failed to parse handshake response"),
+            )
+            .or(Err(()))?;
+
+        self.common_information
+            .connected_peers
+            .register(peer_id, Origin::Incoming)
+            .or(Err(()))
+    }
+}
+
+// The length prefix is only peeked at, so a message still arriving is left whole in the stream.
+fn message_waiting(stream: &TcpStream) -> Result<bool, Error> {
+    let mut length = [0_u8; 4];
+
+    stream
+        .set_nonblocking(true)
+        .or(Err(Error::FailedMessageRead))?;
+    let peeked = stream.peek(&mut length);
+    stream
+        .set_nonblocking(false)
+        .or(Err(Error::FailedMessageRead))?;
+
+    match peeked {
+        // The peer closed the connection.
+        Ok(0) => Err(Error::FailedMessageRead),
+        Ok(read) => Ok(read == length.len()),
+        Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(_) => Err(Error::FailedMessageRead),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_torrent::Session;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn request(piece_index: u32, block_offset: u32, block_length: u32) -> Vec<u8> {
+        Message::Request {
+            piece_index,
+            block_offset,
+            block_length,
+        }
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test1_requests_are_validated_and_bounded() {
+        // Pieces of two blocks, the last one a single block.
+        let data = vec![0; 5 * BLOCK_LENGTH as usize];
+        let session = Session::new_for_tests();
+        let (common_information, _rx_torrent, _rx_peers) = CommonInformation::from_data(
+            "test1_requests",
+            &data,
+            2 * BLOCK_LENGTH as usize,
+            &session,
+        );
+        let mut connection = ServerConnection::new(
+            Arc::new(Mutex::new(Bitfield::new(3))),
+            Arc::new(Mutex::new(PeerState::AllPieces(String::new()))),
+            common_information.clone(),
+        );
+
+        for (piece_index, block_offset, block_length) in [
+            (3, 0, BLOCK_LENGTH),
+            (0, 0, 0),
+            (0, 0, BLOCK_LENGTH + 1),
+            (0, BLOCK_LENGTH + 1, BLOCK_LENGTH),
+            (2, 1, BLOCK_LENGTH),
+        ] {
+            assert!(matches!(
+                connection.validate_request(piece_index, block_offset, block_length),
+                Err(Error::InvalidRequest)
+            ));
         }
+        assert!(connection.validate_request(2, 0, BLOCK_LENGTH).is_ok());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let first = request(0, 0, BLOCK_LENGTH);
+
+        // A request still arriving is left whole in the stream while others can be served.
+        peer.write_all(&first).unwrap();
+        peer.write_all(&first[..2]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        connection.queue_requests(&mut stream).unwrap();
+        assert_eq!(connection.requests.len(), 1);
+
+        peer.write_all(&first[2..]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        connection.queue_requests(&mut stream).unwrap();
+        assert_eq!(connection.requests.len(), 2);
+
+        for _ in 2..=MAX_QUEUED_REQUESTS {
+            peer.write_all(&request(1, BLOCK_LENGTH, BLOCK_LENGTH))
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(matches!(
+            connection.queue_requests(&mut stream),
+            Err(Error::TooManyRequests)
+        ));
+        assert_eq!(connection.requests.len(), MAX_QUEUED_REQUESTS);
+
+        fs::remove_dir_all(&common_information.temp_directory).unwrap();
     }
 }
@@ -1,23 +1,82 @@
 use crate::frontend::torrents::TorrentData;
 
 use super::{
-    utils::split_u8, Bitfield, CommonInformation, Decoder, Handshake, InterfaceProtocolHandler,
-    NetworkingError, PeerList, PeerRecord, PeerState, Types, UrlEncoder,
+    Announce, AnnounceResponse, Bitfield, CommonInformation, InterfaceProtocolHandler,
+    NetworkingError, PeerList, PeerState, MIN_SCRAPE_INTERVAL, STOPPED_ANNOUNCE_TIMEOUT,
+    TRACKER_RETRY_INTERVAL,
 };
+use rand::random;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread::{self};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use std::sync::{Arc, Mutex};
 
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct TrackerConnection {
     client: InterfaceProtocolHandler,
     bitfield: Arc<Mutex<Bitfield>>,
     peers: Arc<Mutex<PeerList>>,
     common_information: CommonInformation,
-    tracker_address: String,
     sleep: u64,
+    min_interval: u64,
+    key: u32,
+    tracker_id: Option<Vec<u8>>,
+    events: Events,
+    scrape_supported: bool,
+    last_scrape: Option<Instant>,
     state: Arc<Mutex<PeerState>>,
-    announce: String,
+}
+
+#[derive(Debug, Default)]
+struct Events {
+    started: bool,
+    completed: bool,
+}
+
+impl Events {
+    fn next(&self, left: u64) -> &'static str {
+        if !self.started {
+            "started"
+        } else if !self.completed && left == 0 {
+            "completed"
+        } else {
+            ""
+        }
+    }
+
+    // Torrents that were already complete when started never send `completed`.
+    fn sent(&mut self, event: &str, left: u64) {
+        match event {
+            "started" => {
+                self.started = true;
+                self.completed = left == 0;
+            }
+            "completed" => self.completed = true,
+            _ => {}
+        }
+    }
+
+    fn completion_pending(&self, left: u64) -> bool {
+        self.started && !self.completed && left == 0
+    }
+}
+
+// Keeps `Session::active_trackers` up to date, even if the tracker thread panics.
+struct ActiveTracker(Arc<AtomicUsize>);
+
+impl ActiveTracker {
+    fn new(active_trackers: &Arc<AtomicUsize>) -> Self {
+        active_trackers.fetch_add(1, Ordering::SeqCst);
+        Self(Arc::clone(active_trackers))
+    }
+}
+
+impl Drop for ActiveTracker {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl TrackerConnection {
@@ -28,127 +87,226 @@ impl TrackerConnection {
         common_information: CommonInformation,
         state: Arc<Mutex<PeerState>>,
     ) -> Result<Self, NetworkingError> {
-        let (client, tracker_address) = InterfaceProtocolHandler::new(announce.clone())?;
+        let client = InterfaceProtocolHandler::new(announce)?;
 
         Ok(Self {
-            announce,
             state,
             client,
             bitfield,
             peers,
             common_information,
-            tracker_address,
             sleep: 2,
+            min_interval: 0,
+            key: random(),
+            tracker_id: None,
+            events: Events::default(),
+            scrape_supported: true,
+            last_scrape: None,
         })
     }
 
-    pub fn activate(mut self, listening_port: u16, listening_ip: String) -> thread::JoinHandle<()> {
+    pub fn activate(mut self, listening_port: u16) -> thread::JoinHandle<()> {
+        let active_tracker = ActiveTracker::new(&self.common_information.session.active_trackers);
+
         thread::spawn(move || {
+            let _active_tracker = active_tracker;
             let mut retries = 3;
 
             loop {
-                if let PeerState::Broken = &*self.state.lock().unwrap() {
+                if self.is_broken() {
+                    self.announce_stopped(listening_port);
                     break;
                 }
 
-                let mut peers_guard = self.peers.lock().unwrap();
+                // A tracker down is retried later, the DHT may still find peers meanwhile.
                 if retries == 0 {
-                    *self.state.lock().unwrap() = PeerState::Broken;
-                    panic!("Tracker unavailable - No retries left");
+                    log::warn!(
+                        "Tracker of {} unavailable, retrying in {}s",
+                        self.common_information.file_name,
+                        TRACKER_RETRY_INTERVAL
+                    );
+                    *self.common_information.error.lock().unwrap() =
+                        Some("Tracker unavailable".to_string());
+                    self.refresh();
+
+                    retries = 3;
+                    self.sleep = TRACKER_RETRY_INTERVAL;
+                    self.wait_next_announce();
+                    continue;
                 }
-                let bitfield_guard = self.bitfield.lock().unwrap();
-
-                let (downloaded, left) = bitfield_guard.status();
-
-                drop(bitfield_guard);
-
-                let request = self.client.format_handshake_message(Handshake {
-                    address: self.tracker_address.to_string(),
-                    id: UrlEncoder::encode_binary_data(self.common_information.peer_id.as_ref()),
-                    info_hash: UrlEncoder::encode_binary_data(&self.common_information.info_hash),
-                    port: listening_port,
-                    left,
-                    downloaded,
-                    event: String::from(if left == 0 { "completed" } else { "started" }),
-                });
-                let response = if let Err(error) = self.client.send(request) {
-                    Err(error)
-                } else {
-                    self.client.read_to_end()
-                };
-
-                if let Ok(response) = response {
-                    if !response.is_empty() {
-                        let slice = split_u8(response, b"\r\n\r\n");
-
-                        if let Ok(Types::Dictionary(dict)) =
-                            Decoder::new_from_bytes(&slice).decode()
-                        {
-                            let peers = dict
-                                .get(&b"peers".to_vec())
-                                .expect("Failed to parse peers from tracker response");
-
-                            let sleep = dict
-                                .get(&b"interval".to_vec())
-                                .expect("Failed to get interval from tracker response")
-                                .get_integrer()
-                                .expect("Failed to parse interval from tracker response");
-
-                            self.sleep = sleep as u64;
-
-                            if let Types::List(list) = peers {
-                                log::debug!(
-                                "TrackerConnection::activate() - trying to obtain PeerList lock"
-                            );
-                                log::debug!(
-                                    "TrackerConnection::activate() - PeerList lock obtained"
-                                );
-
-                                let peers = PeerRecord::new_from_list(
-                                    list,
-                                    self.common_information.total_pieces,
-                                );
-
-                                peers_guard.update(peers);
-
-                                peers_guard.remove(&listening_ip, listening_port as i64);
-
-                                let bitfield_guard = self.bitfield.lock().unwrap();
-                                retries = 3;
-                                TorrentData::refresh(
-                                    &self.common_information,
-                                    &peers_guard,
-                                    &bitfield_guard,
-                                );
-                            }
-                        } else {
-                            retries -= 1;
-                        }
-                    }
+
+                let left = self.bytes_left();
+                let event = self.events.next(left);
+
+                if let Ok(response) = self.announce(listening_port, event, left) {
+                    self.events.sent(event, left);
+                    self.update(&response);
+
+                    let mut peers_guard = self.peers.lock().unwrap();
+                    peers_guard.update(response.peers);
+
+                    let bitfield_guard = self.bitfield.lock().unwrap();
+                    retries = 3;
+                    *self.common_information.error.lock().unwrap() = None;
+                    TorrentData::refresh(&self.common_information, &peers_guard, &bitfield_guard);
+
+                    drop(bitfield_guard);
+                    drop(peers_guard);
+
+                    // Only once announced, a slow scrape must not hold up the peers.
+                    self.scrape_if_due();
                 } else {
                     retries -= 1;
                 }
 
-                drop(peers_guard);
+                self.wait_next_announce();
+            }
+        })
+    }
 
-                let last_call = Instant::now();
+    fn refresh(&self) {
+        let peers_guard = self.peers.lock().unwrap();
+        let bitfield_guard = self.bitfield.lock().unwrap();
 
-                loop {
-                    if let PeerState::Broken = &*self.state.lock().unwrap() {
-                        break;
-                    }
+        TorrentData::refresh(&self.common_information, &peers_guard, &bitfield_guard);
+    }
 
-                    if last_call.elapsed().as_secs() >= self.sleep {
-                        let (client, _) =
-                            InterfaceProtocolHandler::new(self.announce.clone()).unwrap();
-                        self.client = client;
+    fn is_broken(&self) -> bool {
+        matches!(&*self.state.lock().unwrap(), PeerState::Broken)
+    }
 
-                        break;
-                    }
+    fn bytes_left(&self) -> u64 {
+        let bitfield_guard = self.bitfield.lock().unwrap();
 
-                    thread::yield_now();
-                }
-            }
+        self.common_information.bytes_left(&bitfield_guard)
+    }
+
+    fn announce(
+        &mut self,
+        listening_port: u16,
+        event: &str,
+        left: u64,
+    ) -> Result<AnnounceResponse, NetworkingError> {
+        let stats = &self.common_information.stats;
+        self.common_information.save_stats();
+
+        self.client.announce(Announce {
+            info_hash: &self.common_information.info_hash,
+            peer_id: &self.common_information.peer_id,
+            port: self
+                .common_information
+                .session
+                .addresses
+                .advertised_port(listening_port),
+            downloaded: stats.downloaded(),
+            uploaded: stats.uploaded(),
+            left,
+            event,
+            key: self.key,
+            tracker_id: self.tracker_id.as_deref(),
+            ip: self.common_information.session.addresses.advertised_ip(),
+            ipv4: self.common_information.session.addresses.public_ipv4(),
+            ipv6: self.common_information.session.addresses.public_ipv6(),
+            total_pieces: self.common_information.total_pieces,
         })
     }
+
+    // At most every other announce interval, and never more often than MIN_SCRAPE_INTERVAL.
+    fn scrape_if_due(&mut self) {
+        let scrape_interval = (self.sleep * 2).max(MIN_SCRAPE_INTERVAL);
+
+        if !self.scrape_supported
+            || self
+                .last_scrape
+                .is_some_and(|last_scrape| last_scrape.elapsed().as_secs() < scrape_interval)
+        {
+            return;
+        }
+
+        self.last_scrape = Some(Instant::now());
+
+        match self.client.scrape(&self.common_information.info_hash) {
+            Ok(scrape) => {
+                *self.common_information.swarm.lock().unwrap() = Some(scrape);
+            }
+            Err(NetworkingError::ScrapeUnsupported) => {
+                log::info!("Tracker does not support scrapes");
+                self.scrape_supported = false;
+            }
+            Err(error) => log::warn!("Failed to scrape tracker: {:?}", error),
+        }
+    }
+
+    fn update(&mut self, response: &AnnounceResponse) {
+        self.min_interval = response.min_interval.unwrap_or(0);
+        self.sleep = response.interval.max(self.min_interval);
+
+        if let Some(tracker_id) = &response.tracker_id {
+            self.tracker_id = Some(tracker_id.clone());
+        }
+    }
+
+    // Wakes up early to report a finished download, as long as `min interval` has passed.
+    fn wait_next_announce(&self) {
+        let last_call = Instant::now();
+
+        loop {
+            if self.is_broken() {
+                break;
+            }
+
+            let elapsed = last_call.elapsed().as_secs();
+
+            if elapsed >= self.sleep {
+                break;
+            }
+
+            if elapsed >= self.min_interval && self.events.completion_pending(self.bytes_left()) {
+                break;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Only sent if the tracker knows about us.
+    fn announce_stopped(&mut self, listening_port: u16) {
+        if !self.events.started {
+            return;
+        }
+
+        if let InterfaceProtocolHandler::Udp(tracker) = &mut self.client {
+            tracker.set_retransmission(Duration::from_secs(STOPPED_ANNOUNCE_TIMEOUT), 0);
+        }
+
+        let left = self.bytes_left();
+
+        if let Err(error) = self.announce(listening_port, "stopped", left) {
+            log::warn!("Failed to announce stopped: {:?}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_events_follow_the_download_lifecycle() {
+        let mut events = Events::default();
+
+        assert_eq!(events.next(100), "started");
+        events.sent("started", 100);
+        assert_eq!(events.next(100), "");
+        assert!(!events.completion_pending(100));
+
+        assert!(events.completion_pending(0));
+        assert_eq!(events.next(0), "completed");
+        events.sent("completed", 0);
+        assert_eq!(events.next(0), "");
+
+        let mut events = Events::default();
+        events.sent("started", 0);
+        assert_eq!(events.next(0), "");
+    }
 }
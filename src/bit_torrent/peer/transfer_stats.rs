@@ -0,0 +1,144 @@
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Downloaded bytes only count pieces that passed their hash check. Pieces that failed it or
+// were abandoned halfway count as wasted.
+#[derive(Debug)]
+pub struct TransferStats {
+    pathname: String,
+    downloaded: AtomicU64,
+    wasted: AtomicU64,
+    uploaded: AtomicU64,
+    // Held while saving, as several threads save to the same file.
+    saving: Mutex<()>,
+}
+
+impl TransferStats {
+    pub fn load(pathname: &str) -> Self {
+        let stats = Self {
+            pathname: pathname.to_string(),
+            downloaded: AtomicU64::new(0),
+            wasted: AtomicU64::new(0),
+            uploaded: AtomicU64::new(0),
+            saving: Mutex::new(()),
+        };
+
+        if let Ok(contents) = fs::read_to_string(pathname) {
+            for line in contents.lines() {
+                let (key, value) = match line.split_once(',') {
+                    Some((key, value)) => (key, value.trim().parse::<u64>().unwrap_or(0)),
+                    None => continue,
+                };
+
+                match key {
+                    "DOWNLOADED" => stats.downloaded.store(value, Ordering::Relaxed),
+                    "WASTED" => stats.wasted.store(value, Ordering::Relaxed),
+                    "UPLOADED" => stats.uploaded.store(value, Ordering::Relaxed),
+                    _ => {}
+                }
+            }
+        }
+
+        stats
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let _saving = self.saving.lock().unwrap();
+
+        if let Some(directory) = Path::new(&self.pathname).parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let temp_pathname = format!("{}.tmp", self.pathname);
+
+        fs::write(
+            &temp_pathname,
+            format!(
+                "DOWNLOADED,{}\nWASTED,{}\nUPLOADED,{}",
+                self.downloaded(),
+                self.wasted(),
+                self.uploaded()
+            ),
+        )?;
+
+        fs::rename(temp_pathname, &self.pathname)
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::Relaxed)
+    }
+
+    pub fn wasted(&self) -> u64 {
+        self.wasted.load(Ordering::Relaxed)
+    }
+
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded.load(Ordering::Relaxed)
+    }
+
+    pub fn add_downloaded(&self, bytes: usize) {
+        self.downloaded.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_wasted(&self, bytes: usize) {
+        self.wasted.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_uploaded(&self, bytes: usize) {
+        self.uploaded.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test1_stats_survive_a_restart() {
+        let pathname = env::temp_dir().join("sitos_test1_stats_survive_a_restart.stats");
+        let pathname = pathname.to_str().unwrap();
+        let _ = fs::remove_file(pathname);
+
+        let stats = TransferStats::load(pathname);
+        stats.add_downloaded(100);
+        stats.add_wasted(10);
+        stats.add_uploaded(50);
+        stats.save().unwrap();
+
+        let stats = TransferStats::load(pathname);
+
+        assert_eq!(stats.downloaded(), 100);
+        assert_eq!(stats.wasted(), 10);
+        assert_eq!(stats.uploaded(), 50);
+
+        fs::remove_file(pathname).unwrap();
+    }
+
+    #[test]
+    fn test2_saves_from_many_threads_keep_the_file_whole() {
+        let pathname = env::temp_dir().join("sitos_test2_saves_from_many_threads.stats");
+        let pathname = pathname.to_str().unwrap();
+        let _ = fs::remove_file(pathname);
+
+        let stats = TransferStats::load(pathname);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        stats.add_downloaded(1);
+                        stats.save().unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(TransferStats::load(pathname).downloaded(), 400);
+
+        fs::remove_file(pathname).unwrap();
+    }
+}
@@ -1,7 +1,8 @@
 use super::{Bitfield, Types};
 use std::collections::LinkedList;
 use std::fmt::{Debug, Error, Formatter};
-use std::net::Ipv6Addr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct PeerRecord {
@@ -10,6 +11,12 @@ pub struct PeerRecord {
     pub has: Bitfield,
     pub ipv6: bool,
     pub in_use: bool,
+    pub failures: u32,
+    pub retry_at: Option<Instant>,
+    pub local: bool,
+    // Zero when unknown.
+    pub priority: u32,
+    pub download_rate: u64,
 }
 
 impl Debug for PeerRecord {
@@ -17,6 +24,10 @@ impl Debug for PeerRecord {
         f.debug_struct("PeerRecord")
             .field("ip", &self.ip)
             .field("port", &self.port)
+            .field("failures", &self.failures)
+            .field("local", &self.local)
+            .field("priority", &self.priority)
+            .field("download_rate", &self.download_rate)
             .finish()
     }
 }
@@ -27,39 +38,130 @@ impl PeerRecord {
             true => {
                 let addr: Ipv6Addr = self.ip.parse().expect("Failed to parse ipv6 addr");
 
-                format!("{}:{}", addr, self.port)
+                SocketAddr::new(addr.into(), self.port as u16).to_string()
             }
             false => format!("{}:{}", self.ip, self.port),
         }
     }
 
-    pub fn new_from_list(list: &LinkedList<Types>, total_pieces: usize) -> Vec<Self> {
+    pub fn is_available(&self) -> bool {
+        !self.in_use
+            && self
+                .retry_at
+                .is_none_or(|retry_at| retry_at <= Instant::now())
+    }
+
+    // Compact peers are `compact_length` bytes of address followed by a 2 byte port.
+    pub fn new_from_list(peers: &Types, compact_length: usize, total_pieces: usize) -> Vec<Self> {
+        match peers {
+            Types::List(list) => Self::new_from_dictionaries(list, total_pieces),
+            Types::String(bytes) => Self::new_from_compact(bytes, compact_length, total_pieces),
+            _ => Vec::new(),
+        }
+    }
+
+    fn new_from_dictionaries(list: &LinkedList<Types>, total_pieces: usize) -> Vec<Self> {
         let mut peer_list: Vec<Self> = Vec::new();
 
         list.iter().for_each(|peer| {
             if let Types::Dictionary(peer) = peer {
-                let ip = peer
-                    .get(&b"ip".to_vec())
-                    .expect("Failed to parse ip for peer");
-
-                let port = peer
-                    .get(&b"port".to_vec())
-                    .expect("Failed to parse port for peer");
-
-                if let (Types::String(ip), Types::Integer(port)) = (ip, port) {
-                    let ip = String::from_utf8(ip.clone()).expect("Malformed peer ip");
-
-                    peer_list.push(Self {
-                        in_use: false,
-                        ipv6: ip.contains(':'),
-                        has: Bitfield::new(total_pieces),
-                        port: *port,
-                        ip,
-                    });
+                if let (Some(Types::String(ip)), Some(Types::Integer(port))) =
+                    (peer.get(b"ip".as_slice()), peer.get(b"port".as_slice()))
+                {
+                    if let Ok(ip) = String::from_utf8(ip.clone()) {
+                        peer_list.push(Self::new(ip, *port, total_pieces));
+                    }
                 }
             };
         });
 
         peer_list
     }
+
+    fn new_from_compact(bytes: &[u8], compact_length: usize, total_pieces: usize) -> Vec<Self> {
+        bytes
+            .chunks_exact(compact_length)
+            .filter_map(|peer| {
+                let (address, port) = peer.split_at(compact_length - 2);
+                let port = u16::from_be_bytes([port[0], port[1]]) as i64;
+
+                let ip = match address.len() {
+                    4 => Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string(),
+                    16 => Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?).to_string(),
+                    _ => return None,
+                };
+
+                Some(Self::new(ip, port, total_pieces))
+            })
+            .collect()
+    }
+
+    pub fn new_from_address(address: SocketAddr, total_pieces: usize) -> Self {
+        Self::new(
+            address.ip().to_string(),
+            address.port() as i64,
+            total_pieces,
+        )
+    }
+
+    fn new(ip: String, port: i64, total_pieces: usize) -> Self {
+        Self {
+            in_use: false,
+            failures: 0,
+            retry_at: None,
+            local: false,
+            priority: 0,
+            download_rate: 0,
+            ipv6: ip.contains(':'),
+            has: Bitfield::new(total_pieces),
+            port,
+            ip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{COMPACT_PEER6_LENGTH, COMPACT_PEER_LENGTH};
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test1_compact_ipv4_peers() {
+        let peers = Types::String(vec![10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0, 80, 1]);
+
+        let records = PeerRecord::new_from_list(&peers, COMPACT_PEER_LENGTH, 1);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get_address(), "10.0.0.1:6881");
+        assert_eq!(records[1].get_address(), "192.168.1.2:80");
+    }
+
+    #[test]
+    fn test2_compact_ipv6_peers() {
+        let mut bytes = Ipv6Addr::LOCALHOST.octets().to_vec();
+        bytes.extend_from_slice(&6881_u16.to_be_bytes());
+
+        let records = PeerRecord::new_from_list(&Types::String(bytes), COMPACT_PEER6_LENGTH, 1);
+
+        assert_eq!(records.len(), 1);
+        assert!(records[0].ipv6);
+        assert_eq!(records[0].get_address(), "[::1]:6881");
+    }
+
+    #[test]
+    fn test3_dictionary_peers_skip_malformed_entries() {
+        let mut peer = HashMap::new();
+        peer.insert(b"ip".to_vec(), Types::String(b"10.0.0.1".to_vec()));
+        peer.insert(b"port".to_vec(), Types::Integer(6881));
+
+        let mut list = LinkedList::new();
+        list.push_back(Types::Dictionary(peer));
+        list.push_back(Types::Dictionary(HashMap::new()));
+
+        let records = PeerRecord::new_from_list(&Types::List(list), COMPACT_PEER_LENGTH, 1);
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].port, 6881);
+    }
 }
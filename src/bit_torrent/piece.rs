@@ -1,6 +1,4 @@
-use super::File;
 use sha1::{Digest, Sha1};
-use std::io::Error;
 
 pub struct Piece {
     id: usize,
@@ -17,7 +15,7 @@ impl Piece {
     pub fn new(id: usize, piece_size: usize, block_size: usize) -> Self {
         let total_blocks = (piece_size as f64 / block_size as f64).ceil() as usize;
 
-        let last_block_size = (piece_size - block_size * (total_blocks - 1)) as usize;
+        let last_block_size = piece_size - block_size * (total_blocks - 1);
 
         Self {
             piece_size,
@@ -51,6 +49,10 @@ impl Piece {
         }
     }
 
+    pub fn received(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn is_complete(&self) -> bool {
         self.data.len() == self.piece_size
     }
@@ -63,7 +65,7 @@ impl Piece {
         piece_hash.to_vec() == hash
     }
 
-    pub fn save(&self, filename: &str) -> Result<File, Error> {
-        File::new_file_from_piece(&self.data, format!("{}.piece{}", filename, self.id))
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
     }
 }
@@ -0,0 +1,106 @@
+use super::{
+    Addresses, BandwidthLimits, ConnectionLimits, Dht, DiskIo, IpFilter, Listener, LocalDiscovery,
+    PortMapper,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Longest shutdown waits for the gateway to drop our port mapping.
+const UNMAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub bandwidth: Arc<BandwidthLimits>,
+    pub connection_limits: Arc<ConnectionLimits>,
+    pub addresses: Arc<Addresses>,
+    pub ip_filter: Arc<IpFilter>,
+    // None when no port could be bound.
+    pub listener: Option<Arc<Listener>>,
+    pub port_mapper: Option<Arc<PortMapper>>,
+    // Shutdown waits for these to send `stopped`.
+    pub active_trackers: Arc<AtomicUsize>,
+    // None when disabled or unable to start.
+    pub dht: Option<Arc<Dht>>,
+    pub lsd: Option<Arc<LocalDiscovery>>,
+    pub disk_io: Arc<DiskIo>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let connection_limits = Arc::new(ConnectionLimits::new());
+        let addresses = Arc::new(Addresses::new());
+        let ip_filter = IpFilter::new_from_env();
+        let listener = Listener::new_from_env(
+            addresses.listen_interface(),
+            Arc::clone(&connection_limits),
+            Arc::clone(&ip_filter),
+        );
+
+        Self {
+            bandwidth: Arc::new(BandwidthLimits::new()),
+            port_mapper: listener.as_ref().and_then(|listener| {
+                PortMapper::new_from_env(listener.port(), Arc::clone(&addresses))
+            }),
+            listener,
+            connection_limits,
+            addresses,
+            ip_filter,
+            active_trackers: Arc::new(AtomicUsize::new(0)),
+            dht: Dht::new_from_env(),
+            lsd: LocalDiscovery::new_from_env(),
+            disk_io: Arc::new(DiskIo::new_from_env()),
+        }
+    }
+
+    // Leaves out the listener, the port mapping, the DHT and local discovery, so tests neither
+    // bind their ports nor reach the network.
+    #[cfg(test)]
+    pub fn new_for_tests() -> Self {
+        Self {
+            bandwidth: Arc::default(),
+            connection_limits: Arc::default(),
+            addresses: Arc::default(),
+            ip_filter: Arc::default(),
+            listener: None,
+            port_mapper: None,
+            active_trackers: Arc::default(),
+            dht: None,
+            lsd: None,
+            disk_io: Arc::default(),
+        }
+    }
+
+    pub fn shutdown(&self, timeout: Duration) {
+        self.wait_for_trackers(timeout);
+
+        // Removed on its own thread, as shutdown runs on the GTK thread and a gateway may
+        // never answer.
+        if let Some(port_mapper) = &self.port_mapper {
+            let port_mapper = Arc::clone(port_mapper);
+            let (removed, on_removed) = mpsc::channel();
+
+            thread::spawn(move || {
+                port_mapper.remove();
+                let _ = removed.send(());
+            });
+
+            let _ = on_removed.recv_timeout(UNMAP_TIMEOUT);
+        }
+    }
+
+    pub fn wait_for_trackers(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+
+        while self.active_trackers.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
@@ -1,7 +1,6 @@
 pub use super::{
     bencoder::{Encoder, Types},
     constants::INTERVAL,
-    networking::utils::get_available_port,
     Ledger, PeerRecord, Request, TrackerError, TrackerStatus,
 };
 use std::io::{Read, Write};
@@ -58,13 +57,17 @@ impl Connection {
                         info_hash,
                         left,
                         event,
-                        ip,
+                        ips,
+                        compact,
                     }) => {
+                        let ip = ips[0];
+
                         if self
                             .manage_announce(
                                 info_hash,
+                                compact,
                                 PeerRecord {
-                                    ip: ip.clone(),
+                                    ips,
                                     peer_id,
                                     port,
                                     uploaded,
@@ -141,6 +144,7 @@ impl Connection {
     pub fn manage_announce(
         &mut self,
         info_hash: [u8; 20],
+        compact: bool,
         peer: PeerRecord,
     ) -> Result<(), TrackerError> {
         let mut ledger = self
@@ -162,26 +166,35 @@ impl Connection {
         );
 
         response_information.insert(b"interval".to_vec(), Types::Integer(INTERVAL));
-        response_information.insert(b"peers".to_vec(), entry.encode());
+
+        match compact {
+            true => {
+                let (peers, peers6) = entry.encode_compact();
+                response_information.insert(b"peers".to_vec(), peers);
+                response_information.insert(b"peers6".to_vec(), peers6);
+            }
+            false => {
+                response_information.insert(b"peers".to_vec(), entry.encode());
+            }
+        }
 
         let encoder = Encoder::new(Types::Dictionary(response_information));
 
-        let response = String::from_utf8_lossy(
-            &encoder
-                .encode()
-                .or(Err(TrackerError::FailedToEncodeResponse))?,
+        // Compact peers are binary, so the body is written as is.
+        let response = encoder
+            .encode()
+            .or(Err(TrackerError::FailedToEncodeResponse))?;
+
+        let mut message = format!(
+            "HTTP/1.1 200 OK \r\nHost: 127.0.0.1:8080\r\nContent-Length:{}\r\nContent-Type: text/plain\r\n\r\n",
+            response.len(),
         )
-        .to_string();
+        .into_bytes();
+        message.extend_from_slice(&response);
 
         self.stream
-            .write_all(
-                format!(
-                    "HTTP/1.1 200 OK \r\nHost: 127.0.0.1:8080\r\nContent-Length:{}\r\nContent-Type: text/plain\r\n\r\n{}",
-                    response.chars().count(),
-                    response
-                )
-                .as_bytes(),
-            ).or(Err(TrackerError::FailedToSendResponse))?;
+            .write_all(&message)
+            .or(Err(TrackerError::FailedToSendResponse))?;
 
         let _ = self.stream.flush();
 
@@ -1,29 +1,39 @@
-pub use super::{
-    networking::utils::get_available_port, Connection, Ledger, TrackerError, TrackerStatus,
-};
+pub use super::{Connection, Ledger, TrackerError, TrackerStatus};
 use std::io;
-use std::net::TcpListener;
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
 use std::sync::mpsc::Receiver;
 pub use std::sync::{Arc, Mutex};
 pub use std::thread;
+use std::time::Duration;
+
+const PORT: u16 = 8080;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 pub struct Tracker {
     ledger: Arc<Mutex<Ledger>>,
-    listener: TcpListener,
+    // IPv6 loopback only where available.
+    listeners: Vec<TcpListener>,
     status: Arc<Mutex<TrackerStatus>>,
 }
 
 impl Tracker {
     pub fn new() -> Result<Self, TrackerError> {
-        let listener = TcpListener::bind(String::from("127.0.0.1:8080"))
-            .or(Err(TrackerError::FailedToBindStream))?;
+        let mut listeners = vec![TcpListener::bind((Ipv4Addr::LOCALHOST, PORT))
+            .or(Err(TrackerError::FailedToBindStream))?];
+
+        match TcpListener::bind((Ipv6Addr::LOCALHOST, PORT)) {
+            Ok(listener) => listeners.push(listener),
+            Err(error) => log::warn!("Tracker::new() - not listening over IPv6: {}", error),
+        }
 
-        listener
-            .set_nonblocking(true)
-            .expect("Cannot set non-blocking");
+        for listener in &listeners {
+            listener
+                .set_nonblocking(true)
+                .expect("Cannot set non-blocking");
+        }
 
         Ok(Self {
-            listener,
+            listeners,
             ledger: Arc::new(Mutex::new(Ledger::new())),
             status: Arc::new(Mutex::new(TrackerStatus::Active)),
         })
@@ -34,32 +44,41 @@ impl Tracker {
             let mut connections: Vec<thread::JoinHandle<()>> =
                 vec![TrackerStatus::await_signal(Arc::clone(&self.status), rx)];
 
-            for stream in self.listener.incoming() {
-                match stream {
-                    Ok(s) => {
-                        if let Ok(mut ledger_guard) = self.ledger.lock() {
-                            ledger_guard.check();
-                        } else {
-                            log::error!("Tracker::activate() - Failed to lock ledger");
-                            break;
-                        }
-                        connections.push(
-                            Connection::new(s, Arc::clone(&self.ledger), Arc::clone(&self.status))
+            'accepting: loop {
+                let mut accepted = false;
+
+                for listener in &self.listeners {
+                    match listener.accept() {
+                        Ok((s, _)) => {
+                            accepted = true;
+
+                            if let Ok(mut ledger_guard) = self.ledger.lock() {
+                                ledger_guard.check();
+                            } else {
+                                log::error!("Tracker::activate() - Failed to lock ledger");
+                                break 'accepting;
+                            }
+                            connections.push(
+                                Connection::new(
+                                    s,
+                                    Arc::clone(&self.ledger),
+                                    Arc::clone(&self.status),
+                                )
                                 .activate(),
-                        );
+                            );
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => panic!("encountered IO error: {e}"),
                     }
-                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                        if TrackerStatus::Break == *self.status.lock().unwrap() {
-                            println!("break - ErrorKind::WouldBlock");
+                }
 
-                            log::debug!("Tracker::activate(): breaking listener loop");
-                            break;
-                        } else {
-                            thread::yield_now();
-                            continue;
-                        }
+                if !accepted {
+                    if TrackerStatus::Break == *self.status.lock().unwrap() {
+                        log::debug!("Tracker::activate(): breaking listener loop");
+                        break;
+                    } else {
+                        thread::sleep(ACCEPT_POLL_INTERVAL);
                     }
-                    Err(e) => panic!("encountered IO error: {e}"),
                 }
             }
 
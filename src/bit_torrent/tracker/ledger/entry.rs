@@ -29,6 +29,10 @@ impl Entry {
         self.peer_list.encode()
     }
 
+    pub fn encode_compact(&self) -> (Types, Types) {
+        self.peer_list.encode_compact()
+    }
+
     pub fn check(&mut self) {
         self.peer_list.check();
     }
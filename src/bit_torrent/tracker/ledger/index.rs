@@ -50,7 +50,7 @@ impl Ledger {
                 entry.remove(peer.peer_id);
             } else {
                 entry.update(PeerRecord::new(
-                    peer.ip,
+                    peer.ips,
                     peer.peer_id,
                     peer.port,
                     peer.uploaded,
@@ -1,6 +1,7 @@
 use super::{bencoder::Types, constants::MAX_WAITTIME, PeerRecord};
 use serde::Serialize;
 use std::collections::{HashMap, LinkedList};
+use std::net::IpAddr;
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct PeerList {
@@ -34,17 +35,42 @@ impl PeerList {
         let mut peers: LinkedList<Types> = LinkedList::new();
 
         self.peers.iter().for_each(|peer| {
-            let mut peer_info: HashMap<Vec<u8>, Types> = HashMap::new();
+            for ip in &peer.ips {
+                let mut peer_info: HashMap<Vec<u8>, Types> = HashMap::new();
 
-            peer_info.insert(b"ip".to_vec(), Types::String(peer.ip.as_bytes().to_owned()));
-            peer_info.insert(b"port".to_vec(), Types::Integer(peer.port as i64));
+                peer_info.insert(b"ip".to_vec(), Types::String(ip.to_string().into_bytes()));
+                peer_info.insert(b"port".to_vec(), Types::Integer(peer.port as i64));
 
-            peers.push_back(Types::Dictionary(peer_info))
+                peers.push_back(Types::Dictionary(peer_info))
+            }
         });
 
         Types::List(peers)
     }
 
+    // 6 and 18 bytes per address.
+    pub fn encode_compact(&self) -> (Types, Types) {
+        let mut peers = vec![];
+        let mut peers6 = vec![];
+
+        for peer in &self.peers {
+            for ip in &peer.ips {
+                match ip {
+                    IpAddr::V4(ip) => {
+                        peers.extend_from_slice(&ip.octets());
+                        peers.extend_from_slice(&peer.port.to_be_bytes());
+                    }
+                    IpAddr::V6(ip) => {
+                        peers6.extend_from_slice(&ip.octets());
+                        peers6.extend_from_slice(&peer.port.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        (Types::String(peers), Types::String(peers6))
+    }
+
     // pub fn update(&mut self, incoming_peers: Vec<PeerRecord>) {
     //   for peer in incoming_peers {
     //     if self
@@ -85,3 +111,63 @@ impl PeerList {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_compact_peers_are_split_by_family() {
+        let peer = |id: u8, ips: &[&str], port: u16| {
+            PeerRecord::new(
+                ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+                [id; 20],
+                port,
+                0,
+                0,
+                "started".to_string(),
+            )
+        };
+
+        let v6 = |ip: &str, port: u16| {
+            let ip: std::net::Ipv6Addr = ip.parse().unwrap();
+            [ip.octets().to_vec(), port.to_be_bytes().to_vec()].concat()
+        };
+
+        for (peers, expected_v4, expected_v6) in [
+            (vec![], vec![], vec![]),
+            (
+                vec![peer(1, &["1.2.3.4"], 6881)],
+                vec![1, 2, 3, 4, 0x1a, 0xe1],
+                vec![],
+            ),
+            (
+                vec![peer(1, &["2001:db8::1"], 80)],
+                vec![],
+                v6("2001:db8::1", 80),
+            ),
+            (
+                vec![
+                    peer(1, &["10.0.0.1", "2001:db8::2"], 256),
+                    peer(2, &["10.0.0.2"], 1),
+                ],
+                vec![10, 0, 0, 1, 1, 0, 10, 0, 0, 2, 0, 1],
+                v6("2001:db8::2", 256),
+            ),
+        ] {
+            let mut list = PeerList::default();
+
+            for peer in peers {
+                list.update_entry(peer);
+            }
+
+            match list.encode_compact() {
+                (Types::String(peers), Types::String(peers6)) => {
+                    assert_eq!(peers, expected_v4);
+                    assert_eq!(peers6, expected_v6);
+                }
+                _ => panic!("Compact peers are not strings"),
+            }
+        }
+    }
+}
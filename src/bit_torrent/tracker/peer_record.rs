@@ -1,10 +1,12 @@
 use super::utils::u8_to_hexa;
 use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::net::IpAddr;
 use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct PeerRecord {
-    pub ip: String,
+    // The one it announced from first.
+    pub ips: Vec<IpAddr>,
     pub peer_id: [u8; 20],
     pub port: u16,
     pub uploaded: u64,
@@ -21,7 +23,14 @@ impl Serialize for PeerRecord {
     {
         let mut state = serializer.serialize_struct("Record", 6)?;
         state.serialize_field("peer_id", &u8_to_hexa(&self.peer_id))?;
-        state.serialize_field("ip", &self.ip)?;
+        state.serialize_field(
+            "ip",
+            &self
+                .ips
+                .first()
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+        )?;
         state.serialize_field("port", &self.port)?;
         state.serialize_field("event", &self.event)?;
         state.serialize_field("uploaded", &self.uploaded)?;
@@ -33,7 +42,7 @@ impl Serialize for PeerRecord {
 
 impl PeerRecord {
     pub fn new(
-        ip: String,
+        ips: Vec<IpAddr>,
         peer_id: [u8; 20],
         port: u16,
         uploaded: u64,
@@ -43,7 +52,7 @@ impl PeerRecord {
         Self {
             created_at: Instant::now(),
             updated_at: Instant::now(),
-            ip,
+            ips,
             peer_id,
             port,
             uploaded,
@@ -53,6 +62,7 @@ impl PeerRecord {
     }
 
     pub fn update(&mut self, new_data: PeerRecord) {
+        self.ips = new_data.ips;
         self.port = new_data.port;
         self.uploaded = new_data.uploaded;
         self.left = new_data.left;
@@ -1,5 +1,5 @@
 use super::{networking::HTTPTracker, urlencoder::encode::UrlEncoder};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 #[derive(Debug)]
 pub enum Request {
@@ -10,7 +10,9 @@ pub enum Request {
         uploaded: u64,
         left: u64,
         event: String,
-        ip: String,
+        // Followed by any other address reported through ipv4 and ipv6 (BEP 7).
+        ips: Vec<IpAddr>,
+        compact: bool,
     },
     Stats,
     Cors {
@@ -119,8 +121,23 @@ impl Request {
                         peer_id.as_bytes().to_vec()
                     };
 
+                    let mut ips = vec![addr.ip().to_canonical()];
+
+                    for family in ["ipv4", "ipv6"] {
+                        if let Some(ip) = query_map.get(family).and_then(|ip| parse_ip(ip)) {
+                            if !ips.contains(&ip) {
+                                ips.push(ip);
+                            }
+                        }
+                    }
+
+                    let compact = query_map
+                        .get("compact")
+                        .is_some_and(|compact| compact == "1");
+
                     Ok(Self::Announce {
-                        ip: addr.ip().to_string(),
+                        ips,
+                        compact,
                         port,
                         info_hash: info_hash
                             .try_into()
@@ -139,3 +156,71 @@ impl Request {
         }
     }
 }
+
+// The port, if any, is ignored.
+fn parse_ip(ip: &str) -> Option<IpAddr> {
+    let ip = UrlEncoder::decode_binary_data(ip.to_string()).ok()?;
+    let ip = String::from_utf8(ip).ok()?;
+
+    ip.parse::<IpAddr>()
+        .or_else(|_| ip.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test1_reported_addresses_are_parsed() {
+        let v4 = |a, b, c, d| Some(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+
+        for (ip, expected) in [
+            ("1.2.3.4", v4(1, 2, 3, 4)),
+            ("1.2.3.4%3A6881", v4(1, 2, 3, 4)),
+            ("%3A%3A1", Some(IpAddr::V6(Ipv6Addr::LOCALHOST))),
+            (
+                "%5B%3A%3A1%5D%3A6881",
+                Some(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+            ),
+            ("%3A%3Affff%3A1.2.3.4", v4(1, 2, 3, 4)),
+            ("tracker.example", None),
+            ("", None),
+        ] {
+            assert_eq!(parse_ip(ip), expected, "{}", ip);
+        }
+
+        let announce = |query: &str| {
+            Request::new(
+                format!(
+                    "GET /announce?info_hash={}&peer_id={}&port=6881&uploaded=0&left=0&event=started{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    "%01".repeat(20),
+                    "a".repeat(20),
+                    query
+                ),
+                "[::ffff:10.0.0.1]:50000".parse().unwrap(),
+            )
+        };
+
+        for (query, expected) in [
+            ("", vec!["10.0.0.1"]),
+            ("&ipv4=1.2.3.4", vec!["10.0.0.1", "1.2.3.4"]),
+            ("&ipv4=10.0.0.1&ipv6=%3A%3A1", vec!["10.0.0.1", "::1"]),
+            (
+                "&ipv4=bogus&ipv6=2001%3Adb8%3A%3A1",
+                vec!["10.0.0.1", "2001:db8::1"],
+            ),
+        ] {
+            match announce(query) {
+                Ok(Request::Announce { ips, .. }) => {
+                    let expected: Vec<IpAddr> =
+                        expected.iter().map(|ip| ip.parse().unwrap()).collect();
+                    assert_eq!(ips, expected, "{}", query);
+                }
+                other => panic!("{}: {:?}", query, other),
+            }
+        }
+    }
+}
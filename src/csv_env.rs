@@ -13,7 +13,6 @@ use std::io::{BufRead, BufReader};
 /// Params:
 ///
 /// name: String -> environment file name
-
 pub fn set_env(name: String) {
     let filename = format!("src/configs/{name}.csv");
     let file = File::open(filename).expect("Failed to open file");
@@ -0,0 +1,513 @@
+use super::Storage;
+use crate::utils::env_setting;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+const DEFAULT_THREADS: usize = 4;
+const DEFAULT_READ_CACHE_SIZE: usize = 32;
+const DEFAULT_WRITE_CACHE_SIZE: usize = 64;
+const MIB: usize = 1024 * 1024;
+
+type OnWritten = Box<dyn FnOnce(Result<(), Error>) + Send>;
+// A torrent's info hash and the index of one of its pieces.
+type PieceKey = (Vec<u8>, usize);
+
+struct PendingWrite {
+    data: Arc<Vec<u8>>,
+    // Taken by the worker writing the piece, which keeps it here until it is on disk.
+    on_written: Option<OnWritten>,
+}
+
+impl fmt::Debug for PendingWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingWrite")
+            .field("length", &self.data.len())
+            .field("writing", &self.on_written.is_none())
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    // Verified pieces not on disk yet, by torrent and piece index.
+    pieces: HashMap<Vec<u8>, BTreeMap<usize, PendingWrite>>,
+    bytes: usize,
+}
+
+enum Job {
+    Write {
+        torrent: Vec<u8>,
+        storage: Arc<dyn Storage>,
+    },
+    Read {
+        torrent: Vec<u8>,
+        storage: Arc<dyn Storage>,
+        piece_index: usize,
+        reply: Sender<Result<Arc<Vec<u8>>, Error>>,
+    },
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Write { .. } => write!(f, "Write"),
+            Self::Read { piece_index, .. } => write!(f, "Read({})", piece_index),
+        }
+    }
+}
+
+// Whole pieces recently read, evicting the least recently used beyond `capacity` bytes.
+#[derive(Debug, Default)]
+struct ReadCache {
+    capacity: usize,
+    size: usize,
+    tick: u64,
+    pieces: HashMap<PieceKey, (Arc<Vec<u8>>, u64)>,
+    // Times each piece was written, so a read that raced a write is not kept.
+    generations: HashMap<PieceKey, u64>,
+}
+
+impl ReadCache {
+    fn get(&mut self, key: &PieceKey) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        self.pieces.get_mut(key).map(|(piece, used)| {
+            *used = tick;
+            Arc::clone(piece)
+        })
+    }
+
+    fn generation(&self, key: &PieceKey) -> u64 {
+        self.generations.get(key).copied().unwrap_or_default()
+    }
+
+    // Keeps a piece read while the piece was at `generation`, unless it was written since.
+    fn insert(&mut self, key: PieceKey, piece: Arc<Vec<u8>>, generation: u64) {
+        if piece.len() > self.capacity || generation != self.generation(&key) {
+            return;
+        }
+
+        self.remove(&key);
+
+        while self.size + piece.len() > self.capacity {
+            let oldest = self
+                .pieces
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+
+        self.tick += 1;
+        self.size += piece.len();
+        self.pieces.insert(key, (piece, self.tick));
+    }
+
+    // Drops a piece that was just written, along with any read of it still under way.
+    fn invalidate(&mut self, key: &PieceKey) {
+        self.remove(key);
+        *self.generations.entry(key.clone()).or_default() += 1;
+    }
+
+    fn remove(&mut self, key: &PieceKey) {
+        if let Some((piece, _)) = self.pieces.remove(key) {
+            self.size -= piece.len();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Caches {
+    pending: Mutex<Pending>,
+    // Signalled whenever written pieces leave `pending`.
+    written: Condvar,
+    read: Mutex<ReadCache>,
+}
+
+// Does the session's disk work, so network threads never wait on the disk while holding a
+// torrent's locks. Pieces waiting in the write-back cache are written along with the ones right
+// after them, and blocks are served from whole pieces kept in an LRU read cache.
+#[derive(Debug)]
+pub struct DiskIo {
+    jobs: Sender<Job>,
+    caches: Arc<Caches>,
+    write_cache_size: usize,
+}
+
+impl Default for DiskIo {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_THREADS,
+            DEFAULT_READ_CACHE_SIZE * MIB,
+            DEFAULT_WRITE_CACHE_SIZE * MIB,
+        )
+    }
+}
+
+impl DiskIo {
+    pub fn new_from_env() -> Self {
+        Self::new(
+            env_setting("DISK_IO_THREADS", DEFAULT_THREADS).max(1),
+            env_setting("READ_CACHE_SIZE", DEFAULT_READ_CACHE_SIZE).saturating_mul(MIB),
+            env_setting("WRITE_CACHE_SIZE", DEFAULT_WRITE_CACHE_SIZE).saturating_mul(MIB),
+        )
+    }
+
+    // The workers stop once the pool is dropped.
+    pub fn new(threads: usize, read_cache_size: usize, write_cache_size: usize) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(queue));
+        let caches = Arc::new(Caches {
+            read: Mutex::new(ReadCache {
+                capacity: read_cache_size,
+                ..ReadCache::default()
+            }),
+            ..Caches::default()
+        });
+
+        for _ in 0..threads {
+            let queue = Arc::clone(&queue);
+            let caches = Arc::clone(&caches);
+
+            thread::spawn(move || Self::work(queue, caches));
+        }
+
+        Self {
+            jobs,
+            caches,
+            write_cache_size,
+        }
+    }
+
+    // `info_hash` tells the torrent's pieces apart in the caches.
+    pub fn for_torrent(
+        self: &Arc<Self>,
+        info_hash: &[u8],
+        storage: Arc<dyn Storage>,
+    ) -> TorrentDisk {
+        TorrentDisk {
+            io: Arc::clone(self),
+            torrent: info_hash.to_vec(),
+            storage,
+        }
+    }
+
+    fn work(queue: Arc<Mutex<Receiver<Job>>>, caches: Arc<Caches>) {
+        loop {
+            let job = match queue.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+
+            match job {
+                Job::Write { torrent, storage } => Self::write(&caches, torrent, storage.as_ref()),
+                Job::Read {
+                    torrent,
+                    storage,
+                    piece_index,
+                    reply,
+                } => {
+                    let key = (torrent, piece_index);
+                    let generation = caches.read.lock().unwrap().generation(&key);
+                    let piece = storage
+                        .read_block(piece_index, 0, storage.layout().piece_size(piece_index))
+                        .map(Arc::new);
+
+                    if let Ok(piece) = &piece {
+                        caches
+                            .read
+                            .lock()
+                            .unwrap()
+                            .insert(key, Arc::clone(piece), generation);
+                    }
+
+                    let _ = reply.send(piece);
+                }
+            }
+        }
+    }
+
+    // Writes the first piece waiting for `torrent` together with the ones following it. The
+    // pieces are served from the write-back cache until they are on disk.
+    fn write(caches: &Caches, torrent: Vec<u8>, storage: &dyn Storage) {
+        let batch: Vec<(usize, Arc<Vec<u8>>, OnWritten)> = {
+            let mut pending = caches.pending.lock().unwrap();
+
+            let waiting = match pending.pieces.get_mut(&torrent) {
+                Some(waiting) => waiting,
+                None => return,
+            };
+
+            let mut batch: Vec<(usize, Arc<Vec<u8>>, OnWritten)> = vec![];
+
+            for (piece_index, write) in waiting.iter_mut() {
+                let follows = batch
+                    .last()
+                    .is_none_or(|(last, _, _)| *last + 1 == *piece_index);
+
+                match (follows, write.on_written.take()) {
+                    (true, Some(on_written)) => {
+                        batch.push((*piece_index, Arc::clone(&write.data), on_written))
+                    }
+                    // Being written by another worker.
+                    (true, None) if batch.is_empty() => continue,
+                    (_, on_written) => {
+                        write.on_written = on_written;
+                        break;
+                    }
+                }
+            }
+
+            batch
+        };
+
+        let first = match batch.first() {
+            Some((first, _, _)) => *first,
+            None => return,
+        };
+
+        let data: Vec<u8> = batch
+            .iter()
+            .flat_map(|(_, data, _)| data.iter().copied())
+            .collect();
+
+        let result = storage.write_block(first, 0, &data);
+
+        log::debug!(
+            "DiskIo::write() - wrote pieces {}..{} in one write",
+            first,
+            first + batch.len()
+        );
+
+        {
+            let mut read = caches.read.lock().unwrap();
+
+            for (piece_index, _, _) in &batch {
+                read.invalidate(&(torrent.clone(), *piece_index));
+            }
+        }
+
+        {
+            let mut pending = caches.pending.lock().unwrap();
+
+            if let Some(waiting) = pending.pieces.get_mut(&torrent) {
+                for (piece_index, _, _) in &batch {
+                    waiting.remove(piece_index);
+                }
+
+                if waiting.is_empty() {
+                    pending.pieces.remove(&torrent);
+                }
+            }
+
+            pending.bytes -= data.len();
+        }
+
+        caches.written.notify_all();
+
+        for (_, _, on_written) in batch {
+            on_written(match &result {
+                Ok(()) => Ok(()),
+                Err(error) => Err(Error::new(error.kind(), error.to_string())),
+            });
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TorrentDisk {
+    io: Arc<DiskIo>,
+    torrent: Vec<u8>,
+    storage: Arc<dyn Storage>,
+}
+
+impl TorrentDisk {
+    // Queues a verified piece to be written, calling `on_written` from the pool once it is on
+    // disk or failed to be. Waits for room first while the write-back cache is full.
+    pub fn write_piece(
+        &self,
+        piece_index: usize,
+        data: Vec<u8>,
+        on_written: impl FnOnce(Result<(), Error>) + Send + 'static,
+    ) {
+        {
+            let mut pending = self.io.caches.pending.lock().unwrap();
+
+            while pending.bytes > 0 && pending.bytes + data.len() > self.io.write_cache_size {
+                pending = self.io.caches.written.wait(pending).unwrap();
+            }
+
+            pending.bytes += data.len();
+            pending
+                .pieces
+                .entry(self.torrent.clone())
+                .or_default()
+                .insert(
+                    piece_index,
+                    PendingWrite {
+                        data: Arc::new(data),
+                        on_written: Some(Box::new(on_written)),
+                    },
+                );
+        }
+
+        let _ = self.io.jobs.send(Job::Write {
+            torrent: self.torrent.clone(),
+            storage: Arc::clone(&self.storage),
+        });
+    }
+
+    // Reads a block, from the caches if its piece is in one, else waiting for a worker to read
+    // the whole piece.
+    pub fn read_block(
+        &self,
+        piece_index: usize,
+        block_offset: usize,
+        length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let block = |piece: &[u8]| {
+            piece
+                .get(block_offset..block_offset + length)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| Error::new(std::io::ErrorKind::InvalidInput, "Out of the piece"))
+        };
+
+        let written = self
+            .io
+            .caches
+            .pending
+            .lock()
+            .unwrap()
+            .pieces
+            .get(&self.torrent)
+            .and_then(|waiting| waiting.get(&piece_index))
+            .map(|write| Arc::clone(&write.data));
+
+        if let Some(piece) = written {
+            return block(&piece);
+        }
+
+        let key = (self.torrent.clone(), piece_index);
+
+        if let Some(piece) = self.io.caches.read.lock().unwrap().get(&key) {
+            return block(&piece);
+        }
+
+        let (reply, response) = mpsc::channel();
+
+        self.io
+            .jobs
+            .send(Job::Read {
+                torrent: self.torrent.clone(),
+                storage: Arc::clone(&self.storage),
+                piece_index,
+                reply,
+            })
+            .map_err(|_| Error::other("Disk pool stopped"))?;
+
+        let piece = response
+            .recv()
+            .map_err(|_| Error::other("Disk pool stopped"))??;
+
+        block(&piece)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::StorageKind;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    #[test]
+    fn test1_pieces_go_through_the_caches() {
+        let io = Arc::new(DiskIo::new(2, 8, MIB));
+        let storage = StorageKind::Memory.open(Path::new(""), vec![(PathBuf::from("file"), 10)], 4);
+        let disk = io.for_torrent(&[1; 20], Arc::clone(&storage));
+        let (written, on_disk) = mpsc::channel();
+
+        for (piece_index, data) in [
+            (1, vec![5, 6, 7, 8]),
+            (0, vec![1, 2, 3, 4]),
+            (2, vec![9, 10]),
+        ] {
+            let written = written.clone();
+            disk.write_piece(piece_index, data, move |result| {
+                written.send((piece_index, result.is_ok())).unwrap()
+            });
+        }
+
+        let mut results: Vec<(usize, bool)> = on_disk.iter().take(3).collect();
+        results.sort_unstable();
+
+        assert_eq!(results, vec![(0, true), (1, true), (2, true)]);
+        assert_eq!(storage.read(0, 10).unwrap(), (1..=10).collect::<Vec<u8>>());
+
+        assert_eq!(disk.read_block(1, 1, 2).unwrap(), vec![6, 7]);
+        assert_eq!(disk.read_block(0, 0, 4).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(disk.read_block(1, 0, 4).unwrap(), vec![5, 6, 7, 8]);
+
+        // Only two pieces fit, reading the last one evicts the least recently used.
+        assert!(disk.read_block(2, 1, 2).is_err());
+
+        let key = |piece_index: usize| (vec![1; 20], piece_index);
+        let mut read_cache = io.caches.read.lock().unwrap();
+
+        assert!(read_cache.get(&key(0)).is_none());
+        assert!(read_cache.get(&key(1)).is_some());
+        assert!(read_cache.get(&key(2)).is_some());
+        assert_eq!(read_cache.size, 6);
+
+        // A read that started before the piece was written again is not kept.
+        let generation = read_cache.generation(&key(0));
+        read_cache.invalidate(&key(0));
+        read_cache.insert(key(0), Arc::new(vec![0; 4]), generation);
+
+        assert!(read_cache.get(&key(0)).is_none());
+    }
+
+    #[test]
+    fn test2_writes_wait_for_room_in_the_write_cache() {
+        // No workers, pieces stay in the write-back cache until written by hand below.
+        let io = Arc::new(DiskIo::new(0, 0, 8));
+        let storage = StorageKind::Memory.open(Path::new(""), vec![(PathBuf::from("file"), 12)], 4);
+        let disk = io.for_torrent(&[2; 20], Arc::clone(&storage));
+
+        disk.write_piece(0, vec![1; 4], |_| {});
+        disk.write_piece(1, vec![2; 4], |_| {});
+
+        let (queued, on_queued) = mpsc::channel();
+        let writer = {
+            let disk = disk.clone();
+
+            thread::spawn(move || {
+                disk.write_piece(2, vec![3; 4], |_| {});
+                queued.send(()).unwrap();
+            })
+        };
+
+        assert!(on_queued.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(disk.read_block(1, 0, 4).unwrap(), vec![2; 4]);
+
+        DiskIo::write(&io.caches, vec![2; 20], storage.as_ref());
+
+        on_queued.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(
+            storage.read(0, 8).unwrap(),
+            [vec![1; 4], vec![2; 4]].concat()
+        );
+        assert_eq!(io.caches.pending.lock().unwrap().bytes, 4);
+    }
+}
@@ -1,11 +1,8 @@
-use crate::file_system::get_sorted_paths;
-
-use std::env;
-use std::fs::{self, remove_dir_all, remove_file, File as Handler};
+use std::fs::File as Handler;
 
 use std::convert::AsRef;
 use std::io::{Error, Read, Seek, SeekFrom, Write};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 pub struct File {
     pathname: String,
@@ -18,6 +15,7 @@ impl File {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(pathname)
     }
 
@@ -66,82 +64,32 @@ impl File {
         piece_size: usize,
         block_size: usize,
         block_offset: usize,
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>, Error> {
         let first_byte = piece_index * piece_size + block_offset;
-        let last_byte = first_byte + block_size;
-
-        self.handler
-            .seek(SeekFrom::Start(first_byte as u64))
-            .expect("Failed to seek");
-
-        let mut buf = vec![0; last_byte - first_byte];
-        self.handler
-            .read_exact(&mut buf)
-            .expect("Failed to read bytes");
 
-        buf
-    }
+        self.handler.seek(SeekFrom::Start(first_byte as u64))?;
 
-    pub fn new_file_from_piece<T: AsRef<Path>>(piece: &[u8], pathname: T) -> Result<Self, Error> {
-        let pathname = pathname
-            .as_ref()
-            .to_str()
-            .expect("Error converting to str")
-            .to_owned();
-
-        let dir = fs::create_dir_all(format!(
-            "{}/{}",
-            env::var("TEMP_PATH")
-                .unwrap_or_else(|_| "".to_string())
-                .as_str(),
-            pathname.split(".piece").collect::<Vec<&str>>()[0]
-        ));
-        if dir.is_err() {}
-
-        let mut handler = File::open_file(&format!(
-            "{}/{}/{}",
-            env::var("TEMP_PATH")
-                .unwrap_or_else(|_| "".to_string())
-                .as_str(),
-            pathname.split(".piece").collect::<Vec<&str>>()[0],
-            pathname
-        ))?;
-        handler.write_all(piece)?;
-
-        Ok(Self { handler, pathname })
-    }
+        let mut buf = vec![0; block_size];
+        self.handler.read_exact(&mut buf)?;
 
-    pub fn concat(&mut self, piece: PathBuf) -> Result<(), Error> {
-        let piece_file = File::new(&piece).get_contents();
-        self.handler.write_all(&piece_file)?;
-        remove_file(piece)?;
-        Ok(())
+        Ok(buf)
     }
+}
 
-    pub fn join_pieces<T: AsRef<Path>>(path_from: T, path_to: T) -> Result<(), Error> {
-        let sorted_path = get_sorted_paths(path_from.as_ref().to_str().unwrap());
-
-        let path_to = path_to
-            .as_ref()
-            .to_str()
-            .expect("Error converting to str")
-            .to_owned();
-
-        let mut split: Vec<&str> = path_to.split('/').collect();
-
-        split.pop();
-
-        let dir = split.join("/");
-
-        fs::create_dir_all(dir)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::remove_file;
 
-        let mut new_file = Self::new(&path_to);
+    #[test]
+    fn test1_out_of_range_block_is_an_error() {
+        let pathname = env::temp_dir().join("sitos_test1_out_of_range_block");
+        let mut file = File::with_contents(&pathname, [1_u8; 10]);
 
-        for piece_path in sorted_path {
-            new_file.concat(piece_path)?
-        }
+        assert_eq!(file.get_block(1, 4, 2, 1).unwrap(), vec![1, 1]);
+        assert!(file.get_block(2, 4, 4, 0).is_err());
 
-        remove_dir_all(path_from.as_ref())?;
-        Ok(())
+        remove_file(pathname).unwrap();
     }
 }
@@ -1,5 +1,9 @@
+pub use disk_io::{DiskIo, TorrentDisk};
 pub use file::File;
-pub use utils::get_sorted_paths;
+pub use storage::{
+    Allocation, FileState, FileStorage, Layout, MemoryStorage, MmapStorage, Storage, StorageKind,
+};
 
+mod disk_io;
 mod file;
-mod utils;
+mod storage;
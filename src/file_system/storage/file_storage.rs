@@ -0,0 +1,224 @@
+use super::layout::{self, Layout};
+use super::{Allocation, FileState, Storage};
+use std::fs::{self, File as Handler};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug)]
+struct Files {
+    directory: PathBuf,
+    handlers: Vec<Option<Handler>>,
+    complete: bool,
+    deleted: bool,
+}
+
+// Pieces are written straight to their offset in the torrent's files, spanning file boundaries
+// where needed. Until the download completes every file is kept as `<name>.part`, then they are
+// all renamed in place.
+#[derive(Debug)]
+pub struct FileStorage {
+    layout: Layout,
+    allocation: Allocation,
+    files: Mutex<Files>,
+}
+
+impl FileStorage {
+    pub fn new(directory: &Path, layout: Layout, allocation: Allocation) -> Self {
+        Self {
+            allocation,
+            files: Mutex::new(Files {
+                directory: directory.to_path_buf(),
+                handlers: (0..layout.files()).map(|_| None).collect(),
+                complete: layout.is_complete_in(directory),
+                deleted: false,
+            }),
+            layout,
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Files>, Error> {
+        let files = self.files.lock().unwrap();
+
+        match files.deleted {
+            true => Err(Error::new(ErrorKind::NotFound, "Storage was deleted")),
+            false => Ok(files),
+        }
+    }
+
+    // Created and preallocated on first use.
+    fn handler<'a>(&self, files: &'a mut Files, index: usize) -> Result<&'a mut Handler, Error> {
+        if files.handlers[index].is_none() {
+            let path = self
+                .layout
+                .current_path(&files.directory, index, files.complete);
+            let handler = layout::open(&path, self.layout.length(index), self.allocation)?;
+
+            files.handlers[index] = Some(handler);
+        }
+
+        Ok(files.handlers[index]
+            .as_mut()
+            .expect("Handler was just opened"))
+    }
+}
+
+impl Storage for FileStorage {
+    fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        let files = self.files.lock().unwrap();
+
+        (0..self.layout.files())
+            .map(|index| self.layout.path(&files.directory, index))
+            .collect()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.files.lock().unwrap().complete
+    }
+
+    fn allocate(&self) -> Result<(), Error> {
+        let mut files = self.lock()?;
+
+        for index in 0..self.layout.files() {
+            self.handler(&mut files, index)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        let mut files = self.lock()?;
+        let mut written = 0;
+
+        for (index, file_offset, length) in self.layout.spans(offset, data.len() as u64)? {
+            let handler = self.handler(&mut files, index)?;
+
+            handler.seek(SeekFrom::Start(file_offset))?;
+            handler.write_all(&data[written..written + length])?;
+            written += length;
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Error> {
+        let mut files = self.lock()?;
+        let mut data = vec![0; length];
+        let mut read = 0;
+
+        for (index, file_offset, length) in self.layout.spans(offset, length as u64)? {
+            let handler = self.handler(&mut files, index)?;
+
+            handler.seek(SeekFrom::Start(file_offset))?;
+            handler.read_exact(&mut data[read..read + length])?;
+            read += length;
+        }
+
+        Ok(data)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        let mut files = self.files.lock().unwrap();
+
+        for handler in files.handlers.iter_mut().flatten() {
+            handler.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    // Syncs every file and renames it from `.part` to its final name.
+    fn complete(&self) -> Result<(), Error> {
+        let mut files = self.lock()?;
+
+        if files.complete {
+            return Ok(());
+        }
+
+        for index in 0..self.layout.files() {
+            // Files never written to, e.g. in torrents with empty files, are created now.
+            self.handler(&mut files, index)?.sync_all()?;
+            files.handlers[index] = None;
+
+            fs::rename(
+                self.layout.part_path(&files.directory, index),
+                self.layout.path(&files.directory, index),
+            )?;
+        }
+
+        files.complete = true;
+
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), Error> {
+        let mut files = self.lock()?;
+
+        for index in 0..self.layout.files() {
+            files.handlers[index] = None;
+            layout::remove_file(&self.layout.current_path(
+                &files.directory,
+                index,
+                files.complete,
+            ))?;
+        }
+
+        files.deleted = true;
+        self.layout.remove_empty_directories(&files.directory);
+
+        Ok(())
+    }
+
+    fn file_states(&self) -> Vec<FileState> {
+        let files = self.files.lock().unwrap();
+
+        self.layout.file_states(&files.directory, files.complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test1_pieces_spanning_files_end_up_in_place() {
+        let directory = env::temp_dir().join(format!("sitos_storage_test_{}", std::process::id()));
+        let layout = Layout::new(
+            vec![
+                (PathBuf::from("first"), 5),
+                (PathBuf::from("nested").join("second"), 7),
+            ],
+            4,
+        );
+        let storage = FileStorage::new(&directory, layout, Allocation::Full);
+
+        storage.write_block(2, 0, &[9, 10, 11, 12]).unwrap();
+        storage.write_block(1, 0, &[5, 6, 7, 8]).unwrap();
+        storage.write_block(0, 0, &[1, 2, 3, 4]).unwrap();
+
+        assert_eq!(fs::metadata(directory.join("first.part")).unwrap().len(), 5);
+        assert_eq!(storage.read_block(1, 0, 4).unwrap(), vec![5, 6, 7, 8]);
+        assert!(storage.write_block(2, 2, &[0; 4]).is_err());
+
+        storage.complete().unwrap();
+
+        assert!(storage.is_complete());
+        assert_eq!(
+            fs::read(directory.join("first")).unwrap(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            fs::read(directory.join("nested").join("second")).unwrap(),
+            vec![6, 7, 8, 9, 10, 11, 12]
+        );
+        assert!(!directory.join("first.part").exists());
+        assert_eq!(storage.read(3, 4).unwrap(), vec![4, 5, 6, 7]);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use super::{FileStorage, Layout, MemoryStorage, MmapStorage};
+use crate::utils::env_setting;
+use sha1::{Digest, Sha1};
+use std::fmt::Debug;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Allocation {
+    // Files are given their full length without writing it, on filesystems that support holes.
+    Sparse,
+    // Files are filled with zeros up front, so running out of space fails early.
+    Full,
+}
+
+impl Allocation {
+    pub fn from_env() -> Self {
+        match env_setting("PREALLOCATION", String::new()).as_str() {
+            "full" => Self::Full,
+            _ => Self::Sparse,
+        }
+    }
+}
+
+// None if the file does not exist.
+pub type FileState = Option<(u64, SystemTime)>;
+
+pub trait Storage: Debug + Send + Sync {
+    fn layout(&self) -> &Layout;
+
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Error>;
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), Error>;
+
+    // Reserves the space for the whole torrent ahead of the first write.
+    fn allocate(&self) -> Result<(), Error>;
+
+    fn flush(&self) -> Result<(), Error>;
+
+    // Gives the files their final names.
+    fn complete(&self) -> Result<(), Error>;
+
+    fn is_complete(&self) -> bool;
+
+    // The data can be neither read nor written afterwards.
+    fn delete(&self) -> Result<(), Error>;
+
+    // Final paths of the files, whether or not they are complete yet.
+    fn paths(&self) -> Vec<PathBuf>;
+
+    // To tell whether the files changed while the torrent was not running.
+    fn file_states(&self) -> Vec<FileState>;
+
+    fn total_length(&self) -> u64 {
+        self.layout().total_length()
+    }
+
+    fn read_block(
+        &self,
+        piece_index: usize,
+        block_offset: usize,
+        length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.read(self.layout().offset_of(piece_index, block_offset), length)
+    }
+
+    fn write_block(
+        &self,
+        piece_index: usize,
+        block_offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.write(self.layout().offset_of(piece_index, block_offset), data)
+    }
+
+    fn hash_piece(&self, piece_index: usize) -> Result<[u8; 20], Error> {
+        let piece = self.read_block(piece_index, 0, self.layout().piece_size(piece_index))?;
+
+        Ok(Sha1::digest(piece).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    File,
+    Mmap,
+    // Nothing on disk, the data lives as long as the torrent does.
+    Memory,
+}
+
+impl StorageKind {
+    // STORAGE_BACKENDS entries take precedence over STORAGE_BACKEND.
+    pub fn for_torrent(name: &str) -> Self {
+        let chosen = env_setting("STORAGE_BACKENDS", String::new())
+            .split(';')
+            .filter_map(|entry| entry.split_once('='))
+            .find(|(torrent, _)| torrent.trim() == name)
+            .and_then(|(_, kind)| Self::parse(kind));
+
+        chosen
+            .or_else(|| Self::parse(&env_setting("STORAGE_BACKEND", String::new())))
+            .unwrap_or(Self::File)
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind.trim() {
+            "file" => Some(Self::File),
+            "mmap" => Some(Self::Mmap),
+            "memory" => Some(Self::Memory),
+            _ => None,
+        }
+    }
+
+    pub fn open(
+        self,
+        directory: &Path,
+        files: Vec<(PathBuf, u64)>,
+        piece_length: usize,
+    ) -> Arc<dyn Storage> {
+        let layout = Layout::new(files, piece_length);
+
+        match self {
+            Self::File => Arc::new(FileStorage::new(directory, layout, Allocation::from_env())),
+            Self::Mmap => Arc::new(MmapStorage::new(directory, layout, Allocation::from_env())),
+            Self::Memory => Arc::new(MemoryStorage::new(directory, layout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test1_every_backend_keeps_pieces_across_files() {
+        let root = env::temp_dir().join(format!("sitos_storage_test_{}", std::process::id()));
+        let files = vec![
+            (PathBuf::from("first"), 5),
+            (PathBuf::from("nested").join("second"), 7),
+        ];
+
+        for kind in [StorageKind::File, StorageKind::Mmap, StorageKind::Memory] {
+            let directory = root.join(format!("{:?}", kind));
+            let storage = kind.open(&directory, files.clone(), 4);
+
+            storage.allocate().unwrap();
+            storage.write_block(2, 0, &[9, 10, 11, 12]).unwrap();
+            storage.write_block(1, 0, &[5, 6, 7, 8]).unwrap();
+            storage.write_block(0, 0, &[1, 2, 3, 4]).unwrap();
+
+            assert_eq!(storage.read_block(1, 0, 4).unwrap(), vec![5, 6, 7, 8]);
+            assert!(storage.write_block(2, 2, &[0; 4]).is_err());
+            assert_eq!(
+                storage.hash_piece(2).unwrap(),
+                <[u8; 20]>::from(Sha1::digest([9, 10, 11, 12]))
+            );
+
+            storage.complete().unwrap();
+
+            assert!(storage.is_complete());
+            assert_eq!(storage.read(3, 4).unwrap(), vec![4, 5, 6, 7]);
+            assert_eq!(storage.paths()[1], directory.join("nested").join("second"));
+
+            if kind != StorageKind::Memory {
+                assert_eq!(
+                    fs::read(directory.join("first")).unwrap(),
+                    vec![1, 2, 3, 4, 5]
+                );
+                assert!(!directory.join("first.part").exists());
+            }
+
+            storage.delete().unwrap();
+
+            assert!(storage.read(0, 1).is_err());
+            assert!(storage.write(0, &[1]).is_err());
+            assert!(!directory.join("nested").exists());
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use super::{Allocation, FileState};
+use std::fs::{self, File as Handler};
+use std::io::{Error, ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const PART_EXTENSION: &str = "part";
+const ZEROS_CHUNK: usize = 1024 * 1024;
+
+#[derive(Clone, Debug)]
+struct Entry {
+    // Relative to the storage's directory.
+    path: PathBuf,
+    length: u64,
+    // Where the file starts within the torrent's data.
+    offset: u64,
+}
+
+// How a torrent's data is laid out across its files and split into pieces.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    entries: Vec<Entry>,
+    piece_length: u64,
+    total_length: u64,
+}
+
+impl Layout {
+    // Files are listed in the order the torrent lists them.
+    pub fn new(files: Vec<(PathBuf, u64)>, piece_length: usize) -> Self {
+        let mut offset = 0;
+
+        let entries = files
+            .into_iter()
+            .map(|(path, length)| {
+                let entry = Entry {
+                    path,
+                    length,
+                    offset,
+                };
+
+                offset += length;
+                entry
+            })
+            .collect();
+
+        Self {
+            entries,
+            piece_length: piece_length as u64,
+            total_length: offset,
+        }
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    pub fn files(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn length(&self, index: usize) -> u64 {
+        self.entries[index].length
+    }
+
+    pub fn path(&self, directory: &Path, index: usize) -> PathBuf {
+        directory.join(&self.entries[index].path)
+    }
+
+    // Where the file is kept until the download completes.
+    pub fn part_path(&self, directory: &Path, index: usize) -> PathBuf {
+        let path = self.path(directory, index);
+
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}", PART_EXTENSION));
+
+        path.with_file_name(file_name)
+    }
+
+    pub fn current_path(&self, directory: &Path, index: usize, complete: bool) -> PathBuf {
+        match complete {
+            true => self.path(directory, index),
+            false => self.part_path(directory, index),
+        }
+    }
+
+    pub fn offset_of(&self, piece_index: usize, block_offset: usize) -> u64 {
+        piece_index as u64 * self.piece_length + block_offset as u64
+    }
+
+    // Only the last piece may be shorter than the rest.
+    pub fn piece_size(&self, piece_index: usize) -> usize {
+        let start = self.offset_of(piece_index, 0).min(self.total_length);
+
+        (self.total_length - start).min(self.piece_length) as usize
+    }
+
+    // Each file's index and offset within it, along with how many of the bytes go there.
+    pub fn spans(&self, offset: u64, length: u64) -> Result<Vec<(usize, u64, usize)>, Error> {
+        let end = offset + length;
+
+        if end > self.total_length {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Out of the torrent's data",
+            ));
+        }
+
+        Ok(self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.offset < end && offset < entry.offset + entry.length)
+            .map(|(index, entry)| {
+                let start = offset.max(entry.offset);
+                let stop = end.min(entry.offset + entry.length);
+
+                (index, start - entry.offset, (stop - start) as usize)
+            })
+            .collect())
+    }
+
+    // Every file has its final name and none of them is still kept as `.part`.
+    pub fn is_complete_in(&self, directory: &Path) -> bool {
+        (0..self.files()).all(|index| {
+            self.path(directory, index).exists() && !self.part_path(directory, index).exists()
+        })
+    }
+
+    pub fn file_states(&self, directory: &Path, complete: bool) -> Vec<FileState> {
+        (0..self.files())
+            .map(|index| {
+                let metadata = fs::metadata(self.current_path(directory, index, complete)).ok()?;
+
+                Some((metadata.len(), metadata.modified().ok()?))
+            })
+            .collect()
+    }
+
+    // Removes the directories of the files inside `directory` that were left empty.
+    pub fn remove_empty_directories(&self, directory: &Path) {
+        for index in 0..self.files() {
+            let mut path = self.path(directory, index);
+
+            while path.pop() && path.starts_with(directory) && path != directory {
+                if fs::remove_dir(&path).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Creates the file with its directories and preallocates it to `length`.
+pub fn open(path: &Path, length: u64, allocation: Allocation) -> Result<Handler, Error> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut handler = Handler::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    let current_length = handler.metadata()?.len();
+
+    if current_length < length {
+        match allocation {
+            Allocation::Sparse => handler.set_len(length)?,
+            Allocation::Full => {
+                let zeros = vec![0; ZEROS_CHUNK];
+                let mut position = current_length;
+
+                handler.seek(SeekFrom::Start(position))?;
+
+                while position < length {
+                    let chunk = (length - position).min(ZEROS_CHUNK as u64) as usize;
+                    handler.write_all(&zeros[..chunk])?;
+                    position += chunk as u64;
+                }
+            }
+        }
+    }
+
+    Ok(handler)
+}
+
+// The file may never have been created.
+pub fn remove_file(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,103 @@
+use super::layout::Layout;
+use super::{FileState, Storage};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug)]
+struct Data {
+    directory: PathBuf,
+    // None once deleted.
+    bytes: Option<Vec<u8>>,
+    complete: bool,
+}
+
+// For downloads that never touch the disk, such as tests of whole transfers. Its paths are
+// where the files would be, none of them is created.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    layout: Layout,
+    data: Mutex<Data>,
+}
+
+impl MemoryStorage {
+    pub fn new(directory: &Path, layout: Layout) -> Self {
+        Self {
+            data: Mutex::new(Data {
+                directory: directory.to_path_buf(),
+                bytes: Some(vec![0; layout.total_length() as usize]),
+                complete: false,
+            }),
+            layout,
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Error> {
+        self.layout.spans(offset, length as u64)?;
+
+        let data = self.data.lock().unwrap();
+        let bytes = data.bytes.as_ref().ok_or_else(deleted)?;
+        let start = offset as usize;
+
+        Ok(bytes[start..start + length].to_vec())
+    }
+
+    fn write(&self, offset: u64, bytes: &[u8]) -> Result<(), Error> {
+        self.layout.spans(offset, bytes.len() as u64)?;
+
+        let mut data = self.data.lock().unwrap();
+        let stored = data.bytes.as_mut().ok_or_else(deleted)?;
+        let start = offset as usize;
+
+        stored[start..start + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn allocate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn complete(&self) -> Result<(), Error> {
+        self.data.lock().unwrap().complete = true;
+
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.data.lock().unwrap().complete
+    }
+
+    fn delete(&self) -> Result<(), Error> {
+        self.data.lock().unwrap().bytes = None;
+
+        Ok(())
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        let data = self.data.lock().unwrap();
+
+        (0..self.layout.files())
+            .map(|index| self.layout.path(&data.directory, index))
+            .collect()
+    }
+
+    // Nothing is on disk, so the data never outlives a restart.
+    fn file_states(&self) -> Vec<FileState> {
+        vec![None; self.layout.files()]
+    }
+}
+
+fn deleted() -> Error {
+    Error::new(ErrorKind::NotFound, "Storage was deleted")
+}
@@ -0,0 +1,200 @@
+use super::layout::{self, Layout};
+use super::{Allocation, FileState, Storage};
+use memmap2::MmapMut;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug)]
+struct Maps {
+    directory: PathBuf,
+    maps: Vec<Option<MmapMut>>,
+    complete: bool,
+    deleted: bool,
+}
+
+// The same files FileStorage uses, mapped into memory so blocks are copied in and out of the
+// page cache without a system call each. Files are mapped whole as they are first used, so the
+// torrent must fit in the address space.
+#[derive(Debug)]
+pub struct MmapStorage {
+    layout: Layout,
+    allocation: Allocation,
+    maps: Mutex<Maps>,
+}
+
+impl MmapStorage {
+    pub fn new(directory: &Path, layout: Layout, allocation: Allocation) -> Self {
+        Self {
+            allocation,
+            maps: Mutex::new(Maps {
+                directory: directory.to_path_buf(),
+                maps: (0..layout.files()).map(|_| None).collect(),
+                complete: layout.is_complete_in(directory),
+                deleted: false,
+            }),
+            layout,
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Maps>, Error> {
+        let maps = self.maps.lock().unwrap();
+
+        match maps.deleted {
+            true => Err(Error::new(ErrorKind::NotFound, "Storage was deleted")),
+            false => Ok(maps),
+        }
+    }
+
+    // Created and preallocated on first use. Empty files are only created, as there is nothing
+    // to map.
+    fn map<'a>(&self, maps: &'a mut Maps, index: usize) -> Result<Option<&'a mut MmapMut>, Error> {
+        let length = self.layout.length(index);
+
+        if maps.maps[index].is_none() {
+            let path = self
+                .layout
+                .current_path(&maps.directory, index, maps.complete);
+            let handler = layout::open(&path, length, self.allocation)?;
+
+            if length == 0 {
+                return Ok(None);
+            }
+
+            // SAFETY: the file is only ever accessed through this mapping while it is open, any
+            // change made to it from outside the client is undefined behaviour we cannot prevent.
+            maps.maps[index] = Some(unsafe { MmapMut::map_mut(&handler)? });
+        }
+
+        Ok(maps.maps[index].as_mut())
+    }
+
+    fn unmap(maps: &mut Maps) -> Result<(), Error> {
+        for map in maps.maps.iter_mut() {
+            if let Some(map) = map.take() {
+                map.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for MmapStorage {
+    fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Error> {
+        let mut maps = self.lock()?;
+        let mut data = Vec::with_capacity(length);
+
+        for (index, file_offset, length) in self.layout.spans(offset, length as u64)? {
+            let map = self.map(&mut maps, index)?.expect("Spans skip empty files");
+            let start = file_offset as usize;
+
+            data.extend_from_slice(&map[start..start + length]);
+        }
+
+        Ok(data)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        let mut maps = self.lock()?;
+        let mut written = 0;
+
+        for (index, file_offset, length) in self.layout.spans(offset, data.len() as u64)? {
+            let map = self.map(&mut maps, index)?.expect("Spans skip empty files");
+            let start = file_offset as usize;
+
+            map[start..start + length].copy_from_slice(&data[written..written + length]);
+            written += length;
+        }
+
+        Ok(())
+    }
+
+    fn allocate(&self) -> Result<(), Error> {
+        let mut maps = self.lock()?;
+
+        for index in 0..self.layout.files() {
+            self.map(&mut maps, index)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        let maps = self.maps.lock().unwrap();
+
+        for map in maps.maps.iter().flatten() {
+            map.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // Writes every mapping back before renaming the files.
+    fn complete(&self) -> Result<(), Error> {
+        let mut maps = self.lock()?;
+
+        if maps.complete {
+            return Ok(());
+        }
+
+        for index in 0..self.layout.files() {
+            self.map(&mut maps, index)?;
+        }
+
+        Self::unmap(&mut maps)?;
+
+        for index in 0..self.layout.files() {
+            fs::rename(
+                self.layout.part_path(&maps.directory, index),
+                self.layout.path(&maps.directory, index),
+            )?;
+        }
+
+        maps.complete = true;
+
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.maps.lock().unwrap().complete
+    }
+
+    fn delete(&self) -> Result<(), Error> {
+        let mut maps = self.lock()?;
+
+        maps.maps.iter_mut().for_each(|map| *map = None);
+
+        for index in 0..self.layout.files() {
+            layout::remove_file(
+                &self
+                    .layout
+                    .current_path(&maps.directory, index, maps.complete),
+            )?;
+        }
+
+        maps.deleted = true;
+        self.layout.remove_empty_directories(&maps.directory);
+
+        Ok(())
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        let maps = self.maps.lock().unwrap();
+
+        (0..self.layout.files())
+            .map(|index| self.layout.path(&maps.directory, index))
+            .collect()
+    }
+
+    fn file_states(&self) -> Vec<FileState> {
+        let maps = self.maps.lock().unwrap();
+
+        self.layout.file_states(&maps.directory, maps.complete)
+    }
+}
@@ -0,0 +1,11 @@
+pub use file_storage::FileStorage;
+pub use index::{Allocation, FileState, Storage, StorageKind};
+pub use layout::Layout;
+pub use memory_storage::MemoryStorage;
+pub use mmap_storage::MmapStorage;
+
+mod file_storage;
+mod index;
+mod layout;
+mod memory_storage;
+mod mmap_storage;
@@ -1,5 +1,5 @@
-use super::{peers, torrents};
-use crate::bit_torrent::BitTorrent;
+use super::{peers, status, torrents};
+use crate::bit_torrent::{BandwidthScheduler, BitTorrent, IpFilter, Schedule};
 use gtk::glib;
 use gtk::glib::Receiver as GtkReceiver;
 use gtk::glib::Sender as GtkSender;
@@ -9,12 +9,18 @@ use gtk::Builder;
 use std::collections::HashMap;
 use std::env;
 use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex, Weak};
 use std::thread;
+use std::time::Duration;
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const BLOCKED_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
 
 pub fn build_ui(application: &gtk::Application) {
     // Comunication
     let bit_torrent_instance = BitTorrent::new();
+    let bandwidth = bit_torrent_instance.bandwidth();
+    let ip_filter = bit_torrent_instance.ip_filter();
     let bit_torrent_instance = Arc::new(Mutex::new(bit_torrent_instance));
     let bit_torrent_instance_clone = Arc::clone(&bit_torrent_instance);
 
@@ -34,9 +40,25 @@ pub fn build_ui(application: &gtk::Application) {
     let gtk_tx_peers = Mutex::new(gtk_tx_peers);
     let gtk_tx_peers = Arc::new(gtk_tx_peers);
 
+    let (gtk_tx_status, gtk_rx_status): (
+        GtkSender<status::StatusData>,
+        GtkReceiver<status::StatusData>,
+    ) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+    report_blocked_attempts(Arc::downgrade(&ip_filter), gtk_tx_status.clone());
+
+    match Schedule::new_from_env() {
+        Ok(schedule) => {
+            BandwidthScheduler::new(Arc::clone(&bandwidth), schedule, gtk_tx_status).activate();
+        }
+        Err(error) => log::error!("build_ui() - Invalid bandwidth schedule: {}", error),
+    }
+
     let remove_senders: HashMap<String, Sender<String>> = HashMap::new();
     let remove_senders = Arc::new(Mutex::new(remove_senders));
     let remove_senders_clone = Arc::clone(&remove_senders);
+    let remove_senders_shutdown = Arc::clone(&remove_senders);
+    let bit_torrent_instance_shutdown = Arc::clone(&bit_torrent_instance);
 
     thread::spawn(move || {
         for received in path_rx {
@@ -60,8 +82,20 @@ pub fn build_ui(application: &gtk::Application) {
     let window: ApplicationWindow = builder.object("window").expect("Couldn't get window");
     window.set_application(Some(application));
 
+    // Removing every torrent lets their trackers know we are leaving.
+    window.connect_destroy(move |_| {
+        for remove_tx in remove_senders_shutdown.lock().unwrap().values() {
+            let _ = remove_tx.send("End".to_string());
+        }
+
+        bit_torrent_instance_shutdown
+            .lock()
+            .unwrap()
+            .shutdown(SHUTDOWN_TIMEOUT);
+    });
+
     let torrents_data = vec![];
-    torrents::get_view(
+    let treeview_torrent = torrents::get_view(
         &builder,
         &torrents_data,
         gtk_rx_torrent,
@@ -72,6 +106,8 @@ pub fn build_ui(application: &gtk::Application) {
     let peers_data = vec![];
     peers::get_view(&builder, &peers_data, gtk_rx_peers);
 
+    status::get_view(&builder, gtk_rx_status);
+
     // Dialogs
     let help_dialog: gtk::MessageDialog = builder
         .object("help_dialog")
@@ -86,12 +122,28 @@ pub fn build_ui(application: &gtk::Application) {
         .object("d_path")
         .expect("Couldn't get download path");
 
+    let download_limit: gtk::Entry = builder
+        .object("download_limit")
+        .expect("Couldn't get download limit");
+    let upload_limit: gtk::Entry = builder
+        .object("upload_limit")
+        .expect("Couldn't get upload limit");
+    let torrent_download_limit: gtk::Entry = builder
+        .object("torrent_download_limit")
+        .expect("Couldn't get torrent download limit");
+    let torrent_upload_limit: gtk::Entry = builder
+        .object("torrent_upload_limit")
+        .expect("Couldn't get torrent upload limit");
+
     download_path.set_text(
         env::var("DOWNLOAD_PATH")
             .unwrap_or_else(|_| "".to_string())
             .as_str(),
     );
 
+    let bandwidth_clone = Arc::clone(&bandwidth);
+    let treeview_torrent_clone = treeview_torrent.clone();
+
     builder.connect_signals(move |_, handler_name| match handler_name {
         "on_help_btn_open_clicked" => Box::new(
             glib::clone!(@weak help_dialog => @default-return None, move |_| {
@@ -106,14 +158,39 @@ pub fn build_ui(application: &gtk::Application) {
             }),
         ),
         "on_btn_settings_open_clicked" => Box::new(
-            glib::clone!(@weak settings_dialog => @default-return None, move |_| {
+            glib::clone!(@weak settings_dialog, @weak download_limit, @weak upload_limit, @weak torrent_download_limit, @weak torrent_upload_limit, @strong bandwidth_clone, @strong treeview_torrent_clone => @default-return None, move |_| {
+                let (download_rate, upload_rate) = bandwidth_clone.global_limits();
+                download_limit.set_text(&(download_rate / 1024).to_string());
+                upload_limit.set_text(&(upload_rate / 1024).to_string());
+
+                let (download_rate, upload_rate) = match torrents::selected_pathname(&treeview_torrent_clone) {
+                    Some(pathname) => bandwidth_clone.torrent_limits(&pathname),
+                    None => (0, 0),
+                };
+                torrent_download_limit.set_text(&(download_rate / 1024).to_string());
+                torrent_upload_limit.set_text(&(upload_rate / 1024).to_string());
+
                 settings_dialog.run();
                 None
             }),
         ),
         "on_btn_settings_close_clicked" => Box::new(
-            glib::clone!(@weak settings_dialog, @weak download_path => @default-return None, move |_| {
+            glib::clone!(@weak settings_dialog, @weak download_path, @weak download_limit, @weak upload_limit, @weak torrent_download_limit, @weak torrent_upload_limit, @strong bandwidth, @strong treeview_torrent => @default-return None, move |_| {
                 env::set_var("DOWNLOAD_PATH", download_path.text());
+
+                bandwidth.set_global_limits(
+                    parse_rate_limit(&download_limit),
+                    parse_rate_limit(&upload_limit),
+                );
+
+                if let Some(pathname) = torrents::selected_pathname(&treeview_torrent) {
+                    bandwidth.set_torrent_limits(
+                        &pathname,
+                        parse_rate_limit(&torrent_download_limit),
+                        parse_rate_limit(&torrent_upload_limit),
+                    );
+                }
+
                 settings_dialog.hide();
                 None
             }),
@@ -135,3 +212,41 @@ pub fn build_ui(application: &gtk::Application) {
 
     window.show_all();
 }
+
+fn report_blocked_attempts(
+    ip_filter: Weak<IpFilter>,
+    gtk_tx_status: GtkSender<status::StatusData>,
+) {
+    thread::spawn(move || {
+        let mut reported = None;
+
+        while let Some(ip_filter) = ip_filter.upgrade() {
+            let blocked = ip_filter.blocked();
+            drop(ip_filter);
+
+            if reported != Some(blocked) {
+                if gtk_tx_status
+                    .send(status::StatusData::BlockedAttempts(blocked))
+                    .is_err()
+                {
+                    break;
+                }
+
+                reported = Some(blocked);
+            }
+
+            thread::sleep(BLOCKED_REFRESH_INTERVAL);
+        }
+    });
+}
+
+// The entries are in KiB/s, invalid or too large input meaning unlimited.
+fn parse_rate_limit(entry: &gtk::Entry) -> u64 {
+    entry
+        .text()
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|limit| limit.checked_mul(1024))
+        .unwrap_or(0)
+}
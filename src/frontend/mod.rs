@@ -3,4 +3,5 @@ pub use index::*;
 pub mod index;
 pub mod views;
 pub use views::peers;
+pub use views::status;
 pub use views::torrents;
@@ -15,6 +15,7 @@ pub struct PeersData {
     pub connection: ConnectionData,
     pub torrent_pathname: String,
     pub remove: bool,
+    pub local: bool,
 }
 #[derive(Debug)]
 pub struct ConnectionData {
@@ -28,9 +29,8 @@ impl PeersData {
         let mut down_speed = String::from("-");
 
         if peer_connection.instant.elapsed().as_millis() > 0 {
-            let down_speed_num = (peer_connection.common_information.piece_length as f64
-                / (peer_connection.instant.elapsed().as_millis() as f64 / 1000_f64))
-                as f64;
+            let down_speed_num = peer_connection.common_information.piece_length as f64
+                / (peer_connection.instant.elapsed().as_millis() as f64 / 1000_f64);
 
             if down_speed_num / 1073741824_f64 > 1_f64 {
                 down_speed = format!("{:.2} GB/s", down_speed_num / 1073741824_f64);
@@ -60,6 +60,7 @@ impl PeersData {
             },
             torrent_pathname: peer_connection.common_information.torrent_pathname.clone(),
             remove,
+            local: peer_connection.peer.local,
         };
         peer_connection
             .common_information
@@ -105,7 +106,6 @@ pub fn get_view(builder: &gtk::Builder, data: &[PeersData], gtk_rx_peers: GtkRec
     add_columns_peers(&model_peers, &treeview_peers);
 
     gtk_rx_peers.attach(None, move |msg| {
-        let msg = msg;
         {
             let mut found = false;
             if model_peers.iter_children(None).is_some() {
@@ -145,7 +145,8 @@ pub fn get_view(builder: &gtk::Builder, data: &[PeersData], gtk_rx_peers: GtkRec
 }
 
 fn create_model_peers(data: &[PeersData]) -> gtk::ListStore {
-    let col_types: [glib::Type; 6] = [
+    let col_types: [glib::Type; 7] = [
+        glib::Type::STRING,
         glib::Type::STRING,
         glib::Type::STRING,
         glib::Type::STRING,
@@ -156,17 +157,19 @@ fn create_model_peers(data: &[PeersData]) -> gtk::ListStore {
 
     let store = gtk::ListStore::new(&col_types);
 
-    for (_d_idx, d) in data.iter().enumerate() {
+    for d in data.iter() {
         let down_speed: String = format!("{}KB/S", &d.connection.down_speed);
         let up_speed: String = format!("{}KB/S", &d.connection.up_speed);
 
-        let values: [(u32, &dyn ToValue); 6] = [
+        let source = peer_source(d);
+        let values: [(u32, &dyn ToValue); 7] = [
             (0, &d.ip),
             (1, &d.port),
             (2, &down_speed),
             (3, &up_speed),
             (4, &d.connection.status),
             (5, &d.torrent_pathname),
+            (6, &source),
         ];
         store.set(&store.append(), &values);
     }
@@ -235,28 +238,49 @@ fn add_columns_peers(_model: &Rc<gtk::ListStore>, treeview: &gtk::TreeView) {
         column.set_sort_column_id(5);
         treeview.append_column(&column);
     }
+    // Column for Source
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Source");
+        column.add_attribute(&renderer, "text", 6);
+        column.set_sort_column_id(6);
+        treeview.append_column(&column);
+    }
+}
+
+fn peer_source(data: &PeersData) -> String {
+    match data.local {
+        true => String::from("LAN"),
+        false => String::from(""),
+    }
 }
 
 fn insert_peers_row(list: &Rc<ListStore>, data: &PeersData) {
-    let values: [(u32, &dyn ToValue); 6] = [
+    let source = peer_source(data);
+    let values: [(u32, &dyn ToValue); 7] = [
         (0, &data.ip),
         (1, &data.port),
         (2, &data.connection.down_speed),
         (3, &data.connection.up_speed),
         (4, &data.connection.status),
         (5, &data.torrent_pathname),
+        (6, &source),
     ];
     list.insert_with_values(Some(100), &values);
 }
 
 fn update_row(list: &Rc<ListStore>, tree_iter: &TreeIter, data: &PeersData) {
-    let values: [(u32, &dyn ToValue); 6] = [
+    let source = peer_source(data);
+    let values: [(u32, &dyn ToValue); 7] = [
         (0, &data.ip),
         (1, &data.port),
         (2, &data.connection.down_speed),
         (3, &data.connection.up_speed),
         (4, &data.connection.status),
         (5, &data.torrent_pathname),
+        (6, &source),
     ];
 
     list.set(tree_iter, &values)
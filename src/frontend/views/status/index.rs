@@ -0,0 +1,48 @@
+use gtk::glib;
+use gtk::glib::Receiver as GtkReceiver;
+use gtk::prelude::*;
+
+#[derive(Debug)]
+pub enum StatusData {
+    BandwidthProfile(Option<String>),
+    BlockedAttempts(u64),
+}
+
+#[derive(Default)]
+struct Status {
+    bandwidth_profile: Option<String>,
+    blocked_attempts: u64,
+}
+
+impl Status {
+    fn update(&mut self, msg: StatusData) {
+        match msg {
+            StatusData::BandwidthProfile(profile) => self.bandwidth_profile = profile,
+            StatusData::BlockedAttempts(blocked) => self.blocked_attempts = blocked,
+        }
+    }
+
+    fn text(&self) -> String {
+        format!(
+            "Bandwidth profile: {} | Blocked connections: {}",
+            self.bandwidth_profile.as_deref().unwrap_or("default"),
+            self.blocked_attempts
+        )
+    }
+}
+
+pub fn get_view(builder: &gtk::Builder, gtk_rx_status: GtkReceiver<StatusData>) {
+    let status_label: gtk::Label = builder
+        .object("status_label")
+        .expect("Couldn't get status label");
+
+    let mut status = Status::default();
+    status_label.set_text(&status.text());
+
+    gtk_rx_status.attach(None, move |msg| {
+        status.update(msg);
+        status_label.set_text(&status.text());
+
+        glib::Continue(true)
+    });
+}
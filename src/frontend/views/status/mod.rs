@@ -0,0 +1,3 @@
+pub use index::*;
+
+pub mod index;
@@ -1,4 +1,4 @@
-use crate::bit_torrent::{Bitfield, CommonInformation, PeerList};
+use crate::bit_torrent::{Bitfield, CommonInformation, PeerList, ScrapeResponse};
 use gtk::glib;
 use gtk::glib::Receiver as GtkReceiver;
 use gtk::pango;
@@ -22,6 +22,15 @@ pub struct TorrentData {
     pub pieces_done: u32,
     pub connections: u32,
     pub torrent_pathname: String,
+    pub downloaded: String,
+    pub uploaded: String,
+    pub wasted: String,
+    pub left: String,
+    pub seeders: String,
+    pub leechers: String,
+    pub completed: String,
+    pub checked: String,
+    pub error: String,
 }
 
 impl TorrentData {
@@ -30,33 +39,43 @@ impl TorrentData {
         for &byte in &common_information.info_hash {
             write!(&mut info_hash, "{:X}", byte).expect("Unable to write");
         }
-        let mut size: String = (common_information.file_length).to_string();
-        if common_information.file_length / 1073741824 > 1 {
-            size = format!(
-                "{:.2} GB",
-                (common_information.file_length as f64 / 1073741824_f64)
-            );
-        } else if common_information.file_length / 1048576 > 1 {
-            size = format!(
-                "{:.2} MB",
-                (common_information.file_length as f64 / 1048576_f64)
-            );
-        } else if common_information.file_length / 1024 > 1 {
-            size = format!(
-                "{:.2} KB",
-                (common_information.file_length as f64 / 1024_f64)
-            );
-        }
+        let stats = &common_information.stats;
+        let swarm = *common_information.swarm.lock().unwrap();
+        let swarm_count = |count: fn(&ScrapeResponse) -> u32| {
+            swarm
+                .as_ref()
+                .map(|swarm| count(swarm).to_string())
+                .unwrap_or_default()
+        };
         let torrent_data = TorrentData {
             name: String::from(&common_information.file_name),
             hash: info_hash,
-            size,
+            size: format_size(common_information.file_length),
             pieces: common_information.total_pieces.try_into().unwrap(),
             peers: peers.len().try_into().unwrap(),
             done: ((have.status().0 * 100) as f64 / common_information.total_pieces as f64),
             pieces_done: have.status().0.try_into().unwrap(),
             connections: peers.active().try_into().unwrap(),
             torrent_pathname: String::from(&common_information.torrent_pathname),
+            downloaded: format_size(stats.downloaded()),
+            uploaded: format_size(stats.uploaded()),
+            wasted: format_size(stats.wasted()),
+            left: format_size(common_information.bytes_left(have)),
+            seeders: swarm_count(|swarm| swarm.seeders),
+            leechers: swarm_count(|swarm| swarm.leechers),
+            completed: swarm_count(|swarm| swarm.completed),
+            checked: common_information
+                .checking
+                .lock()
+                .unwrap()
+                .map(|checked| format!("{:.2}%", checked))
+                .unwrap_or_default(),
+            error: common_information
+                .error
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or_default(),
         };
         common_information
             .tx_torrent
@@ -67,13 +86,25 @@ impl TorrentData {
     }
 }
 
+fn format_size(bytes: u64) -> String {
+    if bytes / 1073741824 > 1 {
+        format!("{:.2} GB", (bytes as f64 / 1073741824_f64))
+    } else if bytes / 1048576 > 1 {
+        format!("{:.2} MB", (bytes as f64 / 1048576_f64))
+    } else if bytes / 1024 > 1 {
+        format!("{:.2} KB", (bytes as f64 / 1024_f64))
+    } else {
+        bytes.to_string()
+    }
+}
+
 pub fn get_view(
     builder: &gtk::Builder,
     data: &[TorrentData],
     gtk_rx_torrent: GtkReceiver<TorrentData>,
     path_tx: Sender<String>,
     remove_senders: Arc<Mutex<HashMap<String, Sender<String>>>>,
-) {
+) -> gtk::TreeView {
     // Torrent vbox and label
     let vbox_torrent: gtk::Box = builder.object("list_box").expect("Couldn't get vbox");
     let vbox_torrent_label = gtk::Label::new(Some("Torrents list"));
@@ -121,6 +152,9 @@ pub fn get_view(
     let torrent_info: gtk::Button = builder
         .object("btn_torrent_info")
         .expect("Couldn't get torrent info");
+    let recheck_torrent: gtk::Button = builder
+        .object("btn_recheck_torrent")
+        .expect("Couldn't get recheck torrent");
 
     // Handlers
     let model_torrent_clone = model_torrent.clone();
@@ -156,6 +190,15 @@ pub fn get_view(
              pieces_done: 0,
              connections: 0,
              torrent_pathname: String::from(torrent_path.to_str().unwrap()),
+             downloaded: String::from(""),
+             uploaded: String::from(""),
+             wasted: String::from(""),
+             left: String::from(""),
+             seeders: String::from(""),
+             leechers: String::from(""),
+             completed: String::from(""),
+             checked: String::from(""),
+             error: String::from(""),
         };
 
         insert_torrent_row(&model_torrent_clone, &torrent_data);
@@ -166,6 +209,18 @@ pub fn get_view(
         vbox_torrent_label_copy.set_label("Torrent added successfully");  
     }));
 
+    let treeview_torrent_clone = treeview_torrent.clone();
+    let remove_senders_clone = remove_senders.clone();
+    let vbox_torrent_label_copy = vbox_torrent_label.clone();
+    recheck_torrent.connect_clicked(glib::clone!(@weak recheck_torrent => move |_| {
+        if let Some(path) = selected_pathname(&treeview_torrent_clone) {
+            if let Some(sender) = remove_senders_clone.lock().unwrap().get(&path) {
+                let _ = sender.send("Recheck".to_string());
+                vbox_torrent_label_copy.set_label("Rechecking torrent");
+            }
+        }
+    }));
+
     let model_torrent_clone = model_torrent.clone();
     let treeview_torrent_clone = treeview_torrent.clone();
     let vbox_torrent_label_copy = vbox_torrent_label;
@@ -175,7 +230,7 @@ pub fn get_view(
         if let Some(selected) = selected {
             let iter:TreeIter = selected.1;
             let path = model_torrent_clone.value(&iter, 8).get::<String>().expect("Treeview selection, column 8");
-            remove_senders.lock().unwrap().get(&path).unwrap().send("End".to_string()).unwrap();
+            remove_senders.lock().unwrap().get(&path).unwrap().send("Remove".to_string()).unwrap();
             model_torrent_clone.remove(&(selected.1));
             let mut attr = pango::AttrColor::new_foreground(0, 0, 0);
             attr.set_start_index(0);
@@ -187,7 +242,7 @@ pub fn get_view(
 
     let model_torrent_clone = model_torrent.clone();
     let torrent_dialog_clone = torrent_dialog;
-    let treeview_torrent_clone = treeview_torrent;
+    let treeview_torrent_clone = treeview_torrent.clone();
     torrent_info.connect_clicked(glib::clone!(@weak torrent_info => move |_| {
         let selected = treeview_torrent_clone.selection().selected();
         match selected {
@@ -195,7 +250,7 @@ pub fn get_view(
                 let iter:TreeIter = selected.1;
                 let name = model_torrent_clone.value(&iter, 0).get::<String>().expect("Treeview selection, column 0");
                 let info = &format!(
-                    "Name: {}\nHash: {}\nSize: {}\npieces: {}\nPeers: {}\nDone: {}\npieces done: {}\nconnections: {}\nPathname: {}\n",
+                    "Name: {}\nHash: {}\nSize: {}\npieces: {}\nPeers: {}\nDone: {}\npieces done: {}\nconnections: {}\nPathname: {}\nDownloaded: {}\nUploaded: {}\nWasted: {}\nLeft: {}\nSeeders: {}\nLeechers: {}\nCompleted: {}\nChecked: {}\nError: {}\n",
                     name,
                     model_torrent_clone.value(&iter, 1).get::<String>().expect("Treeview selection, column 1"),
                     model_torrent_clone.value(&iter, 2).get::<String>().expect("Treeview selection, column 2"),
@@ -205,6 +260,15 @@ pub fn get_view(
                     model_torrent_clone.value(&iter, 6).get::<u32>().expect("Treeview selection, column 6"),
                     model_torrent_clone.value(&iter, 7).get::<u32>().expect("Treeview selection, column 7"),
                     model_torrent_clone.value(&iter, 8).get::<String>().expect("Treeview selection, column 8"),
+                    model_torrent_clone.value(&iter, 9).get::<String>().expect("Treeview selection, column 9"),
+                    model_torrent_clone.value(&iter, 10).get::<String>().expect("Treeview selection, column 10"),
+                    model_torrent_clone.value(&iter, 11).get::<String>().expect("Treeview selection, column 11"),
+                    model_torrent_clone.value(&iter, 12).get::<String>().expect("Treeview selection, column 12"),
+                    model_torrent_clone.value(&iter, 13).get::<String>().expect("Treeview selection, column 13"),
+                    model_torrent_clone.value(&iter, 14).get::<String>().expect("Treeview selection, column 14"),
+                    model_torrent_clone.value(&iter, 15).get::<String>().expect("Treeview selection, column 15"),
+                    model_torrent_clone.value(&iter, 16).get::<String>().expect("Treeview selection, column 16"),
+                    model_torrent_clone.value(&iter, 17).get::<String>().expect("Treeview selection, column 17"),
                 );
                 torrent_dialog_clone.set_text(Some(name.as_str()));
                 torrent_dialog_clone.set_secondary_text(Some(info));
@@ -218,7 +282,6 @@ pub fn get_view(
 
     let model_torrent_clone = model_torrent;
     gtk_rx_torrent.attach(None, move |msg| {
-        let msg = msg;
         {
             if model_torrent_clone.iter_children(None).is_some() {
                 let tree_iter = model_torrent_clone
@@ -241,10 +304,19 @@ pub fn get_view(
         }
         glib::Continue(true)
     });
+
+    treeview_torrent
+}
+
+pub fn selected_pathname(treeview: &gtk::TreeView) -> Option<String> {
+    let (model, iter) = treeview.selection().selected()?;
+
+    model.value(&iter, 8).get::<String>().ok()
 }
 
 fn create_model_torrents(data: &[TorrentData]) -> gtk::ListStore {
-    let col_types: [glib::Type; 9] = [
+    let col_types: [glib::Type; 18] = [
+        glib::Type::STRING,
         glib::Type::STRING,
         glib::Type::STRING,
         glib::Type::STRING,
@@ -254,13 +326,21 @@ fn create_model_torrents(data: &[TorrentData]) -> gtk::ListStore {
         glib::Type::U32,
         glib::Type::U32,
         glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
+        glib::Type::STRING,
     ];
 
     let store = gtk::ListStore::new(&col_types);
 
-    for (_d_idx, d) in data.iter().enumerate() {
+    for d in data.iter() {
         let done_percentage: String = format!("{:.2}%", &d.done);
-        let values: [(u32, &dyn ToValue); 9] = [
+        let values: [(u32, &dyn ToValue); 18] = [
             (0, &d.name),
             (1, &d.hash),
             (2, &d.size),
@@ -270,6 +350,15 @@ fn create_model_torrents(data: &[TorrentData]) -> gtk::ListStore {
             (6, &d.pieces_done),
             (7, &d.connections),
             (8, &d.torrent_pathname),
+            (9, &d.downloaded),
+            (10, &d.uploaded),
+            (11, &d.wasted),
+            (12, &d.left),
+            (13, &d.seeders),
+            (14, &d.leechers),
+            (15, &d.completed),
+            (16, &d.checked),
+            (17, &d.error),
         ];
         store.set(&store.append(), &values);
     }
@@ -369,11 +458,81 @@ fn add_columns_torrents(_model: &Rc<gtk::ListStore>, treeview: &gtk::TreeView) {
         column.set_sort_column_id(8);
         treeview.append_column(&column);
     }
+    // Column for Downloaded
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Downloaded");
+        column.add_attribute(&renderer, "text", 9);
+        column.set_sort_column_id(9);
+        treeview.append_column(&column);
+    }
+    // Column for Uploaded
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Uploaded");
+        column.add_attribute(&renderer, "text", 10);
+        column.set_sort_column_id(10);
+        treeview.append_column(&column);
+    }
+    // Column for Seeders
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Seeders");
+        column.add_attribute(&renderer, "text", 13);
+        column.set_sort_column_id(13);
+        treeview.append_column(&column);
+    }
+    // Column for Leechers
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Leechers");
+        column.add_attribute(&renderer, "text", 14);
+        column.set_sort_column_id(14);
+        treeview.append_column(&column);
+    }
+    // Column for Completed
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Completed");
+        column.add_attribute(&renderer, "text", 15);
+        column.set_sort_column_id(15);
+        treeview.append_column(&column);
+    }
+    // Column for Checked
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Checked");
+        column.add_attribute(&renderer, "text", 16);
+        column.set_sort_column_id(16);
+        treeview.append_column(&column);
+    }
+    // Column for Error
+    {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Error");
+        column.add_attribute(&renderer, "text", 17);
+        column.set_sort_column_id(17);
+        treeview.append_column(&column);
+    }
 }
 
 fn insert_torrent_row(list: &Rc<ListStore>, data: &TorrentData) {
     let done_percentage: String = format!("{:.2}%", &data.done);
-    let values: [(u32, &dyn ToValue); 9] = [
+    let values: [(u32, &dyn ToValue); 18] = [
         (0, &data.name),
         (1, &data.hash),
         (2, &data.size),
@@ -383,6 +542,15 @@ fn insert_torrent_row(list: &Rc<ListStore>, data: &TorrentData) {
         (6, &data.pieces_done),
         (7, &data.connections),
         (8, &data.torrent_pathname),
+        (9, &data.downloaded),
+        (10, &data.uploaded),
+        (11, &data.wasted),
+        (12, &data.left),
+        (13, &data.seeders),
+        (14, &data.leechers),
+        (15, &data.completed),
+        (16, &data.checked),
+        (17, &data.error),
     ];
 
     list.insert_with_values(Some(100), &values);
@@ -390,7 +558,7 @@ fn insert_torrent_row(list: &Rc<ListStore>, data: &TorrentData) {
 
 fn update_row(list: &Rc<ListStore>, tree_iter: &TreeIter, data: &TorrentData) {
     let done_percentage: String = format!("{:.2}%", &data.done);
-    let values: [(u32, &dyn ToValue); 9] = [
+    let values: [(u32, &dyn ToValue); 18] = [
         (0, &data.name),
         (1, &data.hash),
         (2, &data.size),
@@ -400,6 +568,15 @@ fn update_row(list: &Rc<ListStore>, tree_iter: &TreeIter, data: &TorrentData) {
         (6, &data.pieces_done),
         (7, &data.connections),
         (8, &data.torrent_pathname),
+        (9, &data.downloaded),
+        (10, &data.uploaded),
+        (11, &data.wasted),
+        (12, &data.left),
+        (13, &data.seeders),
+        (14, &data.leechers),
+        (15, &data.completed),
+        (16, &data.checked),
+        (17, &data.error),
     ];
 
     list.set(tree_iter, &values);
@@ -6,4 +6,7 @@ pub enum NetworkingError {
     FailedToRead,
     FailedToConnect,
     FailedPeerConnection,
+    BlockedAddress,
+    FailedTrackerRequest,
+    ScrapeUnsupported,
 }
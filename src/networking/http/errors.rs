@@ -0,0 +1,12 @@
+#[derive(Debug)]
+
+pub enum HttpError {
+    InvalidUrl,
+    UnsupportedScheme,
+    FailedToConnect,
+    FailedToSend,
+    FailedToRead,
+    InvalidResponse,
+    TooManyRedirects,
+    ScrapeUnsupported,
+}
@@ -0,0 +1,156 @@
+use super::{HttpError, HttpResponse, Url};
+use crate::networking::{HTTPSTracker, HTTPTracker, Protocol};
+use std::io::{BufReader, Read, Write};
+
+const MAX_REDIRECTS: usize = 5;
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+pub struct HttpClient;
+
+impl HttpClient {
+    // Follows up to five redirects.
+    pub fn get(url: &Url) -> Result<HttpResponse, HttpError> {
+        let mut url = url.clone();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let response = Self::request(&url, "GET", &[], &[])?;
+
+            if !response.is_redirect() {
+                return Ok(response);
+            }
+
+            let location = response
+                .header("Location")
+                .ok_or(HttpError::InvalidResponse)?;
+
+            log::debug!("HttpClient::get() - redirected to {}", location);
+
+            url = url.join(location)?;
+        }
+
+        Err(HttpError::TooManyRedirects)
+    }
+
+    // Does not follow redirects.
+    pub fn post(
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<HttpResponse, HttpError> {
+        Self::request(url, "POST", headers, body)
+    }
+
+    fn request(
+        url: &Url,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<HttpResponse, HttpError> {
+        match url.scheme.as_str() {
+            "http" => Self::send(
+                HTTPTracker::connect(&url.authority()).or(Err(HttpError::FailedToConnect))?,
+                url,
+                method,
+                headers,
+                body,
+            ),
+            "https" => Self::send(
+                HTTPSTracker::connect(&url.authority()).or(Err(HttpError::FailedToConnect))?,
+                url,
+                method,
+                headers,
+                body,
+            ),
+            _ => Err(HttpError::UnsupportedScheme),
+        }
+    }
+
+    fn send<S: Read + Write>(
+        mut stream: S,
+        url: &Url,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<HttpResponse, HttpError> {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\nAccept-Encoding: identity\r\nConnection: close\r\n",
+            method,
+            url.request_target(),
+            url.host_header(),
+            USER_AGENT
+        );
+
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        if method != "GET" {
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+
+        request.push_str("\r\n");
+
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body);
+
+        stream
+            .write_all(&request)
+            .or(Err(HttpError::FailedToSend))?;
+        stream.flush().or(Err(HttpError::FailedToSend))?;
+
+        HttpResponse::read_from(&mut BufReader::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test1_follows_redirects_to_chunked_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut request_lines = vec![];
+
+            let responses = [
+                "HTTP/1.1 307 Temporary Redirect\r\nLocation: /moved?x=1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n",
+            ];
+
+            for (response, stream) in responses.iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                request_lines.push(line.trim_end().to_string());
+
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+
+            request_lines
+        });
+
+        let url = Url::parse(&format!("http://{}/announce?passkey=abc", address)).unwrap();
+        let response = HttpClient::get(&url).unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"ok".to_vec());
+        assert_eq!(
+            handle.join().unwrap(),
+            vec![
+                "GET /announce?passkey=abc HTTP/1.1",
+                "GET /moved?x=1 HTTP/1.1"
+            ]
+        );
+    }
+}
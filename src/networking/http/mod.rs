@@ -0,0 +1,9 @@
+pub use errors::HttpError;
+pub use index::HttpClient;
+pub use response::HttpResponse;
+pub use url::Url;
+
+mod errors;
+mod index;
+mod response;
+mod url;
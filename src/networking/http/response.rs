@@ -0,0 +1,183 @@
+use super::HttpError;
+use std::io::{BufRead, Read};
+
+const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        matches!(self.status, 301 | 302 | 303 | 307 | 308)
+    }
+
+    // The body is framed by `Transfer-Encoding: chunked`, `Content-Length` or the end of the
+    // connection.
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<Self, HttpError> {
+        let status_line = read_line(reader)?;
+        let mut status_fields = status_line.split_whitespace();
+
+        match status_fields.next() {
+            Some(version) if version.starts_with("HTTP/1.") => {}
+            _ => return Err(HttpError::InvalidResponse),
+        }
+
+        let status = status_fields
+            .next()
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or(HttpError::InvalidResponse)?;
+
+        let mut headers = vec![];
+
+        loop {
+            let line = read_line(reader)?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            let (name, value) = line.split_once(':').ok_or(HttpError::InvalidResponse)?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let mut response = Self {
+            status,
+            headers,
+            body: vec![],
+        };
+
+        let chunked = response
+            .header("Transfer-Encoding")
+            .is_some_and(|encoding| encoding.to_lowercase().contains("chunked"));
+
+        response.body = if chunked {
+            read_chunked(reader)?
+        } else if let Some(length) = response.header("Content-Length") {
+            let length = length
+                .parse::<usize>()
+                .or(Err(HttpError::InvalidResponse))?;
+
+            if length > MAX_BODY_LENGTH {
+                return Err(HttpError::InvalidResponse);
+            }
+
+            let mut body = vec![0; length];
+            reader
+                .read_exact(&mut body)
+                .or(Err(HttpError::FailedToRead))?;
+            body
+        } else {
+            let mut body = vec![];
+            reader
+                .take(MAX_BODY_LENGTH as u64)
+                .read_to_end(&mut body)
+                .or(Err(HttpError::FailedToRead))?;
+            body
+        };
+
+        Ok(response)
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, HttpError> {
+    let mut line = String::new();
+
+    match reader.by_ref().take(MAX_LINE_LENGTH).read_line(&mut line) {
+        Ok(0) => Err(HttpError::InvalidResponse),
+        Ok(read) if read as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') => {
+            Err(HttpError::InvalidResponse)
+        }
+        Ok(_) => Ok(line.trim_end_matches(['\r', '\n']).to_string()),
+        Err(_) => Err(HttpError::FailedToRead),
+    }
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, HttpError> {
+    let mut body = vec![];
+
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).or(Err(HttpError::InvalidResponse))?;
+
+        if size == 0 {
+            break;
+        }
+
+        let total = body
+            .len()
+            .checked_add(size)
+            .filter(|total| *total <= MAX_BODY_LENGTH)
+            .ok_or(HttpError::InvalidResponse)?;
+
+        let start = body.len();
+        body.resize(total, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .or(Err(HttpError::FailedToRead))?;
+
+        read_line(reader)?;
+    }
+
+    // Trailer fields, if any, end with an empty line.
+    while !read_line(reader)?.is_empty() {}
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test1_reads_chunked_body() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nd8:i\r\n7\r\nnterval\r\n0\r\nX-Trailer: 1\r\n\r\n";
+
+        let response = HttpResponse::read_from(&mut Cursor::new(raw.to_vec())).unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"d8:interval".to_vec());
+    }
+
+    #[test]
+    fn test2_reads_content_length_and_headers() {
+        let raw = b"HTTP/1.0 302 Found\r\nlocation: /moved\r\nContent-Length: 2\r\n\r\nokextra";
+
+        let response = HttpResponse::read_from(&mut Cursor::new(raw.to_vec())).unwrap();
+
+        assert!(response.is_redirect());
+        assert_eq!(response.header("Location"), Some("/moved"));
+        assert_eq!(response.body, b"ok".to_vec());
+        assert!(HttpResponse::read_from(&mut Cursor::new(b"garbage\r\n\r\n".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test3_refuses_oversized_chunks_and_lines() {
+        let raw =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n";
+        assert!(matches!(
+            HttpResponse::read_from(&mut Cursor::new(raw.to_vec())),
+            Err(HttpError::InvalidResponse)
+        ));
+
+        let mut raw = b"HTTP/1.1 200 OK\r\nX-Long: ".to_vec();
+        raw.extend(vec![b'a'; MAX_LINE_LENGTH as usize]);
+        assert!(matches!(
+            HttpResponse::read_from(&mut Cursor::new(raw)),
+            Err(HttpError::InvalidResponse)
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+use super::HttpError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    pub query: Option<String>,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, HttpError> {
+        let (scheme, rest) = url.split_once("://").ok_or(HttpError::InvalidUrl)?;
+        let scheme = scheme.to_lowercase();
+
+        let default_port = match scheme.as_str() {
+            "http" => 80,
+            "https" => 443,
+            _ => return Err(HttpError::UnsupportedScheme),
+        };
+
+        let rest = rest.split('#').next().unwrap_or_default();
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, target) = rest.split_at(authority_end);
+
+        let (host, port) = match authority.strip_prefix('[') {
+            // IPv6 literals keep their colons inside brackets.
+            Some(bracketed) => {
+                let (host, port) = bracketed.split_once(']').ok_or(HttpError::InvalidUrl)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+
+        if host.is_empty() {
+            return Err(HttpError::InvalidUrl);
+        }
+
+        let port = match port {
+            Some(port) => port.parse::<u16>().or(Err(HttpError::InvalidUrl))?,
+            None => default_port,
+        };
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+
+        Ok(Self {
+            scheme,
+            host: host.to_string(),
+            port,
+            path: if path.is_empty() {
+                String::from("/")
+            } else {
+                path.to_string()
+            },
+            query,
+        })
+    }
+
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    // The Host header leaves out the scheme's default port.
+    pub fn host_header(&self) -> String {
+        let default_port = if self.scheme == "https" { 443 } else { 80 };
+
+        match self.port == default_port {
+            true if self.host.contains(':') => format!("[{}]", self.host),
+            true => self.host.clone(),
+            false => self.authority(),
+        }
+    }
+
+    pub fn request_target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    // The pairs must already be encoded.
+    pub fn with_query(&self, params: &str) -> Self {
+        let mut url = self.clone();
+
+        url.query = match &self.query {
+            Some(query) if !query.is_empty() => Some(format!("{}&{}", query, params)),
+            _ => Some(params.to_string()),
+        };
+
+        url
+    }
+
+    // Only defined when the last path segment starts with `announce` (BEP 48).
+    pub fn scrape(&self) -> Option<Self> {
+        let index = self.path.rfind('/')?;
+        let rest = self.path[index + 1..].strip_prefix("announce")?;
+        let mut url = self.clone();
+
+        url.path = format!("{}scrape{}", &self.path[..=index], rest);
+
+        Some(url)
+    }
+
+    pub fn join(&self, location: &str) -> Result<Self, HttpError> {
+        if location.contains("://") {
+            return Self::parse(location);
+        }
+
+        let base = format!("{}://{}", self.scheme, self.authority());
+
+        if let Some(rest) = location.strip_prefix("//") {
+            return Self::parse(&format!("{}://{}", self.scheme, rest));
+        }
+
+        if location.starts_with('/') {
+            return Self::parse(&format!("{}{}", base, location));
+        }
+
+        let directory = match self.path.rfind('/') {
+            Some(index) => &self.path[..=index],
+            None => "/",
+        };
+
+        Self::parse(&format!("{}{}{}", base, directory, location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_parses_ports_queries_and_ipv6_hosts() {
+        let url = Url::parse("http://tracker.example:6969/a/announce?passkey=abc#top").unwrap();
+
+        assert_eq!(url.authority(), "tracker.example:6969");
+        assert_eq!(url.request_target(), "/a/announce?passkey=abc");
+        assert_eq!(
+            url.with_query("port=1").request_target(),
+            "/a/announce?passkey=abc&port=1"
+        );
+
+        let url = Url::parse("https://[::1]/announce").unwrap();
+
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.authority(), "[::1]:443");
+        assert_eq!(url.host_header(), "[::1]");
+        assert!(Url::parse("udp://tracker.example:80").is_err());
+    }
+
+    #[test]
+    fn test2_joins_relative_locations() {
+        let url = Url::parse("http://tracker.example:6969/a/announce?x=1").unwrap();
+
+        assert_eq!(
+            url.join("/b/announce").unwrap().request_target(),
+            "/b/announce"
+        );
+        assert_eq!(url.join("other").unwrap().path, "/a/other");
+        assert_eq!(
+            url.join("https://secure.example/announce").unwrap().port,
+            443
+        );
+    }
+
+    #[test]
+    fn test3_derives_scrape_urls() {
+        let url = Url::parse("http://tracker.example/x/announce.php?passkey=abc").unwrap();
+
+        assert_eq!(
+            url.scrape().unwrap().request_target(),
+            "/x/scrape.php?passkey=abc"
+        );
+        assert!(Url::parse("http://tracker.example/a")
+            .unwrap()
+            .scrape()
+            .is_none());
+        assert!(Url::parse("http://tracker.example/announce/x")
+            .unwrap()
+            .scrape()
+            .is_none());
+    }
+}
@@ -1,8 +1,15 @@
 pub use crate::bit_torrent::handshake::Handshake;
 pub use client::{InterfaceProtocol, NetworkingError};
-pub use protocol::{BitTorrent, HTTPSTracker, HTTPTracker, Message, Protocol};
+pub use http::{HttpClient, HttpError, HttpResponse, Url};
+pub use protocol::{
+    BitTorrent, HTTPSTracker, HTTPTracker, Message, Protocol, UDPAnnounce, UDPScrape, UDPTracker,
+    UDPTrackerError,
+};
+pub use proxy::{Proxy, ProxyError, Purpose, UdpAssociation};
 pub use utils::*;
 
 mod client;
+mod http;
 mod protocol;
+mod proxy;
 pub mod utils;
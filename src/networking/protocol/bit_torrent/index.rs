@@ -1,5 +1,9 @@
 use super::Protocol;
-use std::net::TcpStream;
+use crate::networking::{Proxy, Purpose};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct BitTorrent;
 
@@ -36,7 +40,22 @@ impl Protocol for BitTorrent {
             target_address
         );
 
-        match TcpStream::connect(target_address) {
+        let maybe_stream = match Proxy::from_env(Purpose::Peers, target_address) {
+            Ok(Some(proxy)) => proxy
+                .connect(target_address, CONNECT_TIMEOUT)
+                .map_err(|error| log::debug!("BitTorrent::connect() - proxy: {:?}", error)),
+            Err(_) => Err(()),
+            Ok(None) => match target_address.to_socket_addrs() {
+                Ok(mut addresses) => match addresses.next() {
+                    Some(address) => TcpStream::connect_timeout(&address, CONNECT_TIMEOUT),
+                    None => TcpStream::connect(target_address),
+                },
+                Err(error) => Err(error),
+            }
+            .map_err(|error| log::debug!("BitTorrent::connect() - {}", error)),
+        };
+
+        match maybe_stream {
             Ok(stream) => {
                 log::info!(
                     "BitTorrent::connect() - Successfully connected to {}",
@@ -1,4 +1,5 @@
 use super::MessageError;
+use crate::bit_torrent::BLOCK_LENGTH;
 use std::io::Read;
 use std::net::TcpStream;
 
@@ -32,17 +33,15 @@ impl Message {
         stream: &mut TcpStream,
         handshake_len: usize,
         info_hash: &[u8],
-    ) -> Result<bool, String> {
+    ) -> Result<Vec<u8>, String> {
         let mut response = vec![0_u8; handshake_len];
 
         match stream.read_exact(&mut response) {
             Ok(_) => {
-                if response.len() == handshake_len
-                    && response[handshake_len - 40..handshake_len - 20].starts_with(info_hash)
-                {
-                    return Ok(true);
+                if response[handshake_len - 40..handshake_len - 20].starts_with(info_hash) {
+                    return Ok(response[handshake_len - 20..].to_vec());
                 }
-                Ok(false)
+                Err(String::from("Handshake for a different info hash"))
             }
             Err(_) => Err(String::from("Failed to read handshake from stream")),
         }
@@ -55,7 +54,7 @@ impl Message {
             Ok(_) => {
                 let protocol_length = u8::from_be_bytes(protocol_length_buffer) as usize;
 
-                let data_length = (protocol_length + 48) as usize;
+                let data_length = protocol_length + 48;
 
                 let mut data_buffer = vec![0_u8; data_length];
 
@@ -76,6 +75,7 @@ impl Message {
     pub fn read_message_from_stream(
         stream: &mut TcpStream,
         non_blocking: bool,
+        total_pieces: usize,
     ) -> Result<Message, String> {
         let mut length_buffer = [0_u8; 4];
         let mut id_buffer = [0_u8; 1];
@@ -97,6 +97,9 @@ impl Message {
                 }
 
                 match stream.read_exact(&mut id_buffer) {
+                    Ok(_) if payload_length > Self::max_length(id_buffer[0], total_pieces) => {
+                        Err(String::from("Message too long"))
+                    }
                     Ok(_) => {
                         let mut message = vec![];
                         message.extend_from_slice(&length_buffer);
@@ -131,6 +134,13 @@ impl Message {
         maybe_message
     }
 
+    fn max_length(id: u8, total_pieces: usize) -> u32 {
+        match id {
+            5 => (total_pieces.div_ceil(8) + 1) as u32,
+            _ => BLOCK_LENGTH + 9,
+        }
+    }
+
     pub fn read_length_from_header(header: &[u8; 5]) -> u32 {
         u32::from_be_bytes(header[0..4].try_into().expect("Incorrect message length")) - 1
     }
@@ -211,7 +221,7 @@ impl Message {
                         match id {
                             4 => Message::Have { payload },
                             5 => Message::Bitfield { payload },
-                            6 => {
+                            6 if payload.len() == 12 => {
                                 let piece_index = u32::from_be_bytes(
                                     data[5..9]
                                         .try_into()
@@ -245,3 +255,30 @@ impl Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test1_oversized_messages_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        peer.write_all(&[0xff, 0xff, 0xff, 0xff, 7]).unwrap();
+        assert!(Message::read_message_from_stream(&mut stream, false, 16).is_err());
+
+        // A bitfield for 16 pieces takes two bytes.
+        peer.write_all(&[0, 0, 0, 4, 5]).unwrap();
+        assert!(Message::read_message_from_stream(&mut stream, false, 16).is_err());
+
+        peer.write_all(&[0, 0, 0, 3, 5, 0xff, 0xff]).unwrap();
+        assert!(matches!(
+            Message::read_message_from_stream(&mut stream, false, 16),
+            Ok(Message::Bitfield { payload }) if payload == [0xff, 0xff]
+        ));
+    }
+}
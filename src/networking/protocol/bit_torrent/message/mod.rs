@@ -1,4 +1,3 @@
-pub use super::Protocol;
 pub use errors::MessageError;
 pub use index::Message;
 
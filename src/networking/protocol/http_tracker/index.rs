@@ -1,6 +1,10 @@
-pub use super::{Handshake, Protocol};
+pub use super::{Handshake, HttpError, Protocol, Url};
+use crate::networking::{utils::connect_for, Purpose};
 use std::collections::HashMap;
 use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(15);
 
 pub struct HTTPTracker {
     peers: Vec<usize>,
@@ -30,24 +34,22 @@ impl HTTPTracker {
         }
     }
 
-    pub fn format_handshake_message(&self, handshake_params: Handshake) -> String {
-        format!(
-            "GET /{}?peer_id={}&numwant=100&info_hash={}&port={}&uploaded=0&downloaded={}&left={}&event={} HTTP/1.0\r\nHost: {}\r\n\r\n",
-            self.announce,
-            handshake_params.id,
-            handshake_params.info_hash,
-            handshake_params.port,
-            handshake_params.downloaded,
-            handshake_params.left,
-            handshake_params.event,
-            handshake_params.address
-        )
+    // Keeps any query parameters the announce URL already has.
+    pub fn announce_url(&self, handshake_params: Handshake) -> Result<Url, HttpError> {
+        Ok(Url::parse(&self.announce)?.with_query(&handshake_params.query()))
+    }
+
+    pub fn scrape_url(&self, info_hash: &str) -> Result<Url, HttpError> {
+        Ok(Url::parse(&self.announce)?
+            .scrape()
+            .ok_or(HttpError::ScrapeUnsupported)?
+            .with_query(&format!("info_hash={}", info_hash)))
     }
 
     pub fn query_to_hashmap(query: &str) -> HashMap<String, String> {
         let mut map: HashMap<String, String> = HashMap::new();
 
-        query.split('&').into_iter().for_each(|key_value| {
+        query.split('&').for_each(|key_value| {
             let key_value_split: Vec<&str> = key_value.split('=').collect();
 
             let key = key_value_split[0];
@@ -77,7 +79,7 @@ impl Protocol for HTTPTracker {
     type Stream = TcpStream;
 
     fn connect(target_address: &str) -> Result<TcpStream, String> {
-        match TcpStream::connect(target_address) {
+        match connect_for(Purpose::Trackers, target_address, TIMEOUT) {
             Ok(stream) => Ok(stream),
             Err(_) => Err(format!("Failed to connect to {}", target_address)),
         }
@@ -1,7 +1,11 @@
-pub use super::{Handshake, Protocol};
+pub use super::{Handshake, HttpError, Protocol, Url};
+use crate::networking::{utils::connect_for, Purpose};
 use native_tls::{TlsConnector, TlsStream};
 use std::collections::HashMap;
 use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(15);
 
 pub struct HTTPSTracker {
     peers: Vec<usize>,
@@ -31,18 +35,16 @@ impl HTTPSTracker {
         }
     }
 
-    pub fn format_handshake_message(&self, handshake_params: Handshake) -> String {
-        format!(
-            "GET /{}?peer_id={}&numwant=100&info_hash={}&port={}&uploaded=0&downloaded={}&left={}&event={} HTTP/1.0\r\nHost: {}\r\n\r\n",
-            self.announce,
-            handshake_params.id,
-            handshake_params.info_hash,
-            handshake_params.port,
-            handshake_params.downloaded,
-            handshake_params.left,
-            handshake_params.event,
-            handshake_params.address
-        )
+    // Keeps any query parameters the announce URL already has.
+    pub fn announce_url(&self, handshake_params: Handshake) -> Result<Url, HttpError> {
+        Ok(Url::parse(&self.announce)?.with_query(&handshake_params.query()))
+    }
+
+    pub fn scrape_url(&self, info_hash: &str) -> Result<Url, HttpError> {
+        Ok(Url::parse(&self.announce)?
+            .scrape()
+            .ok_or(HttpError::ScrapeUnsupported)?
+            .with_query(&format!("info_hash={}", info_hash)))
     }
 
     pub fn query_to_hashmap(query: &str) -> HashMap<String, String> {
@@ -51,7 +53,7 @@ impl HTTPSTracker {
         let query_params: Vec<&str> = query.split('?').collect();
         let query_params = query_params[1];
 
-        query_params.split('&').into_iter().for_each(|key_value| {
+        query_params.split('&').for_each(|key_value| {
             let key_value_split: Vec<&str> = key_value.split('=').collect();
 
             let key = key_value_split[0];
@@ -82,14 +84,18 @@ impl Protocol for HTTPSTracker {
 
     fn connect(target_address: &str) -> Result<TlsStream<TcpStream>, String> {
         let connector = TlsConnector::new().expect("Failed to creat TlsConnector");
-        let split: Vec<&str> = target_address.split(':').collect();
+        let host = target_address
+            .rsplit_once(':')
+            .map_or(target_address, |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']');
 
         log::info!(
             "HTTPSTracker::connect() - Trying to connect to {}",
             target_address
         );
-        match TcpStream::connect(format!("{}:443", split[0])) {
-            Ok(stream) => match connector.connect(split[0], stream) {
+        match connect_for(Purpose::Trackers, target_address, TIMEOUT) {
+            Ok(stream) => match connector.connect(host, stream) {
                 Ok(stream) => {
                     log::info!(
                         "HTTPSTracker::connect() - Successfully connected to {}",
@@ -1,4 +1,4 @@
-pub use super::{Handshake, Protocol};
+pub use super::{Handshake, HttpError, Protocol, Url};
 pub use index::HTTPSTracker;
 
 mod index;
@@ -1,10 +1,12 @@
-pub use super::Handshake;
+pub use super::{Handshake, HttpError, Url};
 pub use bit_torrent::*;
 pub use http_tracker::HTTPTracker;
 pub use https_tracker::HTTPSTracker;
 pub use index::Protocol;
+pub use udp_tracker::{UDPAnnounce, UDPScrape, UDPTracker, UDPTrackerError};
 
 mod bit_torrent;
 mod http_tracker;
 mod https_tracker;
 mod index;
+mod udp_tracker;
@@ -0,0 +1,10 @@
+#[derive(Debug)]
+
+pub enum UDPTrackerError {
+    InvalidTrackerAddress,
+    FailedToSend,
+    FailedToRead,
+    Timeout,
+    InvalidResponse,
+    Tracker(String),
+}
@@ -0,0 +1,438 @@
+use super::UDPTrackerError;
+use crate::networking::{Proxy, Purpose, UdpAssociation};
+use rand::random;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+const PROTOCOL_ID: u64 = 0x41727101980;
+const CONNECT: u32 = 0;
+const ANNOUNCE: u32 = 1;
+const SCRAPE: u32 = 2;
+const ERROR: u32 = 3;
+
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+const BASE_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_RETRANSMISSIONS: u32 = 8;
+const MAX_PACKET_SIZE: usize = 8192;
+
+pub struct UDPAnnounce<'a> {
+    pub info_hash: &'a [u8],
+    pub peer_id: &'a [u8],
+    pub downloaded: u64,
+    pub left: u64,
+    pub uploaded: u64,
+    pub event: &'a str,
+    pub key: u32,
+    // Unspecified lets the tracker use the address the packet came from.
+    pub ip: Ipv4Addr,
+    pub port: u16,
+}
+
+#[derive(Debug)]
+pub struct UDPAnnounceResponse {
+    pub interval: u32,
+    pub leechers: u32,
+    pub seeders: u32,
+    pub peers: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UDPScrape {
+    pub seeders: u32,
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+// BEP 15: unanswered requests are retransmitted after 15 * 2^n seconds, giving up once n
+// reaches 8. Connection ids are reused until they are a minute old.
+pub struct UDPTracker {
+    socket: UdpSocket,
+    tracker: SocketAddr,
+    // Set when trackers are proxied.
+    relay: Option<UdpAssociation>,
+    connection: Option<(u64, Instant)>,
+    base_timeout: Duration,
+    max_retransmissions: u32,
+}
+
+impl UDPTracker {
+    pub fn new(target_address: &str) -> Result<Self, UDPTrackerError> {
+        let tracker = target_address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or(UDPTrackerError::InvalidTrackerAddress)?;
+
+        let proxy = Proxy::from_env(Purpose::Trackers, target_address)
+            .or(Err(UDPTrackerError::FailedToSend))?;
+
+        let relay = match proxy {
+            Some(proxy) => Some(proxy.udp_associate(BASE_TIMEOUT).map_err(|error| {
+                log::error!("UDP tracker proxy failed: {:?}", error);
+                UDPTrackerError::FailedToSend
+            })?),
+            None => None,
+        };
+
+        let peer = relay.as_ref().map_or(tracker, |relay| relay.relay());
+
+        let local_address = if peer.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+
+        let socket = UdpSocket::bind(local_address).or(Err(UDPTrackerError::FailedToSend))?;
+
+        Ok(Self {
+            socket,
+            tracker,
+            relay,
+            connection: None,
+            base_timeout: BASE_TIMEOUT,
+            max_retransmissions: MAX_RETRANSMISSIONS,
+        })
+    }
+
+    pub fn set_retransmission(&mut self, base_timeout: Duration, max_retransmissions: u32) {
+        self.base_timeout = base_timeout;
+        self.max_retransmissions = max_retransmissions;
+    }
+
+    // Compact peers follow the tracker's address family.
+    pub fn compact_peer_length(&self) -> usize {
+        if self.tracker.is_ipv6() {
+            18
+        } else {
+            6
+        }
+    }
+
+    pub fn announce(
+        &mut self,
+        announce: &UDPAnnounce,
+    ) -> Result<UDPAnnounceResponse, UDPTrackerError> {
+        let mut body = vec![];
+
+        body.extend_from_slice(announce.info_hash);
+        body.extend_from_slice(announce.peer_id);
+        body.extend_from_slice(&announce.downloaded.to_be_bytes());
+        body.extend_from_slice(&announce.left.to_be_bytes());
+        body.extend_from_slice(&announce.uploaded.to_be_bytes());
+        body.extend_from_slice(&event_id(announce.event).to_be_bytes());
+        body.extend_from_slice(&announce.ip.octets());
+        body.extend_from_slice(&announce.key.to_be_bytes());
+        // num_want -1 asks for the tracker's default.
+        body.extend_from_slice(&(-1_i32).to_be_bytes());
+        body.extend_from_slice(&announce.port.to_be_bytes());
+
+        let response = self.transaction(ANNOUNCE, &body)?;
+
+        if response.len() < 12 {
+            return Err(UDPTrackerError::InvalidResponse);
+        }
+
+        Ok(UDPAnnounceResponse {
+            interval: read_u32(&response, 0),
+            leechers: read_u32(&response, 4),
+            seeders: read_u32(&response, 8),
+            peers: response[12..].to_vec(),
+        })
+    }
+
+    pub fn scrape(&mut self, info_hashes: &[&[u8]]) -> Result<Vec<UDPScrape>, UDPTrackerError> {
+        let body = info_hashes.concat();
+
+        let response = self.transaction(SCRAPE, &body)?;
+
+        if response.len() < 12 * info_hashes.len() {
+            return Err(UDPTrackerError::InvalidResponse);
+        }
+
+        Ok(response
+            .chunks_exact(12)
+            .take(info_hashes.len())
+            .map(|stats| UDPScrape {
+                seeders: read_u32(stats, 0),
+                completed: read_u32(stats, 4),
+                leechers: read_u32(stats, 8),
+            })
+            .collect())
+    }
+
+    fn connection_id(&mut self) -> Result<u64, UDPTrackerError> {
+        if let Some((connection_id, obtained)) = self.connection {
+            if obtained.elapsed() < CONNECTION_ID_LIFETIME {
+                return Ok(connection_id);
+            }
+        }
+
+        let response = self.transaction(CONNECT, &[])?;
+
+        let connection_id = u64::from_be_bytes(
+            response
+                .get(0..8)
+                .ok_or(UDPTrackerError::InvalidResponse)?
+                .try_into()
+                .or(Err(UDPTrackerError::InvalidResponse))?,
+        );
+
+        self.connection = Some((connection_id, Instant::now()));
+
+        Ok(connection_id)
+    }
+
+    fn transaction(&mut self, action: u32, body: &[u8]) -> Result<Vec<u8>, UDPTrackerError> {
+        for attempt in 0..=self.max_retransmissions {
+            // Fetched on every attempt, as the connection id may expire while retransmitting.
+            let connection_id = match action {
+                CONNECT => PROTOCOL_ID,
+                _ => self.connection_id()?,
+            };
+            let transaction_id = random::<u32>();
+
+            let mut packet = Vec::with_capacity(16 + body.len());
+            packet.extend_from_slice(&connection_id.to_be_bytes());
+            packet.extend_from_slice(&action.to_be_bytes());
+            packet.extend_from_slice(&transaction_id.to_be_bytes());
+            packet.extend_from_slice(body);
+
+            let sent = match &self.relay {
+                Some(relay) => self
+                    .socket
+                    .send_to(&relay.encapsulate(self.tracker, &packet), relay.relay()),
+                None => self.socket.send_to(&packet, self.tracker),
+            };
+
+            sent.or(Err(UDPTrackerError::FailedToSend))?;
+
+            let timeout = self.base_timeout * 2_u32.pow(attempt);
+
+            if let Some(response) = self.receive(action, transaction_id, timeout)? {
+                return Ok(response);
+            }
+
+            log::debug!(
+                "UDPTracker::transaction() - no answer from {}, retransmitting",
+                self.tracker
+            );
+        }
+
+        Err(UDPTrackerError::Timeout)
+    }
+
+    fn receive(
+        &self,
+        action: u32,
+        transaction_id: u32,
+        timeout: Duration,
+    ) -> Result<Option<Vec<u8>>, UDPTrackerError> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0_u8; MAX_PACKET_SIZE];
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            self.socket
+                .set_read_timeout(Some(remaining))
+                .or(Err(UDPTrackerError::FailedToRead))?;
+
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, from)) => {
+                    let (from, datagram) = match &self.relay {
+                        Some(relay) if from == relay.relay() => {
+                            match relay.decapsulate(&buffer[..length]) {
+                                Some(unwrapped) => unwrapped,
+                                None => continue,
+                            }
+                        }
+                        Some(_) => continue,
+                        None => (from, &buffer[..length]),
+                    };
+
+                    // Late answers to earlier attempts carry another transaction id.
+                    if from != self.tracker
+                        || datagram.len() < 8
+                        || read_u32(datagram, 4) != transaction_id
+                    {
+                        continue;
+                    }
+
+                    let response = datagram[8..].to_vec();
+
+                    return match read_u32(datagram, 0) {
+                        ERROR => Err(UDPTrackerError::Tracker(
+                            String::from_utf8_lossy(&response).to_string(),
+                        )),
+                        response_action if response_action == action => Ok(Some(response)),
+                        _ => Err(UDPTrackerError::InvalidResponse),
+                    };
+                }
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    return Ok(None);
+                }
+                Err(_) => return Err(UDPTrackerError::FailedToRead),
+            }
+        }
+    }
+}
+
+fn event_id(event: &str) -> u32 {
+    match event {
+        "completed" => 1,
+        "started" => 2,
+        "stopped" => 3,
+        _ => 0,
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const CONNECTION_ID: u64 = 42;
+
+    // Ignores the first `dropped` packets and returns how many connect requests it answered
+    // once it has been idle for a second.
+    fn stand_in_tracker(dropped: usize) -> (String, thread::JoinHandle<usize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+
+        let handle = thread::spawn(move || {
+            let mut buffer = [0_u8; MAX_PACKET_SIZE];
+            let mut received = 0;
+            let mut connects = 0;
+
+            socket
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+
+            while let Ok((length, from)) = socket.recv_from(&mut buffer) {
+                received += 1;
+
+                if received <= dropped {
+                    continue;
+                }
+
+                let connection_id = u64::from_be_bytes(buffer[0..8].try_into().unwrap());
+                let action = read_u32(&buffer, 8);
+                let mut response = vec![];
+
+                match action {
+                    CONNECT => {
+                        connects += 1;
+                        response.extend_from_slice(&CONNECT.to_be_bytes());
+                        response.extend_from_slice(&buffer[12..16]);
+                        response.extend_from_slice(&CONNECTION_ID.to_be_bytes());
+                    }
+                    _ if connection_id != CONNECTION_ID || buffer[16] == 0 => {
+                        response.extend_from_slice(&ERROR.to_be_bytes());
+                        response.extend_from_slice(&buffer[12..16]);
+                        response.extend_from_slice(b"unknown torrent");
+                    }
+                    ANNOUNCE => {
+                        response.extend_from_slice(&ANNOUNCE.to_be_bytes());
+                        response.extend_from_slice(&buffer[12..16]);
+                        for value in [1800_u32, 1, 2] {
+                            response.extend_from_slice(&value.to_be_bytes());
+                        }
+                        response.extend_from_slice(&[10, 0, 0, 1, 0x1a, 0xe1]);
+                    }
+                    _ => {
+                        response.extend_from_slice(&SCRAPE.to_be_bytes());
+                        response.extend_from_slice(&buffer[12..16]);
+                        for _ in 0..(length - 16) / 20 {
+                            for value in [5_u32, 6, 7] {
+                                response.extend_from_slice(&value.to_be_bytes());
+                            }
+                        }
+                    }
+                }
+
+                socket.send_to(&response, from).unwrap();
+            }
+
+            connects
+        });
+
+        (address, handle)
+    }
+
+    fn announce(info_hash: &[u8]) -> UDPAnnounce<'_> {
+        UDPAnnounce {
+            info_hash,
+            peer_id: &[2; 20],
+            downloaded: 0,
+            left: 100,
+            uploaded: 0,
+            event: "started",
+            key: 1,
+            ip: Ipv4Addr::UNSPECIFIED,
+            port: 6881,
+        }
+    }
+
+    #[test]
+    fn test1_announce_and_scrape_reuse_connection_id() {
+        let (address, handle) = stand_in_tracker(0);
+        let mut tracker = UDPTracker::new(&address).unwrap();
+
+        let response = tracker.announce(&announce(&[1; 20])).unwrap();
+        let scrape = tracker.scrape(&[&[1; 20], &[3; 20]]).unwrap();
+
+        assert_eq!(response.interval, 1800);
+        assert_eq!(response.seeders, 2);
+        assert_eq!(response.peers, vec![10, 0, 0, 1, 0x1a, 0xe1]);
+        assert_eq!(tracker.compact_peer_length(), 6);
+        assert_eq!(scrape.len(), 2);
+        assert_eq!(
+            scrape[1],
+            UDPScrape {
+                seeders: 5,
+                completed: 6,
+                leechers: 7
+            }
+        );
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test2_unanswered_requests_are_retransmitted() {
+        let (address, handle) = stand_in_tracker(2);
+        let mut tracker = UDPTracker::new(&address).unwrap();
+        tracker.set_retransmission(Duration::from_millis(50), 3);
+
+        assert!(tracker.announce(&announce(&[1; 20])).is_ok());
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test3_tracker_errors_are_reported() {
+        let (address, handle) = stand_in_tracker(0);
+        let mut tracker = UDPTracker::new(&address).unwrap();
+
+        match tracker.announce(&announce(&[0; 20])) {
+            Err(UDPTrackerError::Tracker(message)) => assert_eq!(message, "unknown torrent"),
+            other => panic!("Unexpected announce result: {:?}", other),
+        }
+
+        handle.join().unwrap();
+    }
+}
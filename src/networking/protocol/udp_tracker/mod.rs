@@ -0,0 +1,5 @@
+pub use errors::UDPTrackerError;
+pub use index::{UDPAnnounce, UDPScrape, UDPTracker};
+
+mod errors;
+mod index;
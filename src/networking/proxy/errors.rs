@@ -0,0 +1,13 @@
+#[derive(Debug)]
+
+pub enum ProxyError {
+    InvalidProxy,
+    InvalidTarget,
+    FailedToConnect,
+    FailedToSend,
+    FailedToRead,
+    AuthenticationFailed,
+    // With the proxy's SOCKS reply code or HTTP status.
+    Refused(u16),
+    UdpUnsupported,
+}
@@ -0,0 +1,7 @@
+pub use errors::ProxyError;
+pub use index::{Proxy, Purpose};
+pub use socks5::UdpAssociation;
+
+mod errors;
+mod index;
+mod socks5;
@@ -0,0 +1,261 @@
+use super::ProxyError;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const AUTHENTICATION_VERSION: u8 = 1;
+pub const CONNECT: u8 = 1;
+pub const UDP_ASSOCIATE: u8 = 3;
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+
+// Authenticates if the proxy asks for it (RFC 1929).
+pub fn negotiate(
+    stream: &mut TcpStream,
+    credentials: Option<&(String, String)>,
+) -> Result<(), ProxyError> {
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[VERSION, 2, NO_AUTHENTICATION, USERNAME_PASSWORD],
+        None => &[VERSION, 1, NO_AUTHENTICATION],
+    };
+
+    stream
+        .write_all(greeting)
+        .or(Err(ProxyError::FailedToSend))?;
+
+    let mut choice = [0; 2];
+    stream
+        .read_exact(&mut choice)
+        .or(Err(ProxyError::FailedToRead))?;
+
+    match (choice, credentials) {
+        ([VERSION, NO_AUTHENTICATION], _) => Ok(()),
+        ([VERSION, USERNAME_PASSWORD], Some((user, password))) => {
+            if user.len() > 255 || password.len() > 255 {
+                return Err(ProxyError::InvalidProxy);
+            }
+
+            let mut request = vec![AUTHENTICATION_VERSION, user.len() as u8];
+            request.extend_from_slice(user.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+
+            stream
+                .write_all(&request)
+                .or(Err(ProxyError::FailedToSend))?;
+
+            let mut status = [0; 2];
+            stream
+                .read_exact(&mut status)
+                .or(Err(ProxyError::FailedToRead))?;
+
+            match status[1] {
+                0 => Ok(()),
+                _ => Err(ProxyError::AuthenticationFailed),
+            }
+        }
+        _ => Err(ProxyError::AuthenticationFailed),
+    }
+}
+
+// Returns the address the proxy bound for the command.
+pub fn request(
+    stream: &mut TcpStream,
+    command: u8,
+    host: &str,
+    port: u16,
+) -> Result<SocketAddr, ProxyError> {
+    let mut request = vec![VERSION, command, 0];
+    request.extend_from_slice(&encode_address(host, port)?);
+
+    stream
+        .write_all(&request)
+        .or(Err(ProxyError::FailedToSend))?;
+
+    let mut reply = [0; 4];
+    stream
+        .read_exact(&mut reply)
+        .or(Err(ProxyError::FailedToRead))?;
+
+    if reply[0] != VERSION {
+        return Err(ProxyError::FailedToRead);
+    }
+
+    if reply[1] != 0 {
+        return Err(ProxyError::Refused(reply[1] as u16));
+    }
+
+    let address_length = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN => {
+            let mut length = [0; 1];
+            stream
+                .read_exact(&mut length)
+                .or(Err(ProxyError::FailedToRead))?;
+            length[0] as usize
+        }
+        _ => return Err(ProxyError::FailedToRead),
+    };
+
+    let mut address = vec![0; address_length + 2];
+    stream
+        .read_exact(&mut address)
+        .or(Err(ProxyError::FailedToRead))?;
+
+    let port = u16::from_be_bytes([address[address_length], address[address_length + 1]]);
+
+    // Bound domain names are of no use to us, the proxy's own address stands in for them.
+    let ip = match address_length {
+        4 => IpAddr::from(<[u8; 4]>::try_from(&address[..4]).unwrap_or_default()),
+        16 => IpAddr::from(<[u8; 16]>::try_from(&address[..16]).unwrap_or_default()),
+        _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn encode_address(host: &str, port: u16) -> Result<Vec<u8>, ProxyError> {
+    let mut address = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => [&[IPV4], ip.octets().as_slice()].concat(),
+        Ok(IpAddr::V6(ip)) => [&[IPV6], ip.octets().as_slice()].concat(),
+        Err(_) if host.is_empty() || host.len() > 255 => return Err(ProxyError::InvalidTarget),
+        Err(_) => [&[DOMAIN, host.len() as u8], host.as_bytes()].concat(),
+    };
+
+    address.extend_from_slice(&port.to_be_bytes());
+
+    Ok(address)
+}
+
+fn decode_address(bytes: &[u8]) -> Option<(SocketAddr, usize)> {
+    let (ip, length) = match *bytes.first()? {
+        IPV4 => (
+            IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes.get(1..5)?).ok()?)),
+            4,
+        ),
+        IPV6 => (
+            IpAddr::V6(Ipv6Addr::from(
+                <[u8; 16]>::try_from(bytes.get(1..17)?).ok()?,
+            )),
+            16,
+        ),
+        _ => return None,
+    };
+
+    let port = bytes.get(1 + length..3 + length)?;
+
+    Some((
+        SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]])),
+        3 + length,
+    ))
+}
+
+// Lasts as long as its control connection.
+#[derive(Debug)]
+pub struct UdpAssociation {
+    _control: TcpStream,
+    relay: SocketAddr,
+}
+
+impl UdpAssociation {
+    pub fn new(control: TcpStream, relay: SocketAddr) -> Self {
+        Self {
+            _control: control,
+            relay,
+        }
+    }
+
+    pub fn relay(&self) -> SocketAddr {
+        self.relay
+    }
+
+    pub fn encapsulate(&self, target: SocketAddr, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0, 0, 0];
+        datagram.extend_from_slice(
+            &encode_address(&target.ip().to_string(), target.port())
+                .expect("IP addresses are always valid targets"),
+        );
+        datagram.extend_from_slice(payload);
+
+        datagram
+    }
+
+    // Fragments are dropped.
+    pub fn decapsulate<'a>(&self, datagram: &'a [u8]) -> Option<(SocketAddr, &'a [u8])> {
+        if datagram.get(..3)? != [0, 0, 0] {
+            return None;
+        }
+
+        let (from, length) = decode_address(&datagram[3..])?;
+
+        Some((from, &datagram[3 + length..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::proxy::Proxy;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test1_connects_with_credentials_and_relays_datagrams() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0; 4];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[VERSION, USERNAME_PASSWORD]).unwrap();
+
+            let mut authentication = [0; 13];
+            stream.read_exact(&mut authentication).unwrap();
+            stream.write_all(&[AUTHENTICATION_VERSION, 0]).unwrap();
+
+            let mut request = [0; 22];
+            stream.read_exact(&mut request).unwrap();
+            stream
+                .write_all(&[VERSION, 0, 0, IPV4, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+
+            let mut ping = [0; 4];
+            stream.read_exact(&mut ping).unwrap();
+            stream.write_all(b"pong").unwrap();
+
+            (greeting, authentication, request)
+        });
+
+        let proxy = Proxy::parse(&format!("socks5://user:secret@{}", address)).unwrap();
+        let mut stream = proxy
+            .connect("tracker.example:80", Duration::from_secs(5))
+            .unwrap();
+
+        stream.write_all(b"ping").unwrap();
+        let mut pong = [0; 4];
+        stream.read_exact(&mut pong).unwrap();
+
+        let (greeting, authentication, request) = handle.join().unwrap();
+
+        assert_eq!(&pong, b"pong");
+        assert_eq!(greeting, [VERSION, 2, NO_AUTHENTICATION, USERNAME_PASSWORD]);
+        assert_eq!(&authentication, b"\x01\x04user\x06secret");
+        assert_eq!(&request, b"\x05\x01\x00\x03\x0ftracker.example\x00\x50");
+
+        let association = UdpAssociation::new(stream, address);
+        let tracker = SocketAddr::from(([10, 0, 0, 1], 6969));
+        let datagram = association.encapsulate(tracker, b"announce");
+
+        assert_eq!(datagram[..10], [0, 0, 0, IPV4, 10, 0, 0, 1, 0x1b, 0x39]);
+        assert_eq!(
+            association.decapsulate(&datagram),
+            Some((tracker, b"announce".as_slice()))
+        );
+    }
+}
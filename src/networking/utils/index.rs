@@ -1,9 +1,53 @@
-use std::net::TcpListener;
+use crate::networking::{Proxy, Purpose};
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
-fn port_is_available(port: u16) -> bool {
-    TcpListener::bind(("127.0.0.1", port)).is_ok()
+fn port_is_available(interface: IpAddr, port: u16) -> bool {
+    TcpListener::bind((interface, port)).is_ok()
 }
 
-pub fn get_available_port() -> Option<u16> {
-    (8000..9000).find(|port| port_is_available(*port))
+pub fn get_available_port(interface: IpAddr) -> Option<u16> {
+    (8000..9000).find(|port| port_is_available(interface, *port))
+}
+
+// The timeout also bounds every read and write once connected.
+pub fn connect_with_timeout(target_address: &str, timeout: Duration) -> Result<TcpStream, Error> {
+    let mut last_error = Error::new(ErrorKind::NotFound, "No addresses to connect to");
+
+    for address in target_address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = error,
+        }
+    }
+
+    Err(last_error)
+}
+
+pub fn connect_for(
+    purpose: Purpose,
+    target_address: &str,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    let proxy = Proxy::from_env(purpose, target_address)
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, format!("Proxy: {:?}", error)))?;
+
+    match proxy {
+        Some(proxy) => {
+            let stream = proxy.connect(target_address, timeout).map_err(|error| {
+                Error::new(ErrorKind::ConnectionRefused, format!("Proxy: {:?}", error))
+            })?;
+
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+
+            Ok(stream)
+        }
+        None => connect_with_timeout(target_address, timeout),
+    }
 }
@@ -35,32 +35,81 @@ impl Torrent {
     }
 
     pub fn get_announce(&self) -> Option<Vec<u8>> {
-        self.torrent_dict.get(&b"announce".to_vec())?.get_string()
+        self.torrent_dict.get(b"announce".as_slice())?.get_string()
     }
 
-    pub fn get_files(&self) -> Option<&HashMap<Vec<u8>, Types>> {
-        let info = self.torrent_dict.get(&b"info".to_vec())?.get_dictionary()?;
-        info.get(&b"files".to_vec())?.get_dictionary()
+    // Path components and length of each file of a multi-file torrent, in order.
+    pub fn get_files(&self) -> Option<Vec<(Vec<String>, u64)>> {
+        let info = self
+            .torrent_dict
+            .get(b"info".as_slice())?
+            .get_dictionary()?;
+
+        info.get(b"files".as_slice())?
+            .get_list()?
+            .iter()
+            .map(|file| {
+                let file = file.get_dictionary()?;
+                let length = file.get(b"length".as_slice())?.get_integrer()?;
+                let path = file
+                    .get(b"path".as_slice())?
+                    .get_list()?
+                    .iter()
+                    .map(|component| {
+                        component
+                            .get_string()
+                            .map(|component| String::from_utf8_lossy(&component).to_string())
+                    })
+                    .collect::<Option<Vec<String>>>()?;
+
+                Some((path, length as u64))
+            })
+            .collect()
     }
 
     pub fn get_length(&self) -> Option<i64> {
-        let info = self.torrent_dict.get(&b"info".to_vec())?.get_dictionary()?;
-        info.get(&b"length".to_vec())?.get_integrer()
+        let info = self
+            .torrent_dict
+            .get(b"info".as_slice())?
+            .get_dictionary()?;
+        info.get(b"length".as_slice())?.get_integrer()
     }
 
     pub fn get_name(&self) -> Option<Vec<u8>> {
-        let info = self.torrent_dict.get(&b"info".to_vec())?.get_dictionary()?;
-        info.get(&b"name".to_vec())?.get_string()
+        let info = self
+            .torrent_dict
+            .get(b"info".as_slice())?
+            .get_dictionary()?;
+        info.get(b"name".as_slice())?.get_string()
     }
 
     pub fn get_piece_length(&self) -> Option<i64> {
-        let info = self.torrent_dict.get(&b"info".to_vec())?.get_dictionary()?;
-        info.get(&b"piece length".to_vec())?.get_integrer()
+        let info = self
+            .torrent_dict
+            .get(b"info".as_slice())?
+            .get_dictionary()?;
+        info.get(b"piece length".as_slice())?.get_integrer()
+    }
+
+    // Private torrents (BEP 27) only get peers from their trackers.
+    pub fn is_private(&self) -> bool {
+        let private = || -> Option<i64> {
+            self.torrent_dict
+                .get(b"info".as_slice())?
+                .get_dictionary()?
+                .get(b"private".as_slice())?
+                .get_integrer()
+        };
+
+        private() == Some(1)
     }
 
     pub fn get_pieces(&self) -> Option<Vec<Vec<u8>>> {
-        let info = self.torrent_dict.get(&b"info".to_vec())?.get_dictionary()?;
-        let hashes = info.get(&b"pieces".to_vec())?.get_string()?;
+        let info = self
+            .torrent_dict
+            .get(b"info".as_slice())?
+            .get_dictionary()?;
+        let hashes = info.get(b"pieces".as_slice())?.get_string()?;
         let num_pieces: usize = hashes.len() / 20;
         let mut split_hashes: Vec<Vec<u8>> = vec![vec![0; 0]; num_pieces];
         for i in 0..num_pieces {
@@ -1,5 +1,7 @@
 use rand::random;
+use std::env;
 use std::fmt::Write;
+use std::str::FromStr;
 
 pub fn u8_to_hexa(bytes: &[u8]) -> String {
     let mut hexa = String::new();
@@ -10,6 +12,25 @@ pub fn u8_to_hexa(bytes: &[u8]) -> String {
     hexa
 }
 
+/// Lowercase hex of `bytes`, two digits each.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+
+    for byte in bytes {
+        write!(&mut hex, "{:02x}", byte).expect("Unable to write byte to hex");
+    }
+
+    hex
+}
+
+/// Bytes of `hex`, `None` if it is not made of pairs of hex digits.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
 pub fn random_u64_as_bytes() -> [u8; 8] {
     let mut vec = [0; 8];
 
@@ -33,3 +54,40 @@ pub fn split_u8(source: Vec<u8>, split_seq: &[u8]) -> Vec<u8> {
 
     source[pos + split_seq.len()..].to_vec()
 }
+
+/// Setting `key` from the config, `None` when missing or not a valid `T`.
+pub fn optional_env_setting<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse::<T>().ok())
+}
+
+/// Setting `key` from the config, `default` when missing or not a valid `T`.
+pub fn env_setting<T: FromStr>(key: &str, default: T) -> T {
+    optional_env_setting(key).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test1_env_settings_fall_back_to_the_default() {
+        env::set_var("SITOS_TEST1_SETTING", " 42 ");
+        assert_eq!(env_setting("SITOS_TEST1_SETTING", 7_usize), 42);
+        assert!(!env_setting("SITOS_TEST1_SETTING", false));
+
+        env::set_var("SITOS_TEST1_SETTING", "true");
+        assert!(env_setting("SITOS_TEST1_SETTING", false));
+        assert_eq!(optional_env_setting::<u16>("SITOS_TEST1_SETTING"), None);
+        assert_eq!(env_setting("SITOS_TEST1_MISSING", 7_usize), 7);
+    }
+
+    #[test]
+    fn test2_hex_round_trips() {
+        assert_eq!(encode_hex(&[0, 15, 171]), "000fab");
+        assert_eq!(decode_hex("000fAB"), Some(vec![0, 15, 171]));
+        assert_eq!(decode_hex("000"), None);
+        assert_eq!(decode_hex("0g"), None);
+    }
+}